@@ -0,0 +1,37 @@
+use anyhow::{Context, Result, bail};
+
+use crate::exec;
+
+fn busctl(args: &[&str]) -> Result<String> {
+    let mut command_args = vec!["--system", "call", "org.freedesktop.Accounts"];
+    command_args.extend(args);
+    let output = exec::command("busctl", &command_args)
+        .output()
+        .context("Failed to execute busctl")?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        bail!("AccountsService call failed: {}", error.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The AccountsService object path for the current user, e.g.
+/// "/org/freedesktop/Accounts/User1000".
+fn user_path() -> Result<String> {
+    let output = exec::command("id", &["-u"]).output().context("Failed to execute id")?;
+    let uid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // busctl prints the reply as: o "/org/freedesktop/Accounts/User1000"
+    let reply = busctl(&["/org/freedesktop/Accounts", "org.freedesktop.Accounts", "FindUserById", "x", &uid])?;
+    match reply.split('"').nth(1) {
+        Some(path) => Ok(path.to_string()),
+        None => bail!("AccountsService doesn't know user {}", uid),
+    }
+}
+
+/// Records the user's language with AccountsService, which GDM and GNOME
+/// read instead of /etc/locale.conf.
+pub fn set_language(locale_code: &str) -> Result<()> {
+    let path = user_path()?;
+    busctl(&[&path, "org.freedesktop.Accounts.User", "SetLanguage", "s", locale_code])?;
+    Ok(())
+}
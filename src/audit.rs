@@ -0,0 +1,95 @@
+//! A trail of the system-wide changes, which on a shared machine affect
+//! everyone: who changed what and when, from which value to which. Each one
+//! is appended to a history file in the state directory and sent to
+//! journald as a structured entry, e.g. for
+//! `journalctl MESSAGE_ID=6c2f0b9e41d84a7f9e3a5d18c07b42e6`.
+
+use std::ffi::CStr;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::{config, exec, journal, listen, log};
+
+/// Identifies these entries among everything else in the journal.
+const MESSAGE_ID: &str = "6c2f0b9e41d84a7f9e3a5d18c07b42e6";
+/// LOG_NOTICE: normal, but worth keeping.
+const PRIORITY: &str = "5";
+
+pub fn history_path() -> PathBuf {
+    config::xdg_state_home().join("levocale").join("history")
+}
+
+/// Who asked for the change, by uid and name: the user behind sudo when
+/// there is one. The environment is anyone's to set, so sudo's variables
+/// only count when we really run as root, which is when sudo sets them.
+fn user() -> (u32, String) {
+    // SAFETY: getuid has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+    if uid == 0
+        && let Some(sudo_uid) = std::env::var("SUDO_UID").ok().and_then(|value| value.parse().ok())
+    {
+        let name = std::env::var("SUDO_USER").ok().filter(|name| !name.is_empty());
+        return (sudo_uid, name.or_else(|| user_name(sudo_uid)).unwrap_or_else(|| sudo_uid.to_string()));
+    }
+    (uid, user_name(uid).unwrap_or_else(|| uid.to_string()))
+}
+
+/// The login name of `uid` from the password database.
+fn user_name(uid: u32) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: an all-zero passwd is valid; getpwuid_r fills it in
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found: *mut libc::passwd = std::ptr::null_mut();
+    // SAFETY: every pointer is valid for the call and the buffer's length
+    // is passed along with it
+    let status = unsafe { libc::getpwuid_r(uid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found) };
+    if status != 0 || found.is_null() || entry.pw_name.is_null() {
+        return None;
+    }
+    // SAFETY: pw_name points into the buffer, nul-terminated
+    let name = unsafe { CStr::from_ptr(entry.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Records that `setting`, e.g. "LANG" or "console keymap", went from `old`
+/// to `new` through `backend`. Neither destination failing stops anything;
+/// the history file only says so in the log.
+pub fn record(setting: &str, old: Option<&str>, new: &str, backend: &str) {
+    let (uid, user) = user();
+    let uid = uid.to_string();
+    let old_text = old.filter(|old| !old.is_empty()).unwrap_or("(unset)");
+    let mut message = format!("{} changed {} from {} to {} via {}", user, setting, old_text, new, backend);
+    if let Some(host) = exec::remote_host() {
+        message.push_str(&format!(" on {}", host));
+    }
+    if let Err(e) = append_history(&format!("{} {}", listen::timestamp(), message)) {
+        log::record(log::Kind::Error, &format!("Couldn't write {}: {}", history_path().display(), e));
+    }
+    let mut fields = vec![
+        ("MESSAGE", message.as_str()),
+        ("MESSAGE_ID", MESSAGE_ID),
+        ("PRIORITY", PRIORITY),
+        ("SYSLOG_IDENTIFIER", "levocale"),
+        ("LEVOCALE_USER", user.as_str()),
+        ("LEVOCALE_UID", uid.as_str()),
+        ("LEVOCALE_SETTING", setting),
+        ("LEVOCALE_OLD", old.unwrap_or_default()),
+        ("LEVOCALE_NEW", new),
+        ("LEVOCALE_BACKEND", backend),
+    ];
+    if let Some(host) = exec::remote_host() {
+        fields.push(("LEVOCALE_HOST", host));
+    }
+    // Without systemd there's no journal, and the history file is enough
+    journal::send(&fields);
+}
+
+fn append_history(line: &str) -> std::io::Result<()> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
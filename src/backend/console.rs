@@ -0,0 +1,93 @@
+use std::fs;
+
+use anyhow::{Result, bail};
+
+use super::KeyboardBackend;
+use crate::{audit, exec, log, xkb};
+
+/// systemd's table of console keymaps and the XKB settings they match,
+/// the one localed uses to convert between the two.
+const KBD_MODEL_MAP: &str = "/usr/share/systemd/kbd-model-map";
+
+/// The Linux console's keymap, set through localectl so it lasts in
+/// /etc/vconsole.conf.
+pub struct Console;
+
+/// The console keymap for an XKB layout, looked up in the kbd-model-map,
+/// e.g. "de-latin1-nodeadkeys" for "de(nodeadkeys)". The console holds
+/// one keymap, so only the first of several layouts counts, and a layout
+/// the map doesn't know is tried under its own name.
+fn keymap_for(layout: &str) -> String {
+    let first = layout.split(',').next().unwrap_or(layout).trim();
+    let (layout, variant) = xkb::split_layout_variant(first);
+    let variant = variant.filter(|variant| !variant.is_empty());
+    mapped_keymap(layout, variant).unwrap_or_else(|| layout.to_string())
+}
+
+/// The first keymap the map lists for `layout` with `variant`, or else for
+/// `layout` with no variant.
+fn mapped_keymap(layout: &str, variant: Option<&str>) -> Option<String> {
+    let map = fs::read_to_string(KBD_MODEL_MAP).ok()?;
+    let mut plain = None;
+    for line in map.lines().filter(|line| !line.trim_start().starts_with('#')) {
+        // keymap, X11 layouts, model, variants, options
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [keymap, layouts, _, variants, ..] = fields[..] else {
+            continue;
+        };
+        if layouts.split(',').next() != Some(layout) {
+            continue;
+        }
+        let mapped_variant = variants.split(',').next().filter(|variant| !variant.is_empty() && *variant != "-");
+        if mapped_variant == variant {
+            return Some(keymap.to_string());
+        }
+        if mapped_variant.is_none() && plain.is_none() {
+            plain = Some(keymap.to_string());
+        }
+    }
+    plain
+}
+
+impl KeyboardBackend for Console {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let output = exec::command("localectl", &["status"]).output().ok()?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("VC Keymap:").map(|keymap| keymap.trim().to_string()))
+            .filter(|keymap| !keymap.is_empty() && keymap != "(unset)" && keymap != "n/a")
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        let keymap = keymap_for(layout);
+        let old = self.current_layout();
+        // Left to itself localectl would rewrite the X11 keymap to match
+        match log::run(&mut exec::privileged(&["localectl", "set-keymap", "--no-convert", &keymap])?) {
+            Ok(output) if output.status.success() => {
+                audit::record("console keymap", old.as_deref(), &keymap, self.name());
+                Ok(())
+            }
+            Ok(output) => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                bail!("Failed to set the console keymap to {}: {}", keymap, error.trim())
+            }
+            Err(e) => bail!("Failed to execute localectl: {}", e),
+        }
+    }
+
+    fn options(&self) -> Option<String> {
+        None
+    }
+
+    fn set_options(&self, _options: &str) -> Result<()> {
+        bail!("The console doesn't support XKB options")
+    }
+
+    fn shell_commands(&self, layout: &str, _options: &str) -> Vec<String> {
+        vec![format!("sudo localectl set-keymap --no-convert {}", exec::shell_quote(&keymap_for(layout)))]
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use super::KeyboardBackend;
+use crate::{config, fsutil, xkb};
+
+/// cosmic-comp watches its cosmic-config entries and applies changes to
+/// `xkb_config` immediately, so editing the file is all it takes.
+pub struct Cosmic;
+
+fn xkb_config_path() -> PathBuf {
+    config::xdg_config_home().join("cosmic/com.system76.CosmicComp/v1/xkb_config")
+}
+
+const DEFAULT_XKB_CONFIG: &str = "(
+    rules: \"\",
+    model: \"pc104\",
+    layout: \"us\",
+    variant: \"\",
+    options: None,
+    repeat_delay: 600,
+    repeat_rate: 25,
+)
+";
+
+impl Cosmic {
+    fn read(&self) -> String {
+        fs::read_to_string(xkb_config_path()).unwrap_or_else(|_| DEFAULT_XKB_CONFIG.to_string())
+    }
+
+    /// The string value of a field in the RON struct, unwrapping `Some(..)`.
+    fn get(&self, field: &str) -> Option<String> {
+        self.read().lines().find_map(|line| {
+            let (name, value) = line.trim().split_once(':')?;
+            if name.trim() != field {
+                return None;
+            }
+            let value = value.trim().trim_end_matches(',');
+            let value = value.strip_prefix("Some(").and_then(|v| v.strip_suffix(')')).unwrap_or(value);
+            Some(value.trim_matches('"').to_string()).filter(|_| value != "None")
+        })
+    }
+
+    fn set(&self, values: &[(&str, String)]) -> Result<()> {
+        let contents = self.read();
+        let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+        for (field, value) in values {
+            let position = lines
+                .iter()
+                .position(|line| line.trim().split_once(':').is_some_and(|(name, _)| name.trim() == *field));
+            let assignment = format!("    {}: {},", field, value);
+            match position {
+                Some(index) => lines[index] = assignment,
+                // Keep the closing parenthesis last
+                None => {
+                    let end = lines.iter().rposition(|line| line.trim() == ")").unwrap_or(lines.len());
+                    lines.insert(end, assignment);
+                }
+            }
+        }
+        let mut updated = lines.join("\n");
+        updated.push('\n');
+        if updated == contents {
+            return Ok(());
+        }
+        fsutil::write_atomic(&xkb_config_path(), &updated)
+    }
+}
+
+fn ron_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl KeyboardBackend for Cosmic {
+    fn name(&self) -> &'static str {
+        "cosmic"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let layout = self.get("layout").filter(|layout| !layout.is_empty())?;
+        match self.get("variant") {
+            Some(variant) if !variant.is_empty() && !layout.contains(',') => {
+                Some(format!("{}({})", layout, variant))
+            }
+            _ => Some(layout),
+        }
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        self.set(&[("layout", ron_string(layout)), ("variant", ron_string(variant.unwrap_or("")))])
+    }
+
+    fn options(&self) -> Option<String> {
+        Some(self.get("options").unwrap_or_default())
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        let value = if options.is_empty() {
+            "None".to_string()
+        } else {
+            format!("Some({})", ron_string(options))
+        };
+        self.set(&[("options", value)])
+    }
+
+    fn persist(&self) -> Result<()> {
+        // cosmic-config entries are already persistent
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use super::LocaleBackend;
+use crate::{audit, exec, fsutil, locale_info, log};
+
+/// Debian and Ubuntu: locale.gen decides what gets compiled and
+/// /etc/default/locale holds LANG. debconf seeds itself from locale.gen, so
+/// editing it keeps `dpkg-reconfigure locales` in agreement.
+pub struct Debian;
+
+const LOCALE_GEN: &str = "/etc/locale.gen";
+const DEFAULT_LOCALE: &str = "/etc/default/locale";
+
+/// A locale.gen line, e.g. "# de_DE.UTF-8 UTF-8": (enabled, name, charset).
+fn parse_locale_gen_line(line: &str) -> Option<(bool, &str, &str)> {
+    let trimmed = line.trim();
+    let (enabled, entry) = match trimmed.strip_prefix('#') {
+        Some(rest) => (false, rest.trim()),
+        None => (true, trimmed),
+    };
+    let mut fields = entry.split_whitespace();
+    let (name, charset) = (fields.next()?, fields.next()?);
+    // Skip the prose comments at the top of the file
+    if fields.next().is_some() || !name.contains('_') && !name.starts_with("C.") {
+        return None;
+    }
+    Some((enabled, name, charset))
+}
+
+fn sudo(args: &[&str]) -> Result<()> {
+    match log::run(&mut exec::privileged(args)?) {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => bail!("{} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim()),
+        Err(e) => bail!("Failed to execute sudo: {}", e),
+    }
+}
+
+/// The variables assigned in /etc/default/locale.
+fn default_locale() -> Vec<(String, String)> {
+    let contents = exec::read_to_string(Path::new(DEFAULT_LOCALE)).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.trim().split_once('=')?;
+            Some((name.to_string(), value.trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+impl Debian {
+    /// Uncomments (or adds) the locale in locale.gen and regenerates.
+    fn enable(&self, locale: &str) -> Result<()> {
+        let contents = exec::read_to_string(Path::new(LOCALE_GEN)).unwrap_or_default();
+        let wanted = locale_info::normalized_locale_name(locale);
+        let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+        let existing = lines.iter().enumerate().find_map(|(index, line)| {
+            let (enabled, name, charset) = parse_locale_gen_line(line)?;
+            (locale_info::normalized_locale_name(name) == wanted).then(|| (index, enabled, format!("{} {}", name, charset)))
+        });
+        match existing {
+            Some((_, true, _)) => return Ok(()),
+            Some((index, false, entry)) => lines[index] = entry,
+            None => {
+                let charset = locale_info::split_locale_code(locale).codeset.unwrap_or("ISO-8859-1");
+                lines.push(format!("{} {}", locale, charset));
+            }
+        }
+        let mut updated = lines.join("\n");
+        updated.push('\n');
+        fsutil::write_as_root(Path::new(LOCALE_GEN), &updated)?;
+        sudo(&["locale-gen"])
+    }
+}
+
+impl LocaleBackend for Debian {
+    fn name(&self) -> &'static str {
+        "debian"
+    }
+
+    /// Generated locales plus everything locale.gen offers to generate.
+    fn available_locales(&self) -> Vec<String> {
+        let mut locales = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        if let Ok(contents) = exec::read_to_string(Path::new(LOCALE_GEN)) {
+            for (_, name, _) in contents.lines().filter_map(parse_locale_gen_line) {
+                if seen.insert(locale_info::normalized_locale_name(name)) {
+                    locales.insert(name.to_string());
+                }
+            }
+        }
+        for name in locale_info::installed_locales() {
+            if seen.insert(locale_info::normalized_locale_name(&name)) {
+                locales.insert(name);
+            }
+        }
+        locales.into_iter().collect()
+    }
+
+    fn current_locale(&self) -> Option<String> {
+        default_locale().into_iter().find_map(|(name, value)| (name == "LANG").then_some(value))
+    }
+
+    fn set_locale(&self, locale: &str) -> Result<()> {
+        if !matches!(locale, "C" | "POSIX") && !locale.starts_with("C.") {
+            self.enable(locale)?;
+        }
+        let old = self.current_locale();
+        // update-locale validates the locale and writes /etc/default/locale
+        sudo(&["update-locale", &format!("LANG={}", locale)])?;
+        audit::record("LANG", old.as_deref(), locale, self.name());
+        Ok(())
+    }
+
+    fn charmap(&self, locale: &str) -> Option<String> {
+        let contents = exec::read_to_string(Path::new(LOCALE_GEN)).ok()?;
+        let wanted = locale_info::normalized_locale_name(locale);
+        contents
+            .lines()
+            .filter_map(parse_locale_gen_line)
+            .find(|(_, name, _)| locale_info::normalized_locale_name(name) == wanted)
+            .map(|(_, _, charset)| charset.to_string())
+    }
+
+    fn categories(&self) -> Vec<(String, String)> {
+        default_locale().into_iter().filter(|(name, _)| name.starts_with("LC_")).collect()
+    }
+
+    fn set_category(&self, category: &str, locale: &str) -> Result<()> {
+        self.set_variables(&[(category.to_string(), locale.to_string())])
+    }
+
+    fn set_variables(&self, variables: &[(String, String)]) -> Result<()> {
+        for (_, locale) in variables {
+            if !matches!(locale.as_str(), "C" | "POSIX") && !locale.starts_with("C.") {
+                self.enable(locale)?;
+            }
+        }
+        let old = default_locale();
+        let mut args = vec!["update-locale".to_string()];
+        args.extend(variables.iter().map(|(name, value)| format!("{}={}", name, value)));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        sudo(&args)?;
+        for (name, value) in variables {
+            let before = old.iter().find_map(|(old_name, old)| (old_name == name).then_some(old.as_str()));
+            audit::record(name, before, value, self.name());
+        }
+        Ok(())
+    }
+
+    fn unset_variables(&self, names: &[String]) -> Result<()> {
+        let old = default_locale();
+        // update-locale drops the variables named without a value
+        let mut args = vec!["update-locale"];
+        args.extend(names.iter().map(String::as_str));
+        sudo(&args)?;
+        for (name, value) in old.iter().filter(|(name, _)| names.contains(name)) {
+            audit::record(name, Some(value), "(unset)", self.name());
+        }
+        Ok(())
+    }
+
+    fn shell_commands(&self, lang: &str, categories: &[(String, String)]) -> Vec<String> {
+        let mut locales: Vec<&str> = std::iter::once(lang).chain(categories.iter().map(|(_, value)| value.as_str())).collect();
+        locales.retain(|locale| !matches!(*locale, "C" | "POSIX") && !locale.starts_with("C."));
+        locales.sort();
+        locales.dedup();
+        // Uncomment each one in locale.gen, as enable() does
+        let mut commands: Vec<String> = locales
+            .iter()
+            .map(|locale| {
+                let pattern = locale.replace('.', "\\.");
+                format!("sudo sed -i {} {}", exec::shell_quote(&format!("s/^# *\\({} \\)/\\1/", pattern)), LOCALE_GEN)
+            })
+            .collect();
+        if !locales.is_empty() {
+            commands.push("sudo locale-gen".to_string());
+        }
+        commands.push(format!("sudo update-locale {}", super::assignments(lang, categories).join(" ")));
+        commands
+    }
+}
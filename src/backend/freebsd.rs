@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+
+use super::{KeyboardBackend, LocaleBackend};
+use crate::{audit, config, exec, fsutil};
+
+const LOGIN_CONF: &str = "/etc/login.conf";
+const VT_KEYMAPS: &str = "/usr/share/vt/keymaps";
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    output(program, exec::command(program, args))
+}
+
+/// Like [`run`], as root.
+fn run_privileged(words: &[&str]) -> Result<String> {
+    output(words[0], exec::privileged(words)?)
+}
+
+fn output(program: &str, mut command: Command) -> Result<String> {
+    match command.output() {
+        Ok(output) if output.status.success() => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Ok(output) => bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()),
+        Err(e) => bail!("Failed to execute {}: {}", program, e),
+    }
+}
+
+/// FreeBSD sets LANG and MM_CHARSET from login classes: the user's own
+/// `me` class in ~/.login_conf, and optionally a class in /etc/login.conf.
+pub struct LoginConf {
+    pub system_class: Option<String>,
+}
+
+fn user_login_conf() -> PathBuf {
+    config::home_dir().join(".login_conf")
+}
+
+/// Line range of a class entry, which runs until a line without a
+/// trailing backslash.
+fn find_entry(lines: &[String], class: &str) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| {
+        !line.starts_with(['#', ' ', '\t'])
+            && line.split(':').next().is_some_and(|names| names.split('|').any(|name| name == class))
+    })?;
+    let end = lines[start..]
+        .iter()
+        .position(|line| !line.trim_end().ends_with('\\'))
+        .map_or(lines.len() - 1, |offset| start + offset);
+    Some((start, end))
+}
+
+fn capability(lines: &[String], class: &str, name: &str) -> Option<String> {
+    let (start, end) = find_entry(lines, class)?;
+    let prefix = format!(":{}=", name);
+    lines[start..=end].iter().find_map(|line| {
+        let rest = &line[line.find(&prefix)? + prefix.len()..];
+        Some(rest.split(':').next().unwrap_or_default().to_string())
+    })
+}
+
+/// Sets `lang` and `charset` in a class entry, creating the entry if needed.
+fn set_language(contents: &str, class: &str, locale: &str) -> String {
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let charset = locale.split_once('.').map_or("US-ASCII", |(_, codeset)| codeset.split('@').next().unwrap_or(codeset));
+    let values = [("lang", locale), ("charset", charset)];
+    match find_entry(&lines, class) {
+        Some((start, mut end)) => {
+            for (name, value) in values {
+                let prefix = format!(":{}=", name);
+                match (start..=end).find(|&index| lines[index].contains(&prefix)) {
+                    Some(index) => {
+                        let line = &lines[index];
+                        let value_start = line.find(&prefix).unwrap_or_default() + prefix.len();
+                        let value_end = line[value_start..].find(':').map_or(line.len(), |offset| value_start + offset);
+                        lines[index] = format!("{}{}{}", &line[..value_start], value, &line[value_end..]);
+                    }
+                    None => {
+                        if !lines[start].trim_end().ends_with('\\') {
+                            lines[start].push('\\');
+                        }
+                        let continues = if start == end { "" } else { "\\" };
+                        lines.insert(start + 1, format!("\t:{}={}:{}", name, value, continues));
+                        end += 1;
+                    }
+                }
+            }
+        }
+        None => {
+            lines.push(format!("{}:\\", class));
+            lines.push(format!("\t:charset={}:\\", charset));
+            lines.push(format!("\t:lang={}:", locale));
+        }
+    }
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    updated
+}
+
+impl LocaleBackend for LoginConf {
+    fn name(&self) -> &'static str {
+        "login.conf"
+    }
+
+    fn available_locales(&self) -> Vec<String> {
+        run("locale", &["-a"])
+            .map(|output| output.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn current_locale(&self) -> Option<String> {
+        // A remote host's ~/.login_conf belongs to whoever we ssh in as, so
+        // only the system class is meaningful there
+        if exec::remote_host().is_some() {
+            let contents = exec::read_to_string(Path::new(LOGIN_CONF)).ok()?;
+            let lines: Vec<String> = contents.lines().map(str::to_string).collect();
+            return capability(&lines, self.system_class.as_deref()?, "lang");
+        }
+        let contents = fs::read_to_string(user_login_conf()).ok()?;
+        let lines: Vec<String> = contents.lines().map(str::to_string).collect();
+        capability(&lines, "me", "lang")
+    }
+
+    fn set_locale(&self, locale: &str) -> Result<()> {
+        if exec::remote_host().is_none() {
+            let path = user_login_conf();
+            let contents = fs::read_to_string(&path).unwrap_or_default();
+            fsutil::write_atomic(&path, &set_language(&contents, "me", locale))?;
+        } else if self.system_class.is_none() {
+            bail!("Set login_class in the config to change the locale of a remote FreeBSD host");
+        }
+        // ~/.login_conf is read directly; only the system file has a database
+        if let Some(class) = &self.system_class {
+            let contents = exec::read_to_string(Path::new(LOGIN_CONF))?;
+            let lines: Vec<String> = contents.lines().map(str::to_string).collect();
+            let old = capability(&lines, class, "lang");
+            fsutil::write_as_root(Path::new(LOGIN_CONF), &set_language(&contents, class, locale))?;
+            run_privileged(&["cap_mkdb", LOGIN_CONF])?;
+            audit::record(&format!("lang of login class {}", class), old.as_deref(), locale, self.name());
+        }
+        Ok(())
+    }
+}
+
+/// The FreeBSD vt(4) console, which uses its own keymaps rather than XKB.
+pub struct Vt;
+
+/// kbdcontrol can load a keymap but not report one, so remember ours.
+static LOADED_KEYMAP: Mutex<Option<String>> = Mutex::new(None);
+
+impl KeyboardBackend for Vt {
+    fn name(&self) -> &'static str {
+        "vt"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        if let Some(keymap) = LOADED_KEYMAP.lock().ok()?.clone() {
+            return Some(keymap);
+        }
+        // Otherwise it's whatever rc.conf had the console load at boot
+        let keymap = run("sysrc", &["-n", "keymap"]).ok()?;
+        Some(keymap.trim_end_matches(".kbd").to_string()).filter(|keymap| !keymap.is_empty())
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        // vt keymaps are mostly named after the XKB layout, e.g. de.kbd
+        let keymap = Path::new(VT_KEYMAPS).join(format!("{}.kbd", layout));
+        if !exec::exists(&keymap) {
+            bail!("No console keymap {} (see kbdmap for the available ones)", keymap.display());
+        }
+        run("kbdcontrol", &["-l", &keymap.to_string_lossy()])?;
+        if let Ok(mut loaded) = LOADED_KEYMAP.lock() {
+            *loaded = Some(layout.to_string());
+        }
+        Ok(())
+    }
+
+    fn options(&self) -> Option<String> {
+        None
+    }
+
+    fn shell_commands(&self, layout: &str, _options: &str) -> Vec<String> {
+        let keymap = Path::new(VT_KEYMAPS).join(format!("{}.kbd", layout));
+        vec![format!("kbdcontrol -l {}", exec::shell_quote(&keymap.to_string_lossy()))]
+    }
+
+    fn set_options(&self, _options: &str) -> Result<()> {
+        bail!("The FreeBSD console doesn't support XKB options")
+    }
+
+    fn persist(&self) -> Result<()> {
+        let Some(layout) = self.current_layout() else {
+            bail!("No console keymap to save");
+        };
+        let old = run("sysrc", &["-n", "keymap"]).ok();
+        run_privileged(&["sysrc", &format!("keymap={}", layout)])?;
+        audit::record("rc.conf keymap", old.as_deref(), &layout, self.name());
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+//! Just enough of Hyprland's config language to find and edit the input
+//! settings wherever they live, following `source =` includes.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::config;
+
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+}
+
+impl ConfigFile {
+    pub fn render(&self) -> String {
+        let mut out = self.lines.join("\n");
+        out.push('\n');
+        out
+    }
+}
+
+/// Where the effective `kb_layout` is assigned.
+pub enum Owner {
+    /// Inside an `input { ... }` block spanning these two lines.
+    Block { open: usize, close: usize },
+    /// A top-level `input:kb_layout = ...` line.
+    Inline(usize),
+}
+
+pub struct Location {
+    /// Index into the list returned alongside it by [`load`].
+    pub file: usize,
+    pub owner: Owner,
+}
+
+/// Reads `main` and every file it sources, in the order Hyprland would, and
+/// finds the last `kb_layout` assignment, which is the one that wins.
+pub fn load(main: &Path) -> Result<(Vec<ConfigFile>, Option<Location>)> {
+    let mut files = Vec::new();
+    let mut found = None;
+    let mut visited = HashSet::new();
+    walk(main, &mut files, &mut found, &mut visited)?;
+    Ok((files, found))
+}
+
+fn walk(
+    path: &Path,
+    files: &mut Vec<ConfigFile>,
+    found: &mut Option<Location>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    // Guard against files that source each other
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && files.is_empty() => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let index = files.len();
+    files.push(ConfigFile {
+        path: path.to_path_buf(),
+        lines: contents.lines().map(str::to_string).collect(),
+    });
+    let base_dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+    // (section name, line it opened on, whether it assigns kb_layout)
+    let mut stack: Vec<(String, usize, bool)> = Vec::new();
+    let lines = files[index].lines.clone();
+    for (number, raw) in lines.iter().enumerate() {
+        let line = strip_comment(raw).trim();
+        if let Some(name) = line.strip_suffix('{') {
+            stack.push((name.trim().to_string(), number, false));
+            continue;
+        }
+        if line == "}" {
+            if let Some((name, open, has_layout)) = stack.pop()
+                && stack.is_empty()
+                && name == "input"
+                && has_layout
+            {
+                *found = Some(Location { file: index, owner: Owner::Block { open, close: number } });
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match stack.as_mut_slice() {
+            [] if key == "source" => {
+                for include in expand_source(value, &base_dir) {
+                    walk(&include, files, found, visited)?;
+                }
+            }
+            [] if key == "input:kb_layout" => {
+                *found = Some(Location { file: index, owner: Owner::Inline(number) });
+            }
+            [(name, _, has_layout)] if name == "input" && key == "kb_layout" => *has_layout = true,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a `source =` value to files, expanding `~` and a `*` in the
+/// file name the way Hyprland's glob does for the common cases.
+fn expand_source(value: &str, base_dir: &Path) -> Vec<PathBuf> {
+    let path = config::expand_home(Path::new(value));
+    let path = if path.is_absolute() { path } else { base_dir.join(path) };
+    let Some(file_name) = path.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+        return Vec::new();
+    };
+    let Some((prefix, suffix)) = file_name.split_once('*') else {
+        return vec![path];
+    };
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|candidate| {
+            candidate.file_name().map(|name| name.to_string_lossy()).is_some_and(|name| {
+                name.len() >= prefix.len() + suffix.len() && name.starts_with(prefix) && name.ends_with(suffix)
+            })
+        })
+        .collect();
+    matches.sort();
+    matches
+}
+
+/// Everything before a `#` comment. Hyprland writes a literal `#` as `##`.
+fn strip_comment(line: &str) -> &str {
+    comment_start(line).map_or(line, |index| &line[..index])
+}
+
+fn comment_start(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'#' {
+            if bytes.get(index + 1) == Some(&b'#') {
+                index += 2;
+                continue;
+            }
+            return Some(index);
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Replaces the value of a `key = value` line, keeping its indentation and
+/// any trailing comment.
+fn set_value(line: &str, value: &str) -> String {
+    let Some((lhs, rest)) = line.split_once('=') else {
+        return line.to_string();
+    };
+    let assignment = format!("{}= {}", lhs, value);
+    match comment_start(rest) {
+        Some(index) => format!("{} {}", assignment.trim_end(), &rest[index..]),
+        None => assignment.trim_end().to_string(),
+    }
+}
+
+fn assignment_key(line: &str) -> Option<&str> {
+    strip_comment(line).split_once('=').map(|(key, _)| key.trim())
+}
+
+/// Writes `settings` (input keys without the "input:" prefix) into the
+/// assignment site found by [`load`], editing existing lines and adding
+/// missing non-empty ones next to them.
+pub fn apply(file: &mut ConfigFile, owner: &Owner, settings: &[(&str, String)]) {
+    match *owner {
+        Owner::Block { open, close } => {
+            let mut close = close;
+            let indent = file.lines[open + 1..close]
+                .iter()
+                .find(|line| assignment_key(line) == Some("kb_layout"))
+                .map(|line| line[..line.len() - line.trim_start().len()].to_string())
+                .unwrap_or_else(|| "    ".to_string());
+            for (key, value) in settings {
+                // Only direct children of the input block, not e.g. touchpad { }
+                let mut depth = 0;
+                let mut existing = None;
+                for number in open + 1..close {
+                    let line = strip_comment(&file.lines[number]).trim();
+                    if line.ends_with('{') {
+                        depth += 1;
+                    } else if line == "}" {
+                        depth -= 1;
+                    } else if depth == 0 && assignment_key(line) == Some(key) {
+                        existing = Some(number);
+                    }
+                }
+                match existing {
+                    Some(number) => file.lines[number] = set_value(&file.lines[number], value),
+                    None if !value.is_empty() => {
+                        file.lines.insert(close, format!("{}{} = {}", indent, key, value));
+                        close += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+        Owner::Inline(layout_line) => {
+            let mut insert_at = layout_line + 1;
+            for (key, value) in settings {
+                let full_key = format!("input:{}", key);
+                match file.lines.iter().rposition(|line| assignment_key(line) == Some(full_key.as_str())) {
+                    Some(number) => file.lines[number] = set_value(&file.lines[number], value),
+                    None if !value.is_empty() => {
+                        file.lines.insert(insert_at, format!("{} = {}", full_key, value));
+                        insert_at += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+
+use super::{KeyboardBackend, hyprconf};
+use crate::{config, exec, fsutil, log, managed_block, xkb};
+
+pub struct Hyprland;
+
+fn config_path() -> PathBuf {
+    config::xdg_config_home().join("hypr").join("hyprland.conf")
+}
+
+impl Hyprland {
+    fn get_option(&self, option: &str) -> Option<String> {
+        let output = exec::local("hyprctl").args(["getoption", option]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // Output looks like "str: us\nset: true"
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find_map(|line| line.strip_prefix("str:"))
+            .map(|value| match value.trim() {
+                // Hyprland's placeholder for an empty string option
+                "[[EMPTY]]" => String::new(),
+                value => value.to_string(),
+            })
+    }
+
+    fn keyword(&self, option: &str, value: &str) -> Result<()> {
+        self.keywords(&[(option.to_string(), value)])
+    }
+
+    /// Sets several keywords in one `hyprctl --batch` request. Hyprland
+    /// still applies them one after another, recompiling the keymap each
+    /// time, and keeps the earlier ones when a later one is rejected.
+    fn keywords(&self, settings: &[(String, &str)]) -> Result<()> {
+        let names = settings.iter().map(|(option, _)| option.as_str()).collect::<Vec<_>>().join(", ");
+        // ";" separates the commands of a batch
+        if let Some((option, value)) = settings.iter().find(|(_, value)| value.contains(';')) {
+            bail!("Failed to set {}: {:?} contains a \";\"", option, value);
+        }
+        let batch = settings
+            .iter()
+            .map(|(option, value)| format!("keyword {} {}", option, value))
+            .collect::<Vec<_>>()
+            .join(" ; ");
+        let result = log::run(exec::local("hyprctl").args(["--batch", &batch]));
+        match result {
+            // hyprctl exits 0 even when it rejects a keyword, so check what it said
+            Ok(output) if output.status.success() && !String::from_utf8_lossy(&output.stdout).contains("error") => Ok(()),
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let error = if stderr.trim().is_empty() { stdout } else { stderr };
+                bail!("Failed to set {}: {}", names, error.trim())
+            }
+            Err(e) => bail!("Failed to execute hyprctl: {}", e),
+        }
+    }
+
+    /// The keywords that switch `scope` ("input" or "device[name]") to
+    /// `layout` and `options`. Each keyword recompiles the keymap, so the
+    /// old variant is cleared before switching layouts in case the new
+    /// layout doesn't define it.
+    fn keyboard_keywords<'a>(scope: &str, layout: Option<&'a str>, options: Option<&'a str>) -> Vec<(String, &'a str)> {
+        let mut settings = Vec::new();
+        if let Some(layout) = layout {
+            let (layout, variant) = xkb::split_layout_variant(layout);
+            settings.push((format!("{}:kb_variant", scope), ""));
+            settings.push((format!("{}:kb_layout", scope), layout));
+            settings.extend(variant.map(|variant| (format!("{}:kb_variant", scope), variant)));
+        }
+        settings.extend(options.map(|options| (format!("{}:kb_options", scope), options)));
+        settings
+    }
+}
+
+impl KeyboardBackend for Hyprland {
+    fn name(&self) -> &'static str {
+        "hyprland"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let layout = self.get_option("input:kb_layout").filter(|layout| !layout.is_empty())?;
+        match self.get_option("input:kb_variant") {
+            Some(variant) if !variant.is_empty() && !layout.contains(',') => {
+                Some(format!("{}({})", layout, variant))
+            }
+            _ => Some(layout),
+        }
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        self.keywords(&Hyprland::keyboard_keywords("input", Some(layout), None))
+    }
+
+    fn options(&self) -> Option<String> {
+        self.get_option("input:kb_options")
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        self.keyword("input:kb_options", options)
+    }
+
+    fn set_keyboard(&self, layout: Option<&str>, options: Option<&str>) -> Result<()> {
+        self.keywords(&Hyprland::keyboard_keywords("input", layout, options))
+    }
+
+    fn shell_commands(&self, layout: &str, options: &str) -> Vec<String> {
+        let batch = Hyprland::keyboard_keywords("input", Some(layout), Some(options))
+            .into_iter()
+            .map(|(option, value)| format!("keyword {} {}", option, value))
+            .collect::<Vec<_>>()
+            .join(" ; ");
+        vec![format!("hyprctl --batch {}", exec::shell_quote(&batch))]
+    }
+
+    fn keyboards(&self) -> Vec<String> {
+        let Ok(output) = exec::local("hyprctl").args(["devices", "-j"]).output() else {
+            return Vec::new();
+        };
+        let devices: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap_or_default();
+        devices["keyboards"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|keyboard| keyboard["name"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn set_device_layout(&self, device: &str, layout: &str) -> Result<()> {
+        self.set_device_keyboard(device, Some(layout), None)
+    }
+
+    fn set_device_options(&self, device: &str, options: &str) -> Result<()> {
+        self.set_device_keyboard(device, None, Some(options))
+    }
+
+    fn set_device_keyboard(&self, device: &str, layout: Option<&str>, options: Option<&str>) -> Result<()> {
+        self.keywords(&Hyprland::keyboard_keywords(&format!("device[{}]", device), layout, options))
+    }
+
+    fn keymap_file(&self) -> Option<String> {
+        self.get_option("input:kb_file").filter(|file| !file.is_empty())
+    }
+
+    fn set_keymap_file(&self, path: Option<&str>) -> Result<()> {
+        self.keyword("input:kb_file", path.unwrap_or(""))
+    }
+
+    fn persist(&self) -> Result<()> {
+        let settings: Vec<(&str, String)> = ["kb_layout", "kb_variant", "kb_options", "kb_file"]
+            .into_iter()
+            .map(|key| (key, self.get_option(&format!("input:{}", key)).unwrap_or_default()))
+            .collect();
+        let (mut files, location) = hyprconf::load(&config_path())?;
+        // Edit the assignment Hyprland actually uses, wherever it's sourced
+        // from; only fall back to our own block when there isn't one
+        let (file, original) = match location {
+            Some(location) => {
+                let file = &mut files[location.file];
+                let original = file.render();
+                hyprconf::apply(file, &location.owner, &settings);
+                (file, original)
+            }
+            None => {
+                let file = &mut files[0];
+                let original = if file.lines.is_empty() { String::new() } else { file.render() };
+                let mut body = String::from("input {\n");
+                for (key, value) in &settings {
+                    body.push_str(format!("    {} = {}", key, value).trim_end());
+                    body.push('\n');
+                }
+                body.push('}');
+                let updated = managed_block::upsert(&original, "#", &body);
+                file.lines = updated.lines().map(str::to_string).collect();
+                (file, original)
+            }
+        };
+        let updated = file.render();
+        // Hyprland reloads on every write, so don't touch an up-to-date file
+        if updated == original {
+            return Ok(());
+        }
+        fsutil::write_atomic(&file.path, &updated)
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+
+use super::KeyboardBackend;
+use crate::{config, exec, fsutil, log, xkb};
+
+/// LabWC takes its keymap from XKB_DEFAULT_* variables in its environment
+/// file, so every change is written there and picked up on reconfigure.
+pub struct Labwc;
+
+fn environment_path() -> PathBuf {
+    config::xdg_config_home().join("labwc").join("environment")
+}
+
+impl Labwc {
+    fn read(&self) -> String {
+        fs::read_to_string(environment_path()).unwrap_or_default()
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.read().lines().rev().find_map(|line| {
+            let (name, value) = line.trim().split_once('=')?;
+            (name.trim() == key).then(|| value.trim().trim_matches('"').to_string())
+        })
+    }
+
+    /// Sets (or, for empty values, removes) variables and reconfigures.
+    fn set(&self, values: &[(&str, &str)]) -> Result<()> {
+        let path = environment_path();
+        let contents = self.read();
+        let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+        for (key, value) in values {
+            let matches = |line: &String| line.split_once('=').is_some_and(|(name, _)| name.trim() == *key);
+            let assignment = format!("{}={}", key, value);
+            match lines.iter().position(matches) {
+                Some(index) if value.is_empty() => {
+                    lines.remove(index);
+                }
+                Some(index) => lines[index] = assignment,
+                None if !value.is_empty() => lines.push(assignment),
+                None => {}
+            }
+            // Drop any later duplicates so ours is the one that counts
+            if let Some(first) = lines.iter().position(matches) {
+                let mut index = first + 1;
+                while index < lines.len() {
+                    if matches(&lines[index]) {
+                        lines.remove(index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+        }
+        let mut updated = lines.join("\n");
+        updated.push('\n');
+        if updated != contents {
+            fsutil::write_atomic(&path, &updated)?;
+        }
+        match log::run(exec::local("labwc").arg("--reconfigure")) {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                bail!("labwc --reconfigure failed: {}", error.trim())
+            }
+            Err(e) => Err(e).context("Failed to execute labwc"),
+        }
+    }
+}
+
+impl KeyboardBackend for Labwc {
+    fn name(&self) -> &'static str {
+        "labwc"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let layout = self.get("XKB_DEFAULT_LAYOUT").filter(|layout| !layout.is_empty())?;
+        match self.get("XKB_DEFAULT_VARIANT") {
+            Some(variant) if !variant.is_empty() && !layout.contains(',') => {
+                Some(format!("{}({})", layout, variant))
+            }
+            _ => Some(layout),
+        }
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        self.set(&[("XKB_DEFAULT_LAYOUT", layout), ("XKB_DEFAULT_VARIANT", variant.unwrap_or(""))])
+    }
+
+    fn options(&self) -> Option<String> {
+        Some(self.get("XKB_DEFAULT_OPTIONS").unwrap_or_default())
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        self.set(&[("XKB_DEFAULT_OPTIONS", options)])
+    }
+
+    fn persist(&self) -> Result<()> {
+        // Changes already live in the environment file
+        Ok(())
+    }
+}
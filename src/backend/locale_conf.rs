@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::LocaleBackend;
+use crate::{audit, config, exec, fsutil, locale_info, system};
+
+/// Systems without systemd (Void, Artix, Alpine) read LANG from a plain
+/// file at login. In user mode it's the user's own
+/// `$XDG_CONFIG_HOME/locale.conf` instead, which profile.d/locale.sh
+/// reads after the system one.
+pub struct LocaleConf {
+    pub user: bool,
+}
+
+const LOCALE_CONF: &str = "/etc/locale.conf";
+/// Alpine has no locale.conf; its /etc/profile sources profile.d instead.
+const PROFILE_LOCALE: &str = "/etc/profile.d/locale.sh";
+
+fn system_file() -> PathBuf {
+    if !exec::exists(Path::new(LOCALE_CONF)) && exec::exists(Path::new("/etc/alpine-release")) {
+        return PathBuf::from(PROFILE_LOCALE);
+    }
+    PathBuf::from(LOCALE_CONF)
+}
+
+/// The `NAME=value` pair on a line, with any `export` and quotes dropped.
+fn assignment(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.trim().trim_start_matches("export ").split_once('=')?;
+    Some((name.trim(), value.trim().trim_matches('"')))
+}
+
+impl LocaleConf {
+    fn config_file(&self) -> PathBuf {
+        if self.user { config::xdg_config_home().join("locale.conf") } else { system_file() }
+    }
+
+    /// Replaces the `name` assignment with `value`, inserting it after the
+    /// assignments that should come first.
+    fn set_variable(&self, name: &str, value: &str, insert_at: impl Fn(&[String]) -> usize) -> Result<()> {
+        let path = self.config_file();
+        let contents = exec::read_to_string(&path).unwrap_or_default();
+        let export = if path == Path::new(PROFILE_LOCALE) { "export " } else { "" };
+        // Keep every other variable the user has
+        let mut lines: Vec<String> = contents
+            .lines()
+            .filter(|line| assignment(line).is_none_or(|(key, _)| key != name))
+            .map(str::to_string)
+            .collect();
+        let index = insert_at(&lines);
+        lines.insert(index, format!("{}{}={}", export, name, value));
+        let mut updated = lines.join("\n");
+        updated.push('\n');
+        if self.user {
+            return fsutil::write_atomic(&path, &updated);
+        }
+        fsutil::write_as_root(&path, &updated)?;
+        let old = contents.lines().filter_map(assignment).rfind(|(key, _)| *key == name).map(|(_, old)| old);
+        audit::record(name, old, value, self.name());
+        Ok(())
+    }
+}
+
+impl LocaleBackend for LocaleConf {
+    fn name(&self) -> &'static str {
+        if self.user { "user locale.conf" } else { "locale.conf" }
+    }
+
+    fn available_locales(&self) -> Vec<String> {
+        let mut locales = super::or_supported(locale_info::installed_locales());
+        if system::is_musl() {
+            // Every locale with translations, which `locale -a` may not list
+            locales.extend(exec::read_dir_names(Path::new(locale_info::MUSL_LOCPATH)));
+        }
+        locales.sort();
+        locales.dedup();
+        locales
+    }
+
+
+    fn current_locale(&self) -> Option<String> {
+        let contents = exec::read_to_string(&self.config_file()).unwrap_or_default();
+        let configured = contents
+            .lines()
+            .filter_map(assignment)
+            .rfind(|(name, _)| *name == "LANG")
+            .map(|(_, value)| value.to_string());
+        // Without a LANG of the user's own, the session's is still in effect
+        configured.or_else(|| self.user.then(|| std::env::var("LANG").ok()).flatten())
+    }
+
+    fn set_locale(&self, locale: &str) -> Result<()> {
+        let path = self.config_file();
+        self.set_variable("LANG", locale, |_| 0)?;
+        // musl only finds translations through MUSL_LOCPATH, which nothing
+        // sets by default
+        if system::is_musl() && exec::is_dir(Path::new(locale_info::MUSL_LOCPATH)) {
+            let contents = exec::read_to_string(&path).unwrap_or_default();
+            if !contents.lines().filter_map(assignment).any(|(name, _)| name == "MUSL_LOCPATH") {
+                self.set_variable("MUSL_LOCPATH", locale_info::MUSL_LOCPATH, |lines| lines.len())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn categories(&self) -> Vec<(String, String)> {
+        let contents = exec::read_to_string(&self.config_file()).unwrap_or_default();
+        contents
+            .lines()
+            .filter_map(assignment)
+            .filter(|(name, _)| name.starts_with("LC_"))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn shell_commands(&self, lang: &str, categories: &[(String, String)]) -> Vec<String> {
+        let path = self.config_file();
+        let export = if path == Path::new(PROFILE_LOCALE) { "export " } else { "" };
+        let lines: Vec<String> = std::iter::once(("LANG", lang))
+            .chain(categories.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+            .map(|(name, value)| exec::shell_quote(&format!("{}{}={}", export, name, value)))
+            .collect();
+        let printf = format!("printf '%s\\n' {}", lines.join(" "));
+        if self.user {
+            let directory = path.parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default();
+            let path = exec::shell_quote(&path.to_string_lossy());
+            return vec![format!("mkdir -p {} && {} > {}", exec::shell_quote(&directory), printf, path)];
+        }
+        vec![format!("{} | sudo tee {} >/dev/null", printf, path.display())]
+    }
+
+    fn set_category(&self, category: &str, locale: &str) -> Result<()> {
+        // After LANG, so the file reads from general to specific
+        self.set_variable(category, locale, |lines| {
+            lines.iter().position(|line| assignment(line).is_some_and(|(name, _)| name == "LANG")).map_or(0, |index| index + 1)
+        })
+    }
+
+    fn unset_variables(&self, names: &[String]) -> Result<()> {
+        let path = self.config_file();
+        let contents = exec::read_to_string(&path).unwrap_or_default();
+        let named = |line: &&str| assignment(line).is_some_and(|(name, _)| names.iter().any(|wanted| wanted == name));
+        if !contents.lines().any(|line| named(&line)) {
+            return Ok(());
+        }
+        let updated: String = contents.lines().filter(|line| !named(line)).map(|line| format!("{}\n", line)).collect();
+        if self.user {
+            return fsutil::write_atomic(&path, &updated);
+        }
+        fsutil::write_as_root(&path, &updated)?;
+        for (name, old) in contents.lines().filter(named).filter_map(assignment) {
+            audit::record(name, Some(old), "(unset)", self.name());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{Result, bail};
+
+use super::LocaleBackend;
+use crate::{audit, exec, locale_info, log};
+
+/// systemd-localed, driven through localectl.
+pub struct Localed;
+
+impl LocaleBackend for Localed {
+    fn name(&self) -> &'static str {
+        "localed"
+    }
+
+    fn available_locales(&self) -> Vec<String> {
+        let locales = match exec::command("localectl", &["list-locales"]).output() {
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            // Without localectl, find them the way it would
+            Err(_) => locale_info::compiled_locales(),
+        };
+        super::or_supported(locales)
+    }
+
+    fn current_locale(&self) -> Option<String> {
+        self.settings().into_iter().find_map(|(name, value)| (name == "LANG").then_some(value))
+    }
+
+    fn set_locale(&self, locale: &str) -> Result<()> {
+        // A bare set-locale LANG=… would drop the LC_* overrides
+        self.set_variables(&[("LANG".to_string(), locale.to_string())])
+    }
+
+    fn categories(&self) -> Vec<(String, String)> {
+        self.settings().into_iter().filter(|(name, _)| name.starts_with("LC_")).collect()
+    }
+
+    fn set_category(&self, category: &str, locale: &str) -> Result<()> {
+        self.set_variables(&[(category.to_string(), locale.to_string())])
+    }
+
+    fn set_variables(&self, variables: &[(String, String)]) -> Result<()> {
+        // set-locale replaces every variable at once, so resend the others
+        let old = self.settings();
+        let mut settings: Vec<(String, String)> =
+            old.iter().filter(|(name, _)| variables.iter().all(|(changed, _)| changed != name)).cloned().collect();
+        settings.extend(variables.iter().cloned());
+        let mut args = vec!["localectl".to_string(), "set-locale".to_string()];
+        args.extend(settings.iter().map(|(name, value)| format!("{}={}", name, value)));
+        let names: Vec<&str> = variables.iter().map(|(name, _)| name.as_str()).collect();
+        match log::run(&mut exec::privileged(&args)?) {
+            Ok(output) if output.status.success() => {
+                for (name, value) in variables {
+                    let before = old.iter().find_map(|(old_name, old)| (old_name == name).then_some(old.as_str()));
+                    audit::record(name, before, value, self.name());
+                }
+                Ok(())
+            }
+            _ => bail!("Failed to set {} (check sudo access)", names.join(", ")),
+        }
+    }
+
+    fn unset_variables(&self, names: &[String]) -> Result<()> {
+        let old = self.settings();
+        let mut args = vec!["localectl".to_string(), "set-locale".to_string()];
+        let kept = old.iter().filter(|(name, _)| !names.contains(name));
+        args.extend(kept.map(|(name, value)| format!("{}={}", name, value)));
+        match log::run(&mut exec::privileged(&args)?) {
+            Ok(output) if output.status.success() => {
+                for (name, value) in old.iter().filter(|(name, _)| names.contains(name)) {
+                    audit::record(name, Some(value), "(unset)", self.name());
+                }
+                Ok(())
+            }
+            _ => bail!("Failed to unset {} (check sudo access)", names.join(", ")),
+        }
+    }
+
+    fn shell_commands(&self, lang: &str, categories: &[(String, String)]) -> Vec<String> {
+        vec![format!("sudo localectl set-locale {}", super::assignments(lang, categories).join(" "))]
+    }
+}
+
+impl Localed {
+    /// The LANG and LC_* assignments from `localectl status`.
+    fn settings(&self) -> Vec<(String, String)> {
+        let Ok(output) = exec::command("localectl", &["status"]).output() else {
+            return Vec::new();
+        };
+        let mut settings = Vec::new();
+        let mut in_locale = false;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            // The first variable shares the "System Locale:" line, the rest
+            // are indented below it
+            let line = line.trim();
+            let line = match line.strip_prefix("System Locale:") {
+                Some(rest) => {
+                    in_locale = true;
+                    rest.trim()
+                }
+                None if in_locale && !line.contains(':') => line,
+                None => {
+                    in_locale = false;
+                    continue;
+                }
+            };
+            if let Some((name, value)) = line.split_once('=') {
+                settings.push((name.to_string(), value.trim().to_string()));
+            }
+        }
+        settings
+    }
+}
@@ -0,0 +1,122 @@
+
+use anyhow::{Result, bail};
+
+use super::{KeyboardBackend, LocaleBackend};
+use crate::exec;
+
+const HITOOLBOX: &str = "com.apple.HIToolbox";
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    finish(program, exec::local(program).args(args).output())
+}
+
+/// Like [`run`], but on the `--host` machine when there is one.
+fn run_on_target(program: &str, args: &[&str]) -> Result<String> {
+    finish(program, exec::command(program, args).output())
+}
+
+fn finish(program: &str, output: std::io::Result<std::process::Output>) -> Result<String> {
+    match output {
+        Ok(output) if output.status.success() => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Ok(output) => bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()),
+        Err(e) => bail!("Failed to execute {}: {}", program, e),
+    }
+}
+
+/// The value of a `key = value;` line in `defaults read` output.
+fn plist_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (name, value) = line.trim().split_once('=')?;
+    (name.trim().trim_matches('"') == key).then(|| value.trim().trim_end_matches(';').trim_matches('"'))
+}
+
+/// Input sources, switched with im-select (or macism), since the TIS API
+/// has no command-line front end of its own.
+pub struct MacOs;
+
+impl MacOs {
+    fn switcher(&self) -> &'static str {
+        if exec::local("macism").arg("--help").output().is_ok() { "macism" } else { "im-select" }
+    }
+}
+
+impl KeyboardBackend for MacOs {
+    fn name(&self) -> &'static str {
+        "macos"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        run(self.switcher(), &[]).ok().filter(|id| !id.is_empty())
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        run(self.switcher(), &[layout]).map(|_| ())
+    }
+
+    fn options(&self) -> Option<String> {
+        None
+    }
+
+    fn set_options(&self, _options: &str) -> Result<()> {
+        bail!("macOS doesn't support XKB options")
+    }
+
+    fn shell_commands(&self, layout: &str, _options: &str) -> Vec<String> {
+        vec![format!("{} {}", self.switcher(), exec::shell_quote(layout))]
+    }
+
+    fn available_layouts(&self) -> Option<Vec<(String, String)>> {
+        let output = run("defaults", &["read", HITOOLBOX, "AppleEnabledInputSources"]).ok()?;
+        let mut layouts = Vec::new();
+        // One dictionary per source: keyboard layouts carry a name, input
+        // methods a bundle and usually a mode within it
+        for block in output.split('}') {
+            let value = |key| block.lines().find_map(|line| plist_value(line, key));
+            let source = if let Some(mode) = value("Input Mode") {
+                (mode.to_string(), mode.rsplit('.').next().unwrap_or(mode).to_string())
+            } else if let Some(name) = value("KeyboardLayout Name") {
+                // Source IDs drop the punctuation, e.g. "U.S." is com.apple.keylayout.US
+                let id: String = name.chars().filter(|c| c.is_alphanumeric() || *c == '-').collect();
+                (format!("com.apple.keylayout.{}", id), name.to_string())
+            } else if let Some(bundle) = value("Bundle ID") {
+                (bundle.to_string(), bundle.rsplit('.').next().unwrap_or(bundle).to_string())
+            } else {
+                continue;
+            };
+            if !layouts.contains(&source) {
+                layouts.push(source);
+            }
+        }
+        Some(layouts)
+    }
+}
+
+/// The region format macOS apps use, stored in the global AppleLocale
+/// default.
+pub struct MacLocale;
+
+impl LocaleBackend for MacLocale {
+    fn name(&self) -> &'static str {
+        "macos"
+    }
+
+    fn available_locales(&self) -> Vec<String> {
+        run_on_target("locale", &["-a"])
+            .map(|output| output.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn current_locale(&self) -> Option<String> {
+        run_on_target("defaults", &["read", "-g", "AppleLocale"]).ok()
+    }
+
+    fn set_locale(&self, locale: &str) -> Result<()> {
+        // AppleLocale has no codeset: de_DE.UTF-8 becomes de_DE
+        let region = locale.split(['.', '@']).next().unwrap_or(locale);
+        run_on_target("defaults", &["write", "-g", "AppleLocale", region]).map(|_| ())
+    }
+
+    fn shell_commands(&self, lang: &str, _categories: &[(String, String)]) -> Vec<String> {
+        let region = lang.split(['.', '@']).next().unwrap_or(lang);
+        vec![format!("defaults write -g AppleLocale {}", exec::shell_quote(region))]
+    }
+}
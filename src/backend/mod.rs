@@ -0,0 +1,375 @@
+mod console;
+mod cosmic;
+mod debian;
+mod freebsd;
+mod hyprconf;
+mod hyprland;
+mod labwc;
+mod locale_conf;
+mod macos;
+mod localed;
+mod sway;
+mod weston;
+mod x11;
+mod xorg_seat;
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+
+use crate::locale_info::{self, LocaleError};
+use crate::{config, exec, seat, system};
+
+pub use console::Console;
+pub use cosmic::Cosmic;
+pub use debian::Debian;
+pub use freebsd::{LoginConf, Vt};
+pub use hyprland::Hyprland;
+pub use labwc::Labwc;
+pub use locale_conf::LocaleConf;
+pub use localed::Localed;
+pub use macos::{MacLocale, MacOs};
+pub use sway::Sway;
+pub use weston::Weston;
+pub use x11::X11;
+pub use xorg_seat::XorgSeat;
+
+/// A compositor or display server that owns the live keyboard configuration.
+pub trait KeyboardBackend {
+    fn name(&self) -> &'static str;
+
+    /// The configured layout code(s), e.g. "us" or "us,de".
+    fn current_layout(&self) -> Option<String>;
+
+    fn set_layout(&self, layout: &str) -> Result<()>;
+
+    /// The comma-separated XKB options string currently in effect.
+    fn options(&self) -> Option<String>;
+
+    fn set_options(&self, options: &str) -> Result<()>;
+
+    /// Sets the layout and options together. Backends that can change both
+    /// in one step override this, so the keymap is never left half-changed.
+    fn set_keyboard(&self, layout: Option<&str>, options: Option<&str>) -> Result<()> {
+        if let Some(layout) = layout {
+            self.set_layout(layout)?;
+        }
+        match options {
+            Some(options) => self.set_options(options),
+            None => Ok(()),
+        }
+    }
+
+    /// Layouts this backend knows by its own codes, when they aren't XKB
+    /// layouts that can be derived from the installed locales.
+    fn available_layouts(&self) -> Option<Vec<(String, String)>> {
+        None
+    }
+
+    /// A compiled keymap file overriding the rules-based layout, if any.
+    fn keymap_file(&self) -> Option<String> {
+        None
+    }
+
+    /// Loads a compiled keymap file, or goes back to the rules-based
+    /// layout when `path` is None.
+    fn set_keymap_file(&self, path: Option<&str>) -> Result<()> {
+        let _ = path;
+        bail!("{} doesn't support keymap files", self.name())
+    }
+
+    /// Saves the live settings to the backend's config file so they are
+    /// applied again on the next start.
+    fn persist(&self) -> Result<()> {
+        bail!("{} doesn't support saving keyboard settings", self.name())
+    }
+
+    /// The attached keyboards, named the way [`set_device_layout`] expects.
+    ///
+    /// [`set_device_layout`]: KeyboardBackend::set_device_layout
+    fn keyboards(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Gives one keyboard its own layout, leaving the rest on the shared one.
+    fn set_device_layout(&self, device: &str, layout: &str) -> Result<()> {
+        let _ = (device, layout);
+        bail!("{} can't give keyboards their own layouts", self.name())
+    }
+
+    /// Gives one keyboard its own XKB options.
+    fn set_device_options(&self, device: &str, options: &str) -> Result<()> {
+        let _ = (device, options);
+        bail!("{} can't give keyboards their own options", self.name())
+    }
+
+    /// [`set_keyboard`] for one keyboard.
+    ///
+    /// [`set_keyboard`]: KeyboardBackend::set_keyboard
+    fn set_device_keyboard(&self, device: &str, layout: Option<&str>, options: Option<&str>) -> Result<()> {
+        if let Some(layout) = layout {
+            self.set_device_layout(device, layout)?;
+        }
+        match options {
+            Some(options) => self.set_device_options(device, options),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether changes only take effect once the session restarts.
+    fn needs_restart(&self) -> bool {
+        false
+    }
+
+    /// Shell commands that reproduce `layout` and `options`, for
+    /// `levocale export --shell`.
+    fn shell_commands(&self, _layout: &str, _options: &str) -> Vec<String> {
+        vec![format!("# The {} backend has no command-line equivalent", self.name())]
+    }
+}
+
+/// The keyboard backends `--backend` and the `backend` config key can name.
+pub const KEYBOARD_BACKENDS: [&str; 9] =
+    ["hyprland", "sway", "labwc", "weston", "cosmic", "x11", "console", "vt", "macos"];
+
+/// The backend named with --backend, which beats the config's.
+static FORCED: Mutex<Option<String>> = Mutex::new(None);
+
+/// The config's `backend`, once the config has been read.
+static CONFIGURED: Mutex<Option<Option<String>>> = Mutex::new(None);
+
+/// Uses the keyboard backend called `name` instead of detecting one.
+pub fn force(name: &str) -> Result<()> {
+    if !KEYBOARD_BACKENDS.contains(&name) {
+        bail!("Unknown backend {:?} (there are {})", name, KEYBOARD_BACKENDS.join(", "));
+    }
+    if let Ok(mut forced) = FORCED.lock() {
+        *forced = Some(name.to_string());
+    }
+    Ok(())
+}
+
+pub fn configure(backend: Option<String>) {
+    if let Ok(mut configured) = CONFIGURED.lock() {
+        *configured = Some(backend);
+    }
+}
+
+/// The backend picked by hand, if any; the config is read the first time
+/// when nothing has set it yet.
+fn chosen() -> Option<String> {
+    if let Some(name) = FORCED.lock().ok().and_then(|forced| forced.clone()) {
+        return Some(name);
+    }
+    let mut configured = CONFIGURED.lock().ok()?;
+    configured
+        .get_or_insert_with(|| config::Config::load().ok().and_then(|config| config.backend))
+        .clone()
+}
+
+/// The keyboard backend the session's environment points to. The first of
+/// these wins: the OS for macOS and a FreeBSD console, a compositor's own
+/// socket variable (HYPRLAND_INSTANCE_SIGNATURE, SWAYSOCK, LABWC_PID), the
+/// desktop named in XDG_CURRENT_DESKTOP or XDG_SESSION_DESKTOP, and then
+/// DISPLAY without WAYLAND_DISPLAY for X11. Hyprland is the fallback.
+pub fn detect() -> &'static str {
+    let has = |var: &str| std::env::var_os(var).is_some();
+    if std::env::consts::OS == "macos" {
+        return "macos";
+    }
+    if std::env::consts::OS == "freebsd" && !has("DISPLAY") && !has("WAYLAND_DISPLAY") {
+        return "vt";
+    }
+    // Each compositor advertises itself in the session environment
+    if has("HYPRLAND_INSTANCE_SIGNATURE") {
+        return "hyprland";
+    }
+    if has("SWAYSOCK") {
+        return "sway";
+    }
+    if has("LABWC_PID") {
+        return "labwc";
+    }
+    let is_desktop = |name: &str| {
+        ["XDG_CURRENT_DESKTOP", "XDG_SESSION_DESKTOP"].iter().any(|var| {
+            std::env::var(var).is_ok_and(|value| value.split(':').any(|d| d.eq_ignore_ascii_case(name)))
+        })
+    };
+    if let Some(name) = ["hyprland", "sway", "labwc", "weston", "cosmic"].into_iter().find(|name| is_desktop(name)) {
+        return name;
+    }
+    if !has("WAYLAND_DISPLAY") && has("DISPLAY") {
+        return "x11";
+    }
+    "hyprland"
+}
+
+pub fn active() -> Box<dyn KeyboardBackend> {
+    // Our compositor only drives the keyboards of our own seat
+    if let Some(seat) = seat::target() {
+        return Box::new(XorgSeat { seat });
+    }
+    named(chosen().as_deref().unwrap_or_else(|| detect()))
+}
+
+/// The keyboard backend called `name`, one of [`KEYBOARD_BACKENDS`].
+pub fn named(name: &str) -> Box<dyn KeyboardBackend> {
+    match name {
+        "sway" => Box::new(Sway),
+        "labwc" => Box::new(Labwc),
+        "weston" => Box::new(Weston),
+        "cosmic" => Box::new(Cosmic),
+        "x11" => Box::new(X11),
+        "console" => Box::new(Console),
+        "vt" => Box::new(Vt),
+        "macos" => Box::new(MacOs),
+        _ => Box::new(Hyprland),
+    }
+}
+
+/// Also sets `layout` and `options` on the backends in `mirror_keyboard`,
+/// naming each with how it went. Backends without XKB options, like the
+/// console, only get the layout.
+pub fn mirror_keyboard(
+    config: &config::Config,
+    layout: Option<&str>,
+    options: Option<&str>,
+) -> Vec<(&'static str, Result<()>)> {
+    let primary = active().name();
+    config
+        .mirror_keyboard
+        .iter()
+        .filter(|name| name.as_str() != primary)
+        .map(|name| named(name))
+        .filter_map(|mirror| {
+            let options = options.filter(|_| mirror.options().is_some());
+            if layout.is_none() && options.is_none() {
+                return None;
+            }
+            Some((mirror.name(), mirror.set_keyboard(layout, options)))
+        })
+        .collect()
+}
+
+/// Whatever owns the system-wide locale setting.
+pub trait LocaleBackend {
+    fn name(&self) -> &'static str;
+
+    /// Locale codes that can be selected, e.g. "de_DE.UTF-8".
+    fn available_locales(&self) -> Vec<String>;
+
+    /// The configured system LANG, which may differ from this process's.
+    fn current_locale(&self) -> Option<String>;
+
+    fn set_locale(&self, locale: &str) -> Result<()>;
+
+    /// The charmap a locale that isn't installed yet would be built with,
+    /// when the backend knows, e.g. "ISO-8859-1" for Debian's "de_DE".
+    fn charmap(&self, locale: &str) -> Option<String> {
+        let wanted = locale_info::normalized_locale_name(locale);
+        locale_info::supported_locales()
+            .into_iter()
+            .find(|(name, _)| locale_info::normalized_locale_name(name) == wanted)
+            .map(|(_, charset)| charset)
+    }
+
+    /// Refuses malformed names and ones this backend doesn't offer, so
+    /// callers get a [`LocaleError`] rather than whatever the underlying
+    /// tool makes of them.
+    fn validate(&self, locale: &str) -> Result<()> {
+        locale_info::check_syntax(locale)?;
+        // musl takes any name; ones it has no translations for stay English
+        if matches!(locale_info::split_locale_code(locale).language, "C" | "POSIX") || system::is_musl() {
+            return Ok(());
+        }
+        let wanted = locale_info::normalized_locale_name(locale);
+        let available = self.available_locales();
+        // An empty list means the backend couldn't tell, not that nothing exists
+        if !available.is_empty() && !available.iter().any(|code| locale_info::normalized_locale_name(code) == wanted) {
+            return Err(LocaleError::Unavailable(locale.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// LC_* overrides configured next to LANG, e.g. ("LC_TIME", "en_GB.UTF-8").
+    fn categories(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn set_category(&self, category: &str, _locale: &str) -> Result<()> {
+        bail!("The {} backend can't set {}", self.name(), category)
+    }
+
+    /// Sets LANG and LC_* `variables` at once where the backend can, so
+    /// there's one authentication prompt instead of one per variable.
+    fn set_variables(&self, variables: &[(String, String)]) -> Result<()> {
+        for (name, value) in variables {
+            match name.as_str() {
+                "LANG" => self.set_locale(value)?,
+                category => self.set_category(category, value)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the LANG and LC_* `names`, e.g. to undo setting one that
+    /// wasn't set before, so it follows LANG or the default again.
+    fn unset_variables(&self, names: &[String]) -> Result<()> {
+        bail!("The {} backend can't unset {}", self.name(), names.join(", "))
+    }
+
+    /// Shell commands that set LANG and the LC_* `categories` the same way.
+    fn shell_commands(&self, _lang: &str, _categories: &[(String, String)]) -> Vec<String> {
+        vec![format!("# The {} backend has no command-line equivalent", self.name())]
+    }
+}
+
+/// Minimal installs generate nothing beyond C, which leaves nothing to pick;
+/// offer what SUPPORTED says could be built instead.
+fn or_supported(locales: Vec<String>) -> Vec<String> {
+    let builtin = |name: &String| name == "C" || name == "POSIX" || name.starts_with("C.");
+    if !locales.iter().all(builtin) {
+        return locales;
+    }
+    let mut supported: Vec<String> = locale_info::supported_locales().into_iter().map(|(name, _)| name).collect();
+    if supported.is_empty() {
+        return locales;
+    }
+    supported.extend(locales);
+    supported
+}
+
+/// `NAME=value` words for LANG and each LC_* override, shell-quoted.
+fn assignments(lang: &str, categories: &[(String, String)]) -> Vec<String> {
+    std::iter::once(("LANG", lang))
+        .chain(categories.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+        .map(|(name, value)| exec::shell_quote(&format!("{}={}", name, value)))
+        .collect()
+}
+
+pub fn active_locale() -> Box<dyn LocaleBackend> {
+    // Unlike the keyboard, the locale may belong to a remote host
+    if exec::os() == "macos" {
+        return Box::new(MacLocale);
+    }
+    // User mode keeps to the user's own files, which are only ours to
+    // write on this machine
+    let user = exec::mode() == config::Mode::User && exec::remote_host().is_none();
+    if exec::os() == "freebsd" {
+        let system_class = config::Config::load().ok().and_then(|config| config.login_class).filter(|_| !user);
+        return Box::new(LoginConf { system_class });
+    }
+    if user {
+        return Box::new(LocaleConf { user: true });
+    }
+    // localectl works on Debian too, but leaves locale.gen out of the loop
+    if exec::exists(Path::new("/etc/debian_version")) && exec::exists(Path::new("/usr/sbin/update-locale")) {
+        return Box::new(Debian);
+    }
+    // The same check sd_booted() makes
+    if exec::is_dir(Path::new("/run/systemd/system")) {
+        return Box::new(Localed);
+    }
+    Box::new(LocaleConf { user: false })
+}
@@ -0,0 +1,232 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result, bail};
+
+use super::KeyboardBackend;
+use crate::{config, exec, fsutil, log, managed_block, xkb};
+
+pub struct Sway;
+
+/// Sway's IPC reports neither layout codes nor XKB options, so options set
+/// during this session are remembered here.
+static OPTIONS: Mutex<Option<String>> = Mutex::new(None);
+/// Likewise the layout set during this session, all of it: IPC only names
+/// the active one of several, by its description.
+static LAYOUT: Mutex<Option<String>> = Mutex::new(None);
+
+fn include_path() -> PathBuf {
+    config::xdg_config_home().join("sway").join("levocale.conf")
+}
+
+fn config_path() -> PathBuf {
+    config::xdg_config_home().join("sway").join("config")
+}
+
+fn registry() -> &'static xkb::XkbRegistry {
+    static REGISTRY: OnceLock<xkb::XkbRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(xkb::load_registry)
+}
+
+/// Sway commands setting each of `settings` on the inputs `identifier`
+/// selects, separated by `;` so one swaymsg call sends them all. Values are
+/// quoted, as an empty one would otherwise vanish and leave the setting
+/// without one.
+fn input_commands(identifier: &str, settings: &[(&str, &str)]) -> String {
+    settings
+        .iter()
+        .map(|(setting, value)| format!("input \"{}\" {} \"{}\"", identifier, setting, value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The settings that switch to `layout`. As with Hyprland, each setting
+/// recompiles the keymap, so the variant is cleared first to never leave
+/// one behind that the new layout doesn't define.
+fn layout_settings(layout: &str) -> Vec<(&'static str, &str)> {
+    let (layout, variant) = xkb::split_layout_variant(layout);
+    let mut settings = vec![("xkb_variant", ""), ("xkb_layout", layout)];
+    if let Some(variant) = variant {
+        settings.push(("xkb_variant", variant));
+    }
+    settings
+}
+
+/// The last value of `setting`, e.g. "xkb_options", assigned anywhere in a
+/// Sway config.
+fn configured(contents: &str, setting: &str) -> Option<String> {
+    contents
+        .lines()
+        .filter_map(|line| line.split(setting).nth(1))
+        .map(|value| value.trim().trim_matches('"').to_string())
+        .next_back()
+}
+
+/// The layout a Sway config sets, with its variant folded in, e.g.
+/// "us,de" or "de(nodeadkeys)".
+fn configured_layout(contents: &str) -> Option<String> {
+    let layout = configured(contents, "xkb_layout").filter(|layout| !layout.is_empty())?;
+    Some(match configured(contents, "xkb_variant").filter(|variant| !variant.is_empty()) {
+        Some(variant) => format!("{}({})", layout, variant),
+        None => layout,
+    })
+}
+
+impl Sway {
+    fn swaymsg(&self, args: &[&str]) -> Result<String> {
+        let mut command = exec::local("swaymsg");
+        command.args(args);
+        // Queries run on every refresh; only changes are worth logging
+        let output = if args.first() == Some(&"-t") { command.output() } else { log::run(&mut command) };
+        let output = output.context("Failed to execute swaymsg")?;
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let error = if stderr.trim().is_empty() { stdout.as_str() } else { stderr.as_ref() };
+            bail!("swaymsg failed: {}", error.trim());
+        }
+        Ok(stdout)
+    }
+
+    /// What `read` finds in our include file, or else in the config Sway
+    /// loaded.
+    fn configured(&self, read: impl Fn(&str) -> Option<String>) -> Option<String> {
+        if let Some(value) = fs::read_to_string(include_path()).ok().as_deref().and_then(&read) {
+            return Some(value);
+        }
+        let output = self.swaymsg(&["-t", "get_config", "-r"]).ok()?;
+        let reply: serde_json::Value = serde_json::from_str(&output).ok()?;
+        read(reply["config"].as_str()?)
+    }
+
+    fn inputs(&self, settings: &[(&str, &str)]) -> Result<()> {
+        self.device_inputs("type:keyboard", settings)
+    }
+
+    /// Changes settings of the inputs `identifier` selects, in one call.
+    fn device_inputs(&self, identifier: &str, settings: &[(&str, &str)]) -> Result<()> {
+        let names: Vec<&str> = settings.iter().map(|(setting, _)| *setting).collect();
+        self.swaymsg(&[&input_commands(identifier, settings)])
+            .with_context(|| format!("Failed to set {}", names.join(", ")))
+            .map(|_| ())
+    }
+}
+
+impl KeyboardBackend for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let output = self.swaymsg(&["-t", "get_inputs", "-r"]).ok()?;
+        let inputs: serde_json::Value = serde_json::from_str(&output).ok()?;
+        let name = inputs
+            .as_array()?
+            .iter()
+            .filter(|input| input["type"] == "keyboard")
+            .find_map(|input| input["xkb_active_layout_name"].as_str())?;
+        // Map the description Sway reports back to the code it came from
+        let registry = registry();
+        registry.user_layouts.iter().chain(&registry.layouts).find_map(|layout| {
+            if layout.description == name {
+                return Some(layout.code.clone());
+            }
+            layout
+                .variants
+                .iter()
+                .find(|variant| variant.description == name)
+                .map(|variant| format!("{}({})", layout.code, variant.name))
+        })
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        self.inputs(&layout_settings(layout))?;
+        if let Ok(mut remembered) = LAYOUT.lock() {
+            *remembered = Some(layout.to_string());
+        }
+        Ok(())
+    }
+
+    fn options(&self) -> Option<String> {
+        if let Some(options) = OPTIONS.lock().ok()?.clone() {
+            return Some(options);
+        }
+        self.configured(|contents| configured(contents, "xkb_options"))
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        self.inputs(&[("xkb_options", options)])?;
+        if let Ok(mut remembered) = OPTIONS.lock() {
+            *remembered = Some(options.to_string());
+        }
+        Ok(())
+    }
+
+    fn keyboards(&self) -> Vec<String> {
+        let Ok(output) = self.swaymsg(&["-t", "get_inputs", "-r"]) else {
+            return Vec::new();
+        };
+        let inputs: serde_json::Value = serde_json::from_str(&output).unwrap_or_default();
+        inputs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|input| input["type"] == "keyboard")
+            .filter_map(|input| input["identifier"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn set_device_layout(&self, device: &str, layout: &str) -> Result<()> {
+        self.device_inputs(device, &layout_settings(layout))
+    }
+
+    fn set_device_options(&self, device: &str, options: &str) -> Result<()> {
+        self.device_inputs(device, &[("xkb_options", options)])
+    }
+
+    fn shell_commands(&self, layout: &str, options: &str) -> Vec<String> {
+        let mut settings = layout_settings(layout);
+        settings.push(("xkb_options", options));
+        vec![format!("swaymsg {}", exec::shell_quote(&input_commands("type:keyboard", &settings)))]
+    }
+
+    /// Writes our own include file and makes sure the main config pulls it
+    /// in, so the user's config only ever gains a single include line.
+    fn persist(&self) -> Result<()> {
+        let remembered = LAYOUT.lock().ok().and_then(|layout| layout.clone());
+        let Some(layout) = remembered.or_else(|| self.configured(configured_layout)) else {
+            bail!("Couldn't tell which layout Sway uses, so there's nothing to persist (pick one first)");
+        };
+        let (layout, variant) = xkb::split_layout_variant(&layout);
+        let options = self.options().unwrap_or_default();
+        let mut contents = String::from("# Generated by levocale; changes are overwritten.\ninput type:keyboard {\n");
+        for (setting, value) in [("xkb_layout", layout), ("xkb_variant", variant.unwrap_or("")), ("xkb_options", &options)] {
+            if !value.is_empty() {
+                contents.push_str(&format!("    {} {}\n", setting, value));
+            }
+        }
+        contents.push_str("}\n");
+
+        let include = include_path();
+        if fs::read_to_string(&include).ok().as_deref() != Some(contents.as_str()) {
+            fsutil::write_atomic(&include, &contents)?;
+        }
+
+        let main = config_path();
+        let main_contents = match fs::read_to_string(&main) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", main.display())),
+        };
+        let included = main_contents.lines().any(|line| {
+            let line = line.trim();
+            line.starts_with("include") && line.contains("levocale.conf")
+        });
+        if included {
+            return Ok(());
+        }
+        let body = format!("include {}", include.display());
+        fsutil::write_atomic(&main, &managed_block::upsert(&main_contents, "#", &body))
+    }
+}
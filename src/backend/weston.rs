@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use super::KeyboardBackend;
+use crate::{config, fsutil, xkb};
+
+/// Weston only reads its keymap at startup, from weston.ini's [keyboard]
+/// section.
+pub struct Weston;
+
+fn ini_path() -> PathBuf {
+    match std::env::var_os("WESTON_CONFIG_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => config::xdg_config_home().join("weston.ini"),
+    }
+}
+
+impl Weston {
+    fn read(&self) -> String {
+        fs::read_to_string(ini_path()).unwrap_or_default()
+    }
+
+    /// Line range of the [keyboard] section's body, if there is one.
+    fn keyboard_section(lines: &[String]) -> Option<(usize, usize)> {
+        let start = lines.iter().position(|line| line.trim() == "[keyboard]")? + 1;
+        let end = lines[start..]
+            .iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .map_or(lines.len(), |offset| start + offset);
+        Some((start, end))
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        let lines: Vec<String> = self.read().lines().map(str::to_string).collect();
+        let (start, end) = Self::keyboard_section(&lines)?;
+        lines[start..end].iter().find_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            (name.trim() == key).then(|| value.trim().to_string())
+        })
+    }
+
+    /// Edits keys in place, leaving comments and other sections untouched.
+    fn set(&self, values: &[(&str, &str)]) -> Result<()> {
+        let contents = self.read();
+        let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+        if Self::keyboard_section(&lines).is_none() {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push("[keyboard]".to_string());
+        }
+        for (key, value) in values {
+            let (start, end) = Self::keyboard_section(&lines).unwrap_or((lines.len(), lines.len()));
+            let existing = (start..end)
+                .find(|&index| lines[index].split_once('=').is_some_and(|(name, _)| name.trim() == *key));
+            match existing {
+                Some(index) if value.is_empty() => {
+                    lines.remove(index);
+                }
+                Some(index) => lines[index] = format!("{}={}", key, value),
+                None if !value.is_empty() => {
+                    // After the last setting rather than before a blank separator
+                    let insert_at = (start..end)
+                        .rev()
+                        .find(|&index| !lines[index].trim().is_empty())
+                        .map_or(start, |index| index + 1);
+                    lines.insert(insert_at, format!("{}={}", key, value));
+                }
+                None => {}
+            }
+        }
+        let mut updated = lines.join("\n");
+        updated.push('\n');
+        if updated == contents {
+            return Ok(());
+        }
+        fsutil::write_atomic(&ini_path(), &updated)
+    }
+}
+
+impl KeyboardBackend for Weston {
+    fn name(&self) -> &'static str {
+        "weston"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let layout = self.get("keymap_layout").filter(|layout| !layout.is_empty())?;
+        match self.get("keymap_variant") {
+            Some(variant) if !variant.is_empty() && !layout.contains(',') => {
+                Some(format!("{}({})", layout, variant))
+            }
+            _ => Some(layout),
+        }
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        self.set(&[("keymap_layout", layout), ("keymap_variant", variant.unwrap_or(""))])
+    }
+
+    fn options(&self) -> Option<String> {
+        Some(self.get("keymap_options").unwrap_or_default())
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        self.set(&[("keymap_options", options)])
+    }
+
+    fn persist(&self) -> Result<()> {
+        // weston.ini is the only place the settings live
+        Ok(())
+    }
+
+    fn needs_restart(&self) -> bool {
+        true
+    }
+}
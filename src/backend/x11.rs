@@ -0,0 +1,190 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+
+use super::KeyboardBackend;
+use crate::{config, exec, fsutil, log, managed_block, state, xkb};
+
+pub struct X11;
+
+/// Notes in the state file which keymap file the X server on `display`
+/// has loaded, if any.
+fn remember_keymap(display: &str, path: Option<&str>) -> Result<()> {
+    let mut state = state::State::load();
+    let previous = match path {
+        Some(path) => state.x11_keymaps.insert(display.to_string(), path.to_string()),
+        None => state.x11_keymaps.remove(display),
+    };
+    if previous.as_deref() == path {
+        return Ok(());
+    }
+    state.save()
+}
+
+/// The display xkbcomp talks to.
+fn display() -> Result<String> {
+    std::env::var("DISPLAY").ok().filter(|display| !display.is_empty()).context("DISPLAY isn't set")
+}
+
+impl X11 {
+    /// The keymap the X server is using, as `xkbcomp -xkb` prints it.
+    pub fn dump_keymap() -> Result<String> {
+        let output = exec::local("xkbcomp")
+            .args(["-w", "0", "-xkb", &display()?, "-"])
+            .output()
+            .context("xkbcomp not found (it comes with the X server's tools)")?;
+        if !output.status.success() {
+            bail!("xkbcomp couldn't read the keymap: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// One field of `setxkbmap -query`, e.g. "layout" or "options".
+    fn query(&self, field: &str) -> Option<String> {
+        let output = exec::local("setxkbmap").arg("-query").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout).lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == field).then(|| value.trim().to_string())
+        })
+    }
+
+    fn setxkbmap(&self, args: &[&str]) -> Result<()> {
+        match log::run(exec::local("setxkbmap").args(args)) {
+            Ok(output) if output.status.success() => {
+                // The rules names replace whatever keymap file was loaded
+                if let Ok(display) = display() {
+                    remember_keymap(&display, None)?;
+                }
+                Ok(())
+            }
+            Ok(output) => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                bail!("setxkbmap failed: {}", error.trim())
+            }
+            Err(e) => bail!("Failed to execute setxkbmap: {}", e),
+        }
+    }
+}
+
+/// i3's config when running under i3, otherwise ~/.xprofile, which display
+/// managers source for every X session.
+fn persist_target() -> (PathBuf, bool) {
+    if std::env::var_os("I3SOCK").is_some() {
+        let candidates = [
+            config::xdg_config_home().join("i3").join("config"),
+            config::home_dir().join(".i3").join("config"),
+        ];
+        if let Some(path) = candidates.into_iter().find(|path| path.exists()) {
+            return (path, true);
+        }
+    }
+    (config::home_dir().join(".xprofile"), false)
+}
+
+/// A setxkbmap invocation that sets all three at once, replacing any
+/// options already in effect.
+fn setxkbmap_command(layout: &str, variant: &str, options: &str) -> String {
+    let mut command =
+        format!("setxkbmap -layout {} -variant {} -option ''", exec::shell_quote(layout), exec::shell_quote(variant));
+    if !options.is_empty() {
+        command.push_str(&format!(" -option {}", exec::shell_quote(options)));
+    }
+    command
+}
+
+impl KeyboardBackend for X11 {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let layout = self.query("layout").filter(|layout| !layout.is_empty())?;
+        match self.query("variant") {
+            Some(variant) if !variant.is_empty() && !layout.contains(',') => {
+                Some(format!("{}({})", layout, variant))
+            }
+            _ => Some(layout),
+        }
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        // setxkbmap keeps the current options unless told otherwise
+        self.setxkbmap(&["-layout", layout, "-variant", variant.unwrap_or("")])
+    }
+
+    fn options(&self) -> Option<String> {
+        // -query leaves the line out entirely when no options are set
+        Some(self.query("options").unwrap_or_default())
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        // An empty -option clears the existing list instead of appending to it
+        let mut args = vec!["-option", ""];
+        if !options.is_empty() {
+            args.extend(["-option", options]);
+        }
+        self.setxkbmap(&args)
+    }
+
+    fn shell_commands(&self, layout: &str, options: &str) -> Vec<String> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        vec![setxkbmap_command(layout, variant.unwrap_or(""), options)]
+    }
+
+    fn keymap_file(&self) -> Option<String> {
+        state::State::load().x11_keymaps.remove(&display().ok()?)
+    }
+
+    fn set_keymap_file(&self, path: Option<&str>) -> Result<()> {
+        match path {
+            Some(path) => match log::run(exec::local("xkbcomp").args(["-w", "0", path, &display()?])) {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => {
+                    let error = String::from_utf8_lossy(&output.stderr);
+                    bail!("xkbcomp couldn't load {}: {}", path, error.trim())
+                }
+                Err(e) => bail!("Failed to execute xkbcomp: {}", e),
+            },
+            // xkbcomp leaves the rules names alone, so they still say what
+            // setxkbmap last set up
+            None => {
+                let (layout, variant) = (self.query("layout").unwrap_or_default(), self.query("variant"));
+                let options = self.query("options").unwrap_or_default();
+                self.setxkbmap(&["-layout", &layout, "-variant", variant.as_deref().unwrap_or(""), "-option", ""])?;
+                if !options.is_empty() {
+                    self.setxkbmap(&["-option", &options])?;
+                }
+            }
+        }
+        remember_keymap(&display()?, path)
+    }
+
+    fn persist(&self) -> Result<()> {
+        let layout = self.query("layout").unwrap_or_default();
+        let variant = self.query("variant").unwrap_or_default();
+        let options = self.query("options").unwrap_or_default();
+        let command = setxkbmap_command(&layout, &variant, &options);
+        let (path, is_i3) = persist_target();
+        let body = if is_i3 {
+            // exec_always so a config reload puts the layout back as well
+            format!("exec_always --no-startup-id {}", command)
+        } else {
+            command
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let updated = managed_block::upsert(&contents, "#", &body);
+        if updated == contents {
+            return Ok(());
+        }
+        fsutil::write_atomic(&path, &updated)
+    }
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use super::KeyboardBackend;
+use crate::{audit, exec, fsutil, xkb};
+
+/// The keyboards of another seat, set through an InputClass section that
+/// only matches that seat. Its X server reads it at the next login.
+pub struct XorgSeat {
+    pub seat: String,
+}
+
+impl XorgSeat {
+    fn path(&self) -> PathBuf {
+        PathBuf::from(format!("/etc/X11/xorg.conf.d/00-keyboard-{}.conf", self.seat))
+    }
+
+    /// One `Option "Xkb..."` value from the seat's section.
+    fn get(&self, option: &str) -> Option<String> {
+        let contents = exec::read_to_string(&self.path()).ok()?;
+        let quoted = format!("\"{}\"", option);
+        contents.lines().find_map(|line| {
+            let rest = line.trim().strip_prefix("Option")?.trim().strip_prefix(&quoted)?;
+            Some(rest.trim().trim_matches('"').to_string())
+        })
+    }
+
+    fn write(&self, layout: &str, variant: &str, options: &str) -> Result<()> {
+        let mut contents = format!(
+            "# Written by levocale for {seat}\n\
+             Section \"InputClass\"\n\
+             \tIdentifier \"levocale keyboard {seat}\"\n\
+             \tMatchIsKeyboard \"on\"\n\
+             \tMatchSeat \"{seat}\"\n\
+             \tOption \"XkbLayout\" \"{layout}\"\n",
+            seat = self.seat,
+            layout = layout,
+        );
+        if !variant.is_empty() {
+            contents.push_str(&format!("\tOption \"XkbVariant\" \"{}\"\n", variant));
+        }
+        if !options.is_empty() {
+            contents.push_str(&format!("\tOption \"XkbOptions\" \"{}\"\n", options));
+        }
+        contents.push_str("EndSection\n");
+        let (old_layout, old_options) = (self.current_layout(), self.options().unwrap_or_default());
+        fsutil::write_as_root(&self.path(), &contents)?;
+        let new_layout = if variant.is_empty() { layout.to_string() } else { format!("{}({})", layout, variant) };
+        if old_layout.as_deref() != Some(new_layout.as_str()) {
+            audit::record(&format!("{} layout", self.seat), old_layout.as_deref(), &new_layout, self.name());
+        }
+        if old_options != options {
+            audit::record(&format!("{} XKB options", self.seat), Some(&old_options), options, self.name());
+        }
+        Ok(())
+    }
+}
+
+impl KeyboardBackend for XorgSeat {
+    fn name(&self) -> &'static str {
+        "Xorg"
+    }
+
+    fn current_layout(&self) -> Option<String> {
+        let layout = self.get("XkbLayout")?;
+        match self.get("XkbVariant") {
+            Some(variant) if !variant.is_empty() => Some(format!("{}({})", layout, variant)),
+            _ => Some(layout),
+        }
+    }
+
+    fn set_layout(&self, layout: &str) -> Result<()> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        self.write(layout, variant.unwrap_or(""), &self.options().unwrap_or_default())
+    }
+
+    fn options(&self) -> Option<String> {
+        Some(self.get("XkbOptions").unwrap_or_default())
+    }
+
+    fn set_options(&self, options: &str) -> Result<()> {
+        let layout = self.get("XkbLayout").unwrap_or_else(|| "us".to_string());
+        self.write(&layout, &self.get("XkbVariant").unwrap_or_default(), options)
+    }
+
+    fn persist(&self) -> Result<()> {
+        // The file is all there is
+        Ok(())
+    }
+
+    fn needs_restart(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,224 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::config::Mode;
+use crate::locale_info;
+
+pub const USAGE: &str = "\
+Usage: levocale [OPTIONS] [COMMAND]
+
+Commands:
+  apply <profile.toml>  Apply the layout, options, LANG and LC_* values
+                        declared in a profile, changing only what differs
+  set <NAME=value>...   Set LANG and LC_* variables together, in a single
+                        call to the locale backend, e.g.
+                        set LANG=en_US.UTF-8 LC_TIME=en_DK.UTF-8
+  import --from <path>  Merge in the profiles, favorites and device rules of
+                        another levocale config, e.g. another user's or
+                        one in a dotfiles checkout or git URL
+  export --shell        Print the commands that reproduce the current
+                        keyboard and locale settings
+  profile list          List the saved profiles
+  profile save <name>   Save the current settings as a named profile
+  profile use <name>    Apply a saved profile
+  snapshot <name>       Save the layout, options, LANG and LC_* values in
+                        effect as a profile, which profile use restores,
+                        unsetting the LC_* values set since
+  keymap dump <file>    Write the compiled keymap in use to a .xkb file
+  keymap load <file>    Load a (hand-edited) .xkb keymap in place of the
+                        layout and options, like xkbcomp
+  keymap clear          Go back to the layout and options
+  sync <locale>         Set LANG to a locale and the keyboard layout of its
+                        language on the compositor, X11 and the console,
+                        reporting each
+  daemon                Keep running and react to compositor events as set
+                        up in the [daemon] config table, and push layout
+                        and locale changes to clients of its socket
+  listen [--json]       Print the layout and locale each time either
+                        changes, e.g. for eww's deflisten
+  watch [--format <f>]  Print a timestamped line for every layout or locale
+                        change; the format may use {time}, {changed},
+                        {layout} and {locale}
+
+Options:
+  --host <user@server>  Manage the locale of a remote machine over ssh
+  --seat <seat>         Send keyboard changes to another logind seat,
+                        e.g. seat1, through its Xorg config
+  --backend <name>      Use this keyboard backend instead of detecting one:
+                        hyprland, sway, labwc, weston, cosmic, x11,
+                        console, vt or macos
+  --plain               Numbered menus read line by line instead of the
+                        full-screen interface, e.g. for screen readers
+  --inline[=<lines>]    Draw a compact interface below the prompt instead
+                        of taking over the screen (12 lines by default)
+  --read-only           Show the settings and menus but refuse to change
+                        anything, e.g. on a shared display
+  --user                Only change the session and the user's own files,
+                        e.g. LANG in ~/.config/locale.conf, never using sudo
+  --admin               Allow system-wide changes through sudo (the default
+                        unless the config sets mode = \"user\")
+  -h, --help            Show this help
+  -V, --version         Show the version";
+
+const DEFAULT_INLINE_HEIGHT: u16 = 12;
+/// A status line, a bordered entry and the message line.
+const MIN_INLINE_HEIGHT: u16 = 6;
+
+/// Work to do instead of starting the TUI.
+pub enum Command {
+    Apply(PathBuf),
+    /// LANG and LC_* assignments, in the order given.
+    Set(Vec<(String, String)>),
+    /// Where to import from: a path or a git URL, once --from gives it.
+    Import(String),
+    ExportShell,
+    ProfileList,
+    ProfileSave(String),
+    ProfileUse(String),
+    Snapshot(String),
+    KeymapDump(PathBuf),
+    KeymapLoad(PathBuf),
+    KeymapClear,
+    Sync(String),
+    Daemon,
+    Listen { json: bool },
+    Watch { format: Option<String> },
+}
+
+#[derive(Default)]
+pub struct Args {
+    /// ssh destination to run system commands on instead of this machine.
+    pub host: Option<String>,
+    /// logind seat whose keyboards to configure instead of our own.
+    pub seat: Option<String>,
+    /// Keyboard backend to use instead of detecting one.
+    pub backend: Option<String>,
+    /// Line-based menus instead of the TUI.
+    pub plain: bool,
+    /// Height of the inline viewport to draw in, instead of the alternate
+    /// screen.
+    pub inline: Option<u16>,
+    /// Menus that look but don't touch.
+    pub read_only: bool,
+    /// --user or --admin, over the config's `mode`.
+    pub mode: Option<Mode>,
+    pub command: Option<Command>,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Args {
+    pub fn parse() -> Result<Args> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Args> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            // Accept both "--flag value" and "--flag=value"
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = |name: &str| match inline_value.clone().or_else(|| args.next()) {
+                Some(value) if !value.is_empty() => Ok(value),
+                _ => bail!("{} needs a value", name),
+            };
+            match flag.as_str() {
+                "--host" => parsed.host = Some(value("--host")?),
+                "--seat" => parsed.seat = Some(value("--seat")?),
+                "--backend" => parsed.backend = Some(value("--backend")?),
+                "--plain" => parsed.plain = true,
+                "--read-only" => parsed.read_only = true,
+                "--user" => parsed.mode = Some(Mode::User),
+                "--admin" => parsed.mode = Some(Mode::Admin),
+                // The height is optional, so it only comes attached with "="
+                "--inline" => {
+                    let lines = match &inline_value {
+                        Some(lines) => lines.parse().map_err(|_| anyhow!("--inline needs a number of lines"))?,
+                        None => DEFAULT_INLINE_HEIGHT,
+                    };
+                    if lines < MIN_INLINE_HEIGHT {
+                        bail!("--inline needs at least {} lines", MIN_INLINE_HEIGHT);
+                    }
+                    parsed.inline = Some(lines);
+                }
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                "apply" if parsed.command.is_none() => {
+                    parsed.command = Some(Command::Apply(PathBuf::from(value("apply")?)));
+                }
+                "set" if parsed.command.is_none() => parsed.command = Some(Command::Set(Vec::new())),
+                assignment if matches!(parsed.command, Some(Command::Set(_))) && assignment.contains('=') => {
+                    let (name, value) = assignment.split_once('=').unwrap_or_default();
+                    if name != "LANG" && !locale_info::LC_CATEGORIES.contains(&name) {
+                        bail!("Unknown variable {} (set takes LANG and the LC_* categories)", name);
+                    }
+                    if value.is_empty() {
+                        bail!("{} needs a value", name);
+                    }
+                    if let Some(Command::Set(variables)) = &mut parsed.command {
+                        if variables.iter().any(|(existing, _)| existing == name) {
+                            bail!("{} is set twice", name);
+                        }
+                        variables.push((name.to_string(), value.to_string()));
+                    }
+                }
+                "import" if parsed.command.is_none() => parsed.command = Some(Command::Import(String::new())),
+                "--from" if matches!(parsed.command, Some(Command::Import(_))) => {
+                    parsed.command = Some(Command::Import(value("--from")?));
+                }
+                "export" if parsed.command.is_none() => {
+                    // Room for other formats later; the shell is the only one so far
+                    match args.next().as_deref() {
+                        Some("--shell") => parsed.command = Some(Command::ExportShell),
+                        Some(other) => bail!("Unknown export format: {}", other),
+                        None => bail!("export needs a format: --shell"),
+                    }
+                }
+                "profile" if parsed.command.is_none() => {
+                    parsed.command = Some(match value("profile")?.as_str() {
+                        "list" => Command::ProfileList,
+                        "save" => Command::ProfileSave(value("profile save")?),
+                        "use" => Command::ProfileUse(value("profile use")?),
+                        other => bail!("Unknown profile command: {}", other),
+                    });
+                }
+                "snapshot" if parsed.command.is_none() => {
+                    parsed.command = Some(Command::Snapshot(value("snapshot")?));
+                }
+                "keymap" if parsed.command.is_none() => {
+                    parsed.command = Some(match value("keymap")?.as_str() {
+                        "dump" => Command::KeymapDump(PathBuf::from(value("keymap dump")?)),
+                        "load" => Command::KeymapLoad(PathBuf::from(value("keymap load")?)),
+                        "clear" => Command::KeymapClear,
+                        other => bail!("Unknown keymap command: {}", other),
+                    });
+                }
+                "sync" if parsed.command.is_none() => parsed.command = Some(Command::Sync(value("sync")?)),
+                "daemon" if parsed.command.is_none() => parsed.command = Some(Command::Daemon),
+                "listen" if parsed.command.is_none() => parsed.command = Some(Command::Listen { json: false }),
+                "--json" if matches!(parsed.command, Some(Command::Listen { .. })) => {
+                    parsed.command = Some(Command::Listen { json: true });
+                }
+                "watch" if parsed.command.is_none() => parsed.command = Some(Command::Watch { format: None }),
+                "--format" if matches!(parsed.command, Some(Command::Watch { .. })) => {
+                    parsed.command = Some(Command::Watch { format: Some(value("--format")?) });
+                }
+                other => bail!("Unknown argument: {}", other),
+            }
+        }
+        if matches!(&parsed.command, Some(Command::Set(variables)) if variables.is_empty()) {
+            bail!("set needs at least one assignment, e.g. LANG=en_US.UTF-8");
+        }
+        if matches!(&parsed.command, Some(Command::Import(from)) if from.is_empty()) {
+            bail!("import needs --from <path> or a git URL");
+        }
+        // Commands exist to change things, so there'd be nothing left to run
+        if parsed.read_only && parsed.command.is_some() {
+            bail!("--read-only is for the menus and can't be combined with a command");
+        }
+        Ok(parsed)
+    }
+}
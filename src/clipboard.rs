@@ -0,0 +1,73 @@
+//! Copying text to the system clipboard from inside the terminal.
+
+use std::io::{self, Write};
+use std::process::Stdio;
+
+use anyhow::{Result, bail};
+
+use crate::exec;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let group = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                out.push(BASE64[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Asks the terminal to set the clipboard with an OSC 52 sequence, which
+/// also works over ssh. tmux only forwards it when wrapped in a passthrough.
+fn osc52(text: &str) -> io::Result<()> {
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    let sequence = if std::env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    };
+    let mut stdout = io::stdout();
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()
+}
+
+/// Pipes `text` into a clipboard tool; true when one took it.
+fn pipe_to(program: &str, args: &[&str], text: &str) -> bool {
+    // The tools fork to keep serving the selection, so keep them off the
+    // terminal we're drawing on
+    let Ok(mut child) = exec::local(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait().is_ok_and(|status| status.success())
+}
+
+/// Copies `text` through OSC 52, and through wl-copy or xclip as well where
+/// a display server is around, since not every terminal honours OSC 52.
+pub fn copy(text: &str) -> Result<()> {
+    let osc52_result = osc52(text);
+    let piped = (std::env::var_os("WAYLAND_DISPLAY").is_some() && pipe_to("wl-copy", &[], text))
+        || (std::env::var_os("DISPLAY").is_some() && pipe_to("xclip", &["-selection", "clipboard"], text));
+    if let Err(e) = osc52_result
+        && !piped
+    {
+        bail!("Failed to copy to the clipboard: {}", e);
+    }
+    Ok(())
+}
@@ -0,0 +1,549 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::daemon::RememberBy;
+use crate::{backend, fsutil, glob, keys, theme, translations};
+use crate::notifications::Urgency;
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Entries bound to the 1-9 quick-select keys, written as
+    /// "layout:<code>", "locale:<code>" or "profile:<name>".
+    pub favorites: Vec<String>,
+    /// Layouts gathered under names of their own, each shown as a section
+    /// above the full list, e.g. `[[groups]]` with `name = "Work"` and
+    /// `layouts = ["us", "de(nodeadkeys)"]`.
+    pub groups: Vec<LayoutGroup>,
+    /// Key the compose toggle binds, as in XKB's "compose:<key>" options.
+    pub compose_key: String,
+    /// Directory of compiled .xkb keymaps to offer as keymap files.
+    /// Defaults to `keymaps/` next to this config file.
+    pub keymap_dir: Option<PathBuf>,
+    /// Also write keyboard changes to the compositor's config file so they
+    /// survive a restart, instead of only changing the running session.
+    pub persist_keyboard: bool,
+    /// More keyboard backends to apply layout and option changes to, after
+    /// the one in use, e.g. `["x11", "console"]` so XWayland apps and the
+    /// Linux console follow Hyprland. Any of the names `backend` takes.
+    pub mirror_keyboard: Vec<String>,
+    /// Warn when the compositor, X11 and the console are on different
+    /// layouts, or LANG's language has a layout none of them uses. Off by
+    /// default, since e.g. an English LANG with a local layout is common.
+    pub mismatch_warning: bool,
+    /// Also record locale changes as the user's language in AccountsService,
+    /// which GDM and GNOME use in place of the system locale.
+    pub accountsservice: bool,
+    /// Copy keyboard changes to the display manager's greeter config
+    /// (greetd's sway config or SDDM's Xsetup) so the login screen matches.
+    pub sync_greeter: bool,
+    /// On FreeBSD, a class in /etc/login.conf to set the language for as
+    /// well as ~/.login_conf (the database is rebuilt with cap_mkdb).
+    pub login_class: Option<String>,
+    /// Color preset: "default", "high-contrast", "deuteranopia" (blue and
+    /// orange in place of green and red), "terminal" (only the terminal's
+    /// 16-color palette, to follow pywal or base16 schemes) or
+    /// "monochrome". T cycles through them while running.
+    pub theme: String,
+    /// Order of the layout, input method, locale and profile lists:
+    /// "name", "code", "native" (by the language's own name where known) or
+    /// "recent" (recently applied first). s cycles through them and saves
+    /// the choice here.
+    pub sort: SortOrder,
+    /// Keyboard backend to use instead of detecting one from the session:
+    /// "hyprland", "sway", "labwc", "weston", "cosmic", "x11", "console"
+    /// (the Linux console's keymap), "vt" or "macos". --backend overrides
+    /// it.
+    pub backend: Option<String>,
+    /// "admin" to allow system-wide changes through sudo, or "user" to
+    /// stick to the session and the user's own files, e.g. LANG in
+    /// ~/.config/locale.conf. --user and --admin override it.
+    pub mode: Mode,
+    pub notifications: Notifications,
+    pub keys: Keys,
+    pub hooks: Hooks,
+    pub commands: Commands,
+    pub filter: Filter,
+    pub daemon: Daemon,
+    /// Settings for particular keyboards, by device name pattern where `*`
+    /// matches anything, e.g. `[devices."*french*"]`. `hyprctl devices` or
+    /// `swaymsg -t get_inputs` list the names. When several patterns match,
+    /// the alphabetically first wins, whatever the order in the file.
+    pub devices: BTreeMap<String, DeviceRule>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Name,
+    Code,
+    Native,
+    Recent,
+}
+
+impl SortOrder {
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Name => "name",
+            SortOrder::Code => "code",
+            SortOrder::Native => "native",
+            SortOrder::Recent => "recent",
+        }
+    }
+
+    pub fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Name => SortOrder::Code,
+            SortOrder::Code => SortOrder::Native,
+            SortOrder::Native => SortOrder::Recent,
+            SortOrder::Recent => SortOrder::Name,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    User,
+    #[default]
+    Admin,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::User => "user",
+            Mode::Admin => "admin",
+        }
+    }
+}
+
+/// The `[notifications]` table.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notifications {
+    /// Turns desktop notifications off altogether.
+    pub enabled: bool,
+    /// How long a notification stays up, in milliseconds; -1 leaves it to
+    /// the notification daemon.
+    pub timeout_ms: i32,
+    /// "low", "normal" or "critical".
+    pub urgency: Urgency,
+    pub failure_urgency: Urgency,
+    /// Icon name from the icon theme, or an absolute path to an image.
+    pub icon: String,
+    /// Whether changes that worked are announced.
+    pub successes: bool,
+    /// Whether errors are announced.
+    pub failures: bool,
+    /// Also show every message briefly in a corner of the TUI, independent
+    /// of the settings above.
+    pub toasts: bool,
+    /// The `[notifications.messages]` table: the wording of announcements
+    /// by message id, replacing the built-in English, German, French or
+    /// Spanish. {code}, {name}, {backend} and {detail} are filled in, e.g.
+    /// `locale_set = "Now speaking {name} ({code}) via {backend}"`.
+    pub messages: BTreeMap<String, String>,
+}
+
+/// The `[keys]` table: the keys for each menu action, e.g.
+/// `up = ["up", "k"]`. Names are single characters, arrows, "enter",
+/// "esc", "space", "f1".."f12" and the like, optionally after "ctrl+" or
+/// "alt+".
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+    /// Applies the highlighted entry, or opens its section.
+    pub select: Vec<String>,
+    /// Opens or closes the highlighted section.
+    pub toggle: Vec<String>,
+    /// Jumps to entries matching what's typed.
+    pub search: Vec<String>,
+    pub refresh: Vec<String>,
+    /// Runs the last change that failed again, exactly as it was tried.
+    pub retry: Vec<String>,
+    pub quit: Vec<String>,
+}
+
+/// The `[hooks]` table: shell commands run around a change, with
+/// LEVOCALE_LAYOUT, LEVOCALE_OPTIONS and LEVOCALE_LOCALE set to what's in
+/// effect at the time and LEVOCALE_CHANGED to "layout" or "locale", e.g.
+/// `after_locale = ["fcitx5 -r -d"]`.
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Before the layout, options or keymap file change, with the layout,
+    /// option or file in LEVOCALE_TO. A hook that exits non-zero stops the
+    /// change, and the last line it printed is shown as the reason.
+    pub before_layout: Vec<String>,
+    /// The same for LANG and LC_* categories, with the locale in
+    /// LEVOCALE_TO.
+    pub before_locale: Vec<String>,
+    /// After the layout, variant, options or keymap file changed.
+    pub after_layout: Vec<String>,
+    /// After LANG or an LC_* category changed.
+    pub after_locale: Vec<String>,
+}
+
+/// The `[commands]` table: how to run the programs the backends call, for
+/// wrappers, unusual install paths or containers.
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Commands {
+    /// Put in front of every command, e.g. `["flatpak-spawn", "--host"]`
+    /// to reach the host's hyprctl and localectl from inside a Flatpak.
+    /// With `--host` it goes in front of ssh.
+    pub prefix: Vec<String>,
+    /// What to run in place of a program, by its usual name, e.g.
+    /// `hyprctl = ["/opt/hypr/bin/hyprctl"]` or
+    /// `localectl = ["toolbox", "run", "localectl"]`. Its arguments follow.
+    pub programs: BTreeMap<String, Vec<String>>,
+}
+
+/// One of the `[[groups]]`.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutGroup {
+    pub name: String,
+    /// Layout codes with any variant folded in, e.g. "de(nodeadkeys)".
+    pub layouts: Vec<String>,
+}
+
+/// The `[filter]` table: entries to leave out of the menu. Patterns match
+/// an entry's code, e.g. "*_ZA*" or "*(dvorak*", or its kind and code
+/// together, e.g. "locale:*" or "option:caps:*", with `*` matching
+/// anything. It only trims what the menu shows: the CLI, profiles and the
+/// daemon can still set a filtered entry.
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    /// Entries to hide.
+    pub exclude: Vec<String>,
+    /// When set, the only entries shown, e.g. for a kiosk offering a
+    /// fixed few layouts. `exclude` still applies to them.
+    pub only: Vec<String>,
+}
+
+impl Filter {
+    /// Whether an entry with `code`, written `key` with its kind (as in
+    /// favorites), is shown.
+    pub fn allows(&self, code: &str, key: &str) -> bool {
+        let matches = |pattern: &String| {
+            glob::matches(pattern, code) || glob::matches(pattern, key)
+        };
+        (self.only.is_empty() || self.only.iter().any(matches)) && !self.exclude.iter().any(matches)
+    }
+}
+
+/// The `[daemon]` table, read by `levocale daemon`.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Daemon {
+    /// Switch back to the layout a window last used whenever it gets focus
+    /// (Hyprland only).
+    pub per_window_layout: bool,
+    /// "window" remembers each window on its own; "class" shares one layout
+    /// among all windows of an application.
+    pub remember_by: RememberBy,
+    /// Window classes to remember layouts for, where `*` matches anything.
+    /// Empty means every class.
+    pub include: Vec<String>,
+    /// Window classes to leave alone even if `include` matches them.
+    pub exclude: Vec<String>,
+    /// Put the layout and options back after the machine wakes from
+    /// suspend, and the `[devices]` settings with them, for keyboards that
+    /// come back with the compositor's defaults.
+    pub restore_after_sleep: bool,
+    /// Show the layout in the system tray, with a menu to switch layouts
+    /// and open the TUI.
+    pub tray: bool,
+    /// The terminal the tray opens the TUI in, followed by levocale's path,
+    /// e.g. `["foot"]` or `["kitty", "-e"]`. Defaults to `$TERMINAL -e`,
+    /// or xdg-terminal-exec when TERMINAL is unset.
+    pub terminal: Vec<String>,
+}
+
+/// One `[devices]` entry, given to matching keyboards by the daemon as soon
+/// as they're plugged in. Unset fields leave the shared setting alone.
+#[derive(Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceRule {
+    pub layout: Option<String>,
+    pub variant: Option<String>,
+    /// Comma-separated XKB options, e.g. "ctrl:nocaps".
+    pub options: Option<String>,
+}
+
+impl DeviceRule {
+    /// The layout with the variant folded in, e.g. "de(nodeadkeys)".
+    pub fn layout_code(&self) -> Option<String> {
+        let layout = self.layout.as_deref().filter(|layout| !layout.is_empty())?;
+        Some(match self.variant.as_deref().filter(|variant| !variant.is_empty()) {
+            Some(variant) => format!("{}({})", layout, variant),
+            None => layout.to_string(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layout_code().is_none() && self.options.is_none()
+    }
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Self {
+            per_window_layout: false,
+            remember_by: RememberBy::Window,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            restore_after_sleep: false,
+            tray: false,
+            terminal: Vec::new(),
+        }
+    }
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        Self {
+            up: keys(&["up"]),
+            down: keys(&["down"]),
+            select: keys(&["enter", "right"]),
+            toggle: keys(&["left"]),
+            search: keys(&["/"]),
+            refresh: keys(&["r"]),
+            retry: keys(&["R"]),
+            quit: keys(&["q", "esc"]),
+        }
+    }
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_ms: 2000,
+            urgency: Urgency::Normal,
+            failure_urgency: Urgency::Critical,
+            icon: "preferences-desktop-locale".to_string(),
+            successes: true,
+            failures: true,
+            toasts: true,
+            messages: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            favorites: Vec::new(),
+            groups: Vec::new(),
+            compose_key: "ralt".to_string(),
+            keymap_dir: None,
+            persist_keyboard: false,
+            mirror_keyboard: Vec::new(),
+            mismatch_warning: false,
+            accountsservice: false,
+            sync_greeter: false,
+            login_class: None,
+            theme: "default".to_string(),
+            sort: SortOrder::Name,
+            backend: None,
+            mode: Mode::default(),
+            notifications: Notifications::default(),
+            keys: Keys::default(),
+            hooks: Hooks::default(),
+            commands: Commands::default(),
+            filter: Filter::default(),
+            daemon: Daemon::default(),
+            devices: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn xdg_config_home() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir().join(".config"))
+}
+
+pub fn xdg_state_home() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir().join(".local").join("state"))
+}
+
+pub fn config_dir() -> PathBuf {
+    xdg_config_home().join("levocale")
+}
+
+pub fn home_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("/"))
+}
+
+/// Expands a leading `~/`, which TOML users reasonably expect to work.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+/// When config.toml last changed, or None while there isn't one.
+pub fn modified() -> Option<SystemTime> {
+    fs::metadata(config_path()).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl Config {
+    /// Loads the user's config, treating a missing file as all defaults.
+    pub fn load() -> Result<Config> {
+        let path = config_path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let config: Config =
+            toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+        keys::Bindings::new(&config.keys).with_context(|| format!("Invalid key bindings in {}", path.display()))?;
+        if !theme::names().contains(&config.theme.as_str()) {
+            bail!("{}: unknown theme {:?} (there are {})", path.display(), config.theme, theme::names().join(", "));
+        }
+        let mut names = HashSet::new();
+        let unnamed = |group: &&LayoutGroup| group.name.trim().is_empty() || !names.insert(group.name.clone());
+        if let Some(group) = config.groups.iter().find(unnamed) {
+            bail!("{}: every group needs a name of its own, but {:?} isn't", path.display(), group.name);
+        }
+        let unknown = |id: &&String| !translations::ids().any(|known| known == *id);
+        if let Some(id) = config.notifications.messages.keys().find(unknown) {
+            bail!("{}: unknown message {:?} in [notifications.messages]", path.display(), id);
+        }
+        let backends = config.backend.iter().chain(&config.mirror_keyboard);
+        if let Some(name) = backends.into_iter().find(|name| !backend::KEYBOARD_BACKENDS.contains(&name.as_str())) {
+            bail!(
+                "{}: unknown backend {:?} (there are {})",
+                path.display(),
+                name,
+                backend::KEYBOARD_BACKENDS.join(", ")
+            );
+        }
+        Ok(config)
+    }
+
+    /// The rule for a keyboard: one written for its exact name wins over
+    /// patterns, which are tried in alphabetical order, since the table
+    /// doesn't keep the order they were written in.
+    pub fn device_rule(&self, device: &str) -> Option<(&str, &DeviceRule)> {
+        if let Some((name, rule)) = self.devices.get_key_value(device) {
+            return Some((name, rule));
+        }
+        self.devices
+            .iter()
+            .find(|(pattern, _)| glob::matches(pattern, device))
+            .map(|(pattern, rule)| (pattern.as_str(), rule))
+    }
+
+    pub fn keymap_dir(&self) -> PathBuf {
+        match &self.keymap_dir {
+            Some(dir) => expand_home(dir),
+            None => config_dir().join("keymaps"),
+        }
+    }
+}
+
+/// Changes config.toml through `change`, leaving the rest of the file as the
+/// user wrote it.
+fn edit(change: impl FnOnce(&mut toml_edit::DocumentMut, &Path) -> Result<()>) -> Result<()> {
+    let path = config_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut document: toml_edit::DocumentMut =
+        contents.parse().with_context(|| format!("Failed to parse {}", path.display()))?;
+    change(&mut document, &path)?;
+    fsutil::write_atomic(&path, &document.to_string())
+}
+
+pub fn save_sort(order: SortOrder) -> Result<()> {
+    edit(|document, _| {
+        document["sort"] = toml_edit::value(order.name());
+        Ok(())
+    })
+}
+
+/// Writes the rule for one device into config.toml, or drops it when `rule`
+/// is None.
+pub fn save_device_rule(device: &str, rule: Option<&DeviceRule>) -> Result<()> {
+    edit(|document, path| set_device_rule(document, path, device, rule))
+}
+
+fn set_device_rule(
+    document: &mut toml_edit::DocumentMut,
+    path: &Path,
+    device: &str,
+    rule: Option<&DeviceRule>,
+) -> Result<()> {
+    let devices = document
+        .entry("devices")
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
+        .with_context(|| format!("devices in {} isn't a table", path.display()))?;
+    match rule {
+        Some(rule) => {
+            let mut table = toml_edit::Table::new();
+            let fields = [("layout", &rule.layout), ("variant", &rule.variant), ("options", &rule.options)];
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    table.insert(key, toml_edit::value(value.as_str()));
+                }
+            }
+            devices.insert(device, toml_edit::Item::Table(table));
+        }
+        None => {
+            devices.remove(device);
+        }
+    }
+    Ok(())
+}
+
+/// Adds imported `favorites` after the existing ones and new device rules,
+/// in one write so config.toml.bak keeps the file from before the import.
+pub fn add_imported(favorites: &[String], devices: &[(String, DeviceRule)]) -> Result<()> {
+    edit(|document, path| {
+        if !favorites.is_empty() {
+            let list = document
+                .entry("favorites")
+                .or_insert_with(|| toml_edit::value(toml_edit::Array::new()))
+                .as_array_mut()
+                .with_context(|| format!("favorites in {} isn't a list", path.display()))?;
+            for favorite in favorites {
+                list.push(favorite.as_str());
+            }
+        }
+        for (device, rule) in devices {
+            set_device_rule(document, path, device, Some(rule))?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,513 @@
+//! `levocale daemon`: stays running and adjusts the keyboard in response to
+//! compositor events, and tells clients of its socket about layout and
+//! locale changes. Each event source runs on its own thread and feeds one
+//! loop, so the reactions never race each other.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::backend::{self, KeyboardBackend};
+use crate::config::{self, Config, DeviceRule};
+use crate::{exec, glob, ipc, log};
+use crate::listen::{self, State};
+#[cfg(target_os = "linux")]
+use crate::tray::{self, Tray};
+
+/// What `per_window_layout` keys its memory on.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RememberBy {
+    Window,
+    Class,
+}
+
+enum Event {
+    /// Hyprland focused a window; the class is empty for the desktop.
+    WindowFocused { class: String, address: String },
+    WindowClosed { address: String },
+    /// An input device was added, so there may be a new keyboard.
+    DevicesChanged,
+    /// logind announced the machine is about to sleep (true) or has just
+    /// woken up (false).
+    PrepareForSleep(bool),
+    /// A client asked on the control socket to be told about changes.
+    Subscribed(UnixStream),
+    /// The layout or locale may have changed.
+    StateChanged,
+    /// Something was picked from the tray icon.
+    #[cfg(target_os = "linux")]
+    Tray(tray::Action),
+}
+
+/// Remembers the layout each window (or application) was last using.
+struct LayoutMemory {
+    settings: config::Daemon,
+    layouts: HashMap<String, String>,
+    /// Key of the tracked window that has focus.
+    focused: Option<String>,
+}
+
+impl LayoutMemory {
+    fn tracks(&self, class: &str) -> bool {
+        let matches = |pattern: &String| glob::matches(pattern, class);
+        let included = self.settings.include.is_empty() || self.settings.include.iter().any(matches);
+        included && !self.settings.exclude.iter().any(matches)
+    }
+
+    fn focus(&mut self, keyboard: &dyn KeyboardBackend, class: &str, address: &str) -> Result<()> {
+        let current = keyboard.current_layout();
+        // Whatever the window being left ended up with is what it gets back
+        if let Some(previous) = self.focused.take()
+            && let Some(layout) = &current
+        {
+            self.layouts.insert(previous, layout.clone());
+        }
+        if class.is_empty() || !self.tracks(class) {
+            return Ok(());
+        }
+        let key = match self.settings.remember_by {
+            RememberBy::Window => address.to_string(),
+            RememberBy::Class => class.to_string(),
+        };
+        // Windows seen for the first time keep the layout already active
+        if let Some(layout) = self.layouts.get(&key)
+            && current.as_ref() != Some(layout)
+        {
+            keyboard.set_layout(layout).with_context(|| format!("Failed to restore {} for {}", layout, class))?;
+        }
+        self.focused = Some(key);
+        Ok(())
+    }
+
+    fn close(&mut self, address: &str) {
+        if self.settings.remember_by == RememberBy::Window {
+            self.layouts.remove(address);
+            if self.focused.as_deref() == Some(address) {
+                self.focused = None;
+            }
+        }
+    }
+}
+
+/// Gives keyboards their `[devices]` settings as they appear.
+struct DeviceRules {
+    config: Config,
+    /// Keyboards seen so far, so each one is only set up once.
+    known: HashSet<String>,
+}
+
+impl DeviceRules {
+    fn refresh(&mut self, keyboard: &dyn KeyboardBackend) {
+        let attached: HashSet<String> = keyboard.keyboards().into_iter().collect();
+        if attached.difference(&self.known).next().is_some() {
+            // Rules may have been edited in the TUI since the daemon started
+            match Config::load() {
+                Ok(config) => {
+                    exec::configure(&config);
+                    self.config = config;
+                }
+                Err(e) => log::error(&format!("{:#}", e)),
+            }
+        }
+        for device in attached.difference(&self.known) {
+            let Some((_, rule)) = self.config.device_rule(device) else {
+                continue;
+            };
+            match apply_device_rule(keyboard, device, rule) {
+                Ok(()) => log::info(&format!("{}: {}", device, describe_rule(rule))),
+                Err(e) => log::error(&format!("{}: {:#}", device, e)),
+            }
+        }
+        self.known = attached;
+    }
+}
+
+pub fn apply_device_rule(keyboard: &dyn KeyboardBackend, device: &str, rule: &DeviceRule) -> Result<()> {
+    keyboard.set_device_keyboard(device, rule.layout_code().as_deref(), rule.options.as_deref())
+}
+
+/// e.g. "de(nodeadkeys), ctrl:nocaps"
+pub fn describe_rule(rule: &DeviceRule) -> String {
+    let parts: Vec<String> = rule.layout_code().into_iter().chain(rule.options.clone()).collect();
+    parts.join(", ")
+}
+
+/// Reports input devices being plugged in, through udev when udevadm is
+/// available and by asking every few seconds otherwise.
+fn watch_devices(events: mpsc::Sender<Event>) {
+    let monitor = exec::local("udevadm")
+        .args(["monitor", "--udev", "--subsystem-match=input"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    thread::spawn(move || {
+        let Ok(mut monitor) = monitor else {
+            while events.send(Event::DevicesChanged).is_ok() {
+                thread::sleep(Duration::from_secs(3));
+            }
+            return;
+        };
+        let Some(stdout) = monitor.stdout.take() else {
+            return;
+        };
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            // e.g. "UDEV  [5123.4417] add  /devices/.../input/input27 (input)"
+            if line.split_whitespace().nth(2) != Some("add") {
+                continue;
+            }
+            // Give the compositor a moment to pick the device up first
+            thread::sleep(Duration::from_millis(500));
+            if events.send(Event::DevicesChanged).is_err() {
+                break;
+            }
+        }
+        let _ = monitor.kill();
+    });
+}
+
+/// Forwards logind's PrepareForSleep signals. gdbus only adds a match rule,
+/// which unlike `busctl monitor` doesn't need root on the system bus.
+fn watch_sleep(events: mpsc::Sender<Event>) -> Result<()> {
+    let mut monitor = exec::local("gdbus")
+        .args(["monitor", "--system", "--dest", "org.freedesktop.login1", "--object-path", "/org/freedesktop/login1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("gdbus not found (it comes with GLib)")?;
+    let stdout = monitor.stdout.take().context("gdbus has no output")?;
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            // /org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)
+            let Some((_, arguments)) = line.split_once(".PrepareForSleep (") else {
+                continue;
+            };
+            if events.send(Event::PrepareForSleep(arguments.starts_with("true"))).is_err() {
+                break;
+            }
+        }
+        let _ = monitor.kill();
+    });
+    Ok(())
+}
+
+/// The keyboard settings from before a suspend, to put back on wake.
+#[derive(Default)]
+struct SleepState {
+    layout: Option<String>,
+    options: Option<String>,
+}
+
+impl SleepState {
+    fn save(&mut self, keyboard: &dyn KeyboardBackend) {
+        self.layout = keyboard.current_layout();
+        self.options = keyboard.options();
+    }
+
+    /// Sets everything again even if the backend reports it unchanged: a
+    /// keyboard that re-enumerated can be back on the defaults while the
+    /// setting still reads the same.
+    fn restore(&self, keyboard: &dyn KeyboardBackend) -> Result<()> {
+        keyboard
+            .set_keyboard(self.layout.as_deref(), self.options.as_deref())
+            .with_context(|| format!("Failed to restore {} after sleep", self.layout.as_deref().unwrap_or("the options")))?;
+        log::info(&format!("Restored {} after sleep", self.layout.as_deref().unwrap_or("the options")));
+        Ok(())
+    }
+}
+
+/// How long a client gets to send its request, and to take each line we
+/// write, before it's given up on.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Answers clients on the control socket: `state` right away, while
+/// subscribers are handed to the event loop to be kept up to date. Each
+/// client is read on a thread of its own, so one that says nothing holds
+/// up no one else.
+fn serve(listener: UnixListener, events: mpsc::Sender<Event>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let events = events.clone();
+            thread::spawn(move || answer(stream, &events));
+        }
+    });
+}
+
+fn answer(mut stream: UnixStream, events: &mpsc::Sender<Event>) {
+    // The write timeout stays with subscribers, for Subscribers::update
+    let timeouts = stream.set_read_timeout(Some(CLIENT_TIMEOUT)).and(stream.set_write_timeout(Some(CLIENT_TIMEOUT)));
+    if timeouts.is_err() {
+        return;
+    }
+    match ipc::read_request(&stream).as_deref() {
+        Some("subscribe") => {
+            let _ = events.send(Event::Subscribed(stream));
+        }
+        Some("state") => {
+            let _ = writeln!(stream, "{}", State::current().json());
+        }
+        Some(other) => {
+            let _ = writeln!(stream, "error: unknown request {:?}", other);
+        }
+        None => {}
+    }
+}
+
+/// Forwards the layout and locale watchers' ticks as events.
+fn watch_state(events: mpsc::Sender<Event>) {
+    let (sender, ticks) = mpsc::channel();
+    listen::watch(sender);
+    thread::spawn(move || {
+        for () in ticks {
+            if events.send(Event::StateChanged).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Puts the layout in the system tray, forwarding what's picked there as
+/// events.
+#[cfg(target_os = "linux")]
+fn start_tray(config: &Config, keyboard: &dyn KeyboardBackend, events: mpsc::Sender<Event>) -> Result<Tray> {
+    let (sender, actions) = mpsc::channel();
+    let current = keyboard.current_layout().unwrap_or_default();
+    let tray = Tray::start(&config.favorites, &current, sender).context("Failed to show the tray icon")?;
+    thread::spawn(move || {
+        for action in actions {
+            if events.send(Event::Tray(action)).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(tray)
+}
+
+/// Opens the TUI in a terminal, which brings an already open one forward
+/// instead.
+#[cfg(target_os = "linux")]
+fn open_tui(settings: &config::Daemon) -> Result<()> {
+    let program = std::env::current_exe().context("Couldn't tell where levocale is installed")?;
+    let terminal = match (settings.terminal.as_slice(), std::env::var("TERMINAL")) {
+        ([], Ok(terminal)) if !terminal.is_empty() => vec![terminal, "-e".to_string()],
+        ([], _) => vec!["xdg-terminal-exec".to_string()],
+        (terminal, _) => terminal.to_vec(),
+    };
+    let Some((first, rest)) = terminal.split_first() else {
+        bail!("No terminal to open levocale in");
+    };
+    exec::local(first)
+        .args(rest)
+        .arg(program)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to start {}", first))?;
+    Ok(())
+}
+
+/// The clients subscribed on the control socket, and what they were last
+/// told.
+struct Subscribers {
+    clients: Vec<UnixStream>,
+    state: State,
+}
+
+impl Subscribers {
+    fn add(&mut self, mut client: UnixStream) {
+        if writeln!(client, "{}", self.state.json()).is_ok() {
+            self.clients.push(client);
+        }
+    }
+
+    /// Tells everyone about a change, dropping the clients that left or
+    /// stopped reading, whose writes fail once they time out.
+    fn update(&mut self) {
+        let state = State::current();
+        if state == self.state {
+            return;
+        }
+        self.state = state;
+        let line = self.state.json();
+        self.clients.retain_mut(|client| writeln!(client, "{}", line).is_ok());
+    }
+}
+
+/// Hyprland's event socket, which announces focus changes among others.
+pub fn hyprland_socket() -> Result<PathBuf> {
+    let Some(signature) = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE") else {
+        bail!("Hyprland isn't running (HYPRLAND_INSTANCE_SIGNATURE is unset)");
+    };
+    // Hyprland moved its sockets from /tmp to the runtime directory in 0.40
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    let candidates = runtime_dir.into_iter().map(|dir| dir.join("hypr")).chain([PathBuf::from("/tmp/hypr")]);
+    for dir in candidates {
+        let path = dir.join(&signature).join(".socket2.sock");
+        if path.exists() {
+            return Ok(path);
+        }
+    }
+    bail!("Couldn't find Hyprland's event socket")
+}
+
+/// Forwards window events from Hyprland's socket until it closes.
+fn watch_hyprland(events: mpsc::Sender<Event>) -> Result<()> {
+    let path = hyprland_socket()?;
+    let stream = UnixStream::connect(&path).with_context(|| format!("Failed to connect to {}", path.display()))?;
+    thread::spawn(move || {
+        // activewindow carries the class, activewindowv2 right after it the address
+        let mut class = String::new();
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let Some((name, data)) = line.split_once(">>") else {
+                continue;
+            };
+            let event = match name {
+                "activewindow" => {
+                    class = data.split(',').next().unwrap_or_default().to_string();
+                    continue;
+                }
+                "activewindowv2" => Event::WindowFocused {
+                    class: std::mem::take(&mut class),
+                    address: data.to_string(),
+                },
+                "closewindow" => Event::WindowClosed { address: data.to_string() },
+                _ => continue,
+            };
+            if events.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+pub fn run(config: &Config) -> Result<()> {
+    let settings = &config.daemon;
+    let keyboard = backend::active();
+    if settings.per_window_layout && keyboard.name() != "hyprland" {
+        bail!("Per-window layouts need Hyprland, not {}", keyboard.name());
+    }
+
+    let (sender, events) = mpsc::channel();
+    let mut memory = LayoutMemory {
+        settings: settings.clone(),
+        layouts: HashMap::new(),
+        focused: None,
+    };
+    if settings.per_window_layout {
+        watch_hyprland(sender.clone())?;
+        let per = if settings.remember_by == RememberBy::Window { "window" } else { "application" };
+        log::info(&format!("Remembering layouts per {}", per));
+    }
+    let mut devices = DeviceRules {
+        config: config.clone(),
+        known: HashSet::new(),
+    };
+    if !config.devices.is_empty() {
+        // Keyboards attached already count as just plugged in
+        devices.refresh(keyboard.as_ref());
+        watch_devices(sender.clone());
+    }
+    let mut sleep = SleepState::default();
+    if settings.restore_after_sleep {
+        // In case the sleep signal comes too late to ask
+        sleep.save(keyboard.as_ref());
+        watch_sleep(sender.clone())?;
+    }
+    // Serving subscribers is reason enough to run, whatever else is set
+    serve(ipc::bind()?, sender.clone());
+    log::info(&format!("Answering on {}", ipc::socket_path().display()));
+    let mut subscribers = Subscribers {
+        clients: Vec::new(),
+        state: State::current(),
+    };
+    // The tray speaks D-Bus, which only the Linux build does
+    #[cfg(target_os = "linux")]
+    let tray = match settings.tray {
+        true => Some(start_tray(config, keyboard.as_ref(), sender.clone())?),
+        false => None,
+    };
+    #[cfg(target_os = "linux")]
+    if tray.is_some() {
+        log::info("Showing the layout in the tray");
+    }
+    #[cfg(not(target_os = "linux"))]
+    if settings.tray {
+        log::error("The tray icon is only available on Linux");
+    }
+    watch_state(sender.clone());
+    drop(sender);
+    for event in events {
+        let result = match event {
+            Event::WindowFocused { class, address } => memory.focus(keyboard.as_ref(), &class, &address),
+            Event::WindowClosed { address } => {
+                memory.close(&address);
+                Ok(())
+            }
+            Event::DevicesChanged => {
+                devices.refresh(keyboard.as_ref());
+                Ok(())
+            }
+            Event::PrepareForSleep(true) => {
+                sleep.save(keyboard.as_ref());
+                Ok(())
+            }
+            Event::PrepareForSleep(false) => {
+                // Let the keyboards come back before setting them up
+                thread::sleep(Duration::from_secs(1));
+                let restored = sleep.restore(keyboard.as_ref());
+                if !config.devices.is_empty() {
+                    devices.known.clear();
+                    devices.refresh(keyboard.as_ref());
+                }
+                restored
+            }
+            Event::Subscribed(client) => {
+                subscribers.add(client);
+                Ok(())
+            }
+            Event::StateChanged => {
+                subscribers.update();
+                #[cfg(target_os = "linux")]
+                if let (Some(tray), Some(layout)) = (&tray, keyboard.current_layout())
+                    && let Err(e) = tray.update(&layout)
+                {
+                    log::error(&format!("{:#}", e));
+                }
+                Ok(())
+            }
+            #[cfg(target_os = "linux")]
+            Event::Tray(tray::Action::SwitchTo(layout)) => {
+                keyboard.set_layout(&layout).with_context(|| format!("Failed to switch to {}", layout))
+            }
+            #[cfg(target_os = "linux")]
+            Event::Tray(tray::Action::OpenTui) => open_tui(settings),
+        };
+        // One failed switch shouldn't stop the daemon
+        if let Err(e) = result {
+            log::error(&format!("{:#}", e));
+        }
+    }
+    bail!("Lost track of the compositor's events")
+}
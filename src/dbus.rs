@@ -0,0 +1,596 @@
+//! Just enough of the D-Bus wire protocol to serve objects on the session
+//! bus, which busctl and gdbus can't do: a connection, and messages built
+//! from the basic types. Only little-endian messages are understood.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+
+use crate::log;
+
+pub const METHOD_CALL: u8 = 1;
+pub const METHOD_RETURN: u8 = 2;
+pub const ERROR: u8 = 3;
+pub const SIGNAL: u8 = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    Str(String),
+    Path(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// The elements' signature, then the elements.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::I32(_) => "i".to_string(),
+            Value::U32(_) => "u".to_string(),
+            Value::Str(_) => "s".to_string(),
+            Value::Path(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::Variant(_) => "v".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => format!("({})", fields.iter().map(Value::signature).collect::<String>()),
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(text) | Value::Path(text) | Value::Signature(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Value::I32(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::U32(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array, or nothing for anything else.
+    pub fn elements(&self) -> &[Value] {
+        match self {
+            Value::Array(_, elements) => elements,
+            _ => &[],
+        }
+    }
+}
+
+pub fn string(text: &str) -> Value {
+    Value::Str(text.to_string())
+}
+
+pub fn variant(value: Value) -> Value {
+    Value::Variant(Box::new(value))
+}
+
+/// An `a{sv}` dictionary, as properties are passed around.
+pub fn dict(entries: Vec<(&str, Value)>) -> Value {
+    let entries = entries
+        .into_iter()
+        .map(|(key, value)| Value::DictEntry(Box::new(string(key)), Box::new(variant(value))))
+        .collect();
+    Value::Array("{sv}".to_string(), entries)
+}
+
+/// How deep containers may nest in a message we read, as the D-Bus
+/// specification caps it.
+const MAX_DEPTH: usize = 64;
+
+/// How a type's values are aligned, by the first character of its
+/// signature.
+fn alignment(signature: &str) -> usize {
+    match signature.as_bytes().first() {
+        Some(b'y' | b'g' | b'v') => 1,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 4,
+    }
+}
+
+/// Splits a signature into its complete types, e.g. "sa{sv}(ii)" into
+/// "s", "a{sv}" and "(ii)".
+fn split_signature(signature: &str) -> Result<Vec<&str>> {
+    let bytes = signature.as_bytes();
+    let mut types = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let mut end = start;
+        while bytes[end] == b'a' {
+            end += 1;
+            if end == bytes.len() {
+                bail!("Array without an element type in signature {:?}", signature);
+            }
+        }
+        if matches!(bytes[end], b'(' | b'{') {
+            let mut depth = 0;
+            loop {
+                match bytes.get(end) {
+                    Some(b'(' | b'{') => depth += 1,
+                    Some(b')' | b'}') => depth -= 1,
+                    Some(_) => {}
+                    None => bail!("Unbalanced signature {:?}", signature),
+                }
+                end += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        } else {
+            end += 1;
+        }
+        types.push(&signature[start..end]);
+        start = end;
+    }
+    Ok(types)
+}
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        while !self.data.len().is_multiple_of(alignment) {
+            self.data.push(0);
+        }
+    }
+
+    fn u32(&mut self, number: u32) {
+        self.align(4);
+        self.data.extend(number.to_le_bytes());
+    }
+
+    fn write(&mut self, value: &Value) {
+        match value {
+            Value::Byte(byte) => self.data.push(*byte),
+            Value::Bool(flag) => self.u32(*flag as u32),
+            Value::I32(number) => self.u32(*number as u32),
+            Value::U32(number) => self.u32(*number),
+            Value::Str(text) | Value::Path(text) => {
+                self.u32(text.len() as u32);
+                self.data.extend(text.as_bytes());
+                self.data.push(0);
+            }
+            Value::Signature(text) => {
+                self.data.push(text.len() as u8);
+                self.data.extend(text.as_bytes());
+                self.data.push(0);
+            }
+            Value::Variant(inner) => {
+                self.write(&Value::Signature(inner.signature()));
+                self.write(inner);
+            }
+            Value::Array(element, elements) => {
+                self.u32(0);
+                let length_at = self.data.len() - 4;
+                // The length leaves out the padding before the first element
+                self.align(alignment(element));
+                let start = self.data.len();
+                for element in elements {
+                    self.write(element);
+                }
+                let length = (self.data.len() - start) as u32;
+                self.data[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.align(8);
+                for field in fields {
+                    self.write(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.align(8);
+                self.write(key);
+                self.write(value);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn align(&mut self, alignment: usize) {
+        self.position = self.position.next_multiple_of(alignment);
+    }
+
+    fn take(&mut self, count: usize) -> Result<&[u8]> {
+        let Some(bytes) = self.data.get(self.position..self.position + count) else {
+            bail!("Message ends early");
+        };
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.align(4);
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn text(&mut self, length: usize) -> Result<String> {
+        let text = String::from_utf8_lossy(self.take(length)?).into_owned();
+        // The terminating nul
+        self.take(1)?;
+        Ok(text)
+    }
+
+    fn read(&mut self, signature: &str) -> Result<Value> {
+        self.read_nested(signature, 0)
+    }
+
+    /// Reads a value of `signature`, which has to be one complete type:
+    /// it may come from the wire, in a variant, and a peer mustn't be able
+    /// to crash us with e.g. "(" or nest values without end.
+    fn read_nested(&mut self, signature: &str, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("Values nested more than {} deep", MAX_DEPTH);
+        }
+        if split_signature(signature)?.len() != 1 {
+            bail!("{:?} isn't a single complete type", signature);
+        }
+        let read = |reader: &mut Self, inner: &str| reader.read_nested(inner, depth + 1);
+        Ok(match signature.as_bytes().first() {
+            Some(b'y') => Value::Byte(self.take(1)?[0]),
+            Some(b'b') => Value::Bool(self.u32()? != 0),
+            Some(b'i') => Value::I32(self.u32()? as i32),
+            Some(b'u') => Value::U32(self.u32()?),
+            Some(b's') => {
+                let length = self.u32()? as usize;
+                Value::Str(self.text(length)?)
+            }
+            Some(b'o') => {
+                let length = self.u32()? as usize;
+                Value::Path(self.text(length)?)
+            }
+            Some(b'g') => {
+                let length = self.take(1)?[0] as usize;
+                Value::Signature(self.text(length)?)
+            }
+            Some(b'v') => {
+                let length = self.take(1)?[0] as usize;
+                let inner = self.text(length)?;
+                Value::Variant(Box::new(read(self, &inner)?))
+            }
+            Some(b'a') => {
+                let element = &signature[1..];
+                let length = self.u32()? as usize;
+                self.align(alignment(element));
+                let end = self.position + length;
+                let mut elements = Vec::new();
+                while self.position < end {
+                    elements.push(read(self, element)?);
+                }
+                Value::Array(element.to_string(), elements)
+            }
+            Some(b'(') => {
+                let Some(inner) = signature.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) else {
+                    bail!("Malformed struct {:?}", signature);
+                };
+                // An empty struct would take no room, so an array of them
+                // would never end
+                if inner.is_empty() {
+                    bail!("Empty struct in signature");
+                }
+                self.align(8);
+                let fields = split_signature(inner)?;
+                Value::Struct(fields.into_iter().map(|field| read(self, field)).collect::<Result<_>>()?)
+            }
+            Some(b'{') => {
+                let Some(inner) = signature.strip_prefix('{').and_then(|rest| rest.strip_suffix('}')) else {
+                    bail!("Malformed dictionary entry {:?}", signature);
+                };
+                self.align(8);
+                let fields = split_signature(inner)?;
+                let [key, value] = fields.as_slice() else {
+                    bail!("Dictionary entry {:?} needs a key and a value", signature);
+                };
+                Value::DictEntry(Box::new(read(self, key)?), Box::new(read(self, value)?))
+            }
+            _ => bail!("Unsupported type {:?}", signature),
+        })
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Message {
+    pub kind: u8,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str, body: Vec<Value>) -> Message {
+        Message {
+            kind: METHOD_CALL,
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            destination: Some(destination.to_string()),
+            body,
+            ..Message::default()
+        }
+    }
+
+    pub fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Message {
+        Message {
+            kind: SIGNAL,
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Message::default()
+        }
+    }
+
+    /// The answer to this method call.
+    pub fn reply(&self, body: Vec<Value>) -> Message {
+        Message {
+            kind: METHOD_RETURN,
+            reply_serial: Some(self.serial),
+            destination: self.sender.clone(),
+            body,
+            ..Message::default()
+        }
+    }
+
+    /// An error answering this method call, e.g.
+    /// "org.freedesktop.DBus.Error.UnknownMethod".
+    pub fn error(&self, name: &str, text: &str) -> Message {
+        Message {
+            kind: ERROR,
+            error_name: Some(name.to_string()),
+            reply_serial: Some(self.serial),
+            destination: self.sender.clone(),
+            body: vec![string(text)],
+            ..Message::default()
+        }
+    }
+
+    pub fn member(&self) -> &str {
+        self.member.as_deref().unwrap_or_default()
+    }
+
+    pub fn interface(&self) -> &str {
+        self.interface.as_deref().unwrap_or_default()
+    }
+
+    fn encode(&self, serial: u32) -> Vec<u8> {
+        let mut body = Writer::default();
+        for value in &self.body {
+            body.write(value);
+        }
+        let signature: String = self.body.iter().map(Value::signature).collect();
+        let field = |code: u8, value: Value| Value::Struct(vec![Value::Byte(code), variant(value)]);
+        let mut fields = Vec::new();
+        let strings = [(1, &self.path), (2, &self.interface), (3, &self.member), (4, &self.error_name)];
+        for (code, text) in strings {
+            if let Some(text) = text {
+                fields.push(field(code, if code == 1 { Value::Path(text.clone()) } else { string(text) }));
+            }
+        }
+        if let Some(serial) = self.reply_serial {
+            fields.push(field(5, Value::U32(serial)));
+        }
+        if let Some(destination) = &self.destination {
+            fields.push(field(6, string(destination)));
+        }
+        if !signature.is_empty() {
+            fields.push(field(8, Value::Signature(signature)));
+        }
+        let mut message = Writer::default();
+        for byte in [b'l', self.kind, 0, 1] {
+            message.write(&Value::Byte(byte));
+        }
+        message.u32(body.data.len() as u32);
+        message.u32(serial);
+        message.write(&Value::Array("(yv)".to_string(), fields));
+        message.align(8);
+        message.data.extend(body.data);
+        message.data
+    }
+
+    fn decode(data: &[u8]) -> Result<Message> {
+        if data.first() != Some(&b'l') {
+            bail!("Only little-endian messages are supported");
+        }
+        let mut reader = Reader { data, position: 8 };
+        let mut message = Message { kind: data[1], serial: reader.u32()?, ..Message::default() };
+        let mut signature = String::new();
+        for field in reader.read("a(yv)")?.elements() {
+            let Value::Struct(parts) = field else {
+                continue;
+            };
+            let [Value::Byte(code), Value::Variant(value)] = parts.as_slice() else {
+                continue;
+            };
+            let text = value.as_str().map(str::to_string);
+            match code {
+                1 => message.path = text,
+                2 => message.interface = text,
+                3 => message.member = text,
+                4 => message.error_name = text,
+                5 => message.reply_serial = value.as_u32(),
+                6 => message.destination = text,
+                7 => message.sender = text,
+                8 => signature = text.unwrap_or_default(),
+                _ => {}
+            }
+        }
+        reader.align(8);
+        let mut body = Reader { data: data.get(reader.position..).unwrap_or_default(), position: 0 };
+        for kind in split_signature(&signature)? {
+            message.body.push(body.read(kind)?);
+        }
+        Ok(message)
+    }
+}
+
+/// Where the session bus listens, from DBUS_SESSION_BUS_ADDRESS or the
+/// usual place in the runtime directory.
+fn session_address() -> Result<SocketAddr> {
+    let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").unwrap_or_default();
+    // Several addresses may be listed, to be tried in turn
+    for option in address.split(';').flat_map(|address| address.strip_prefix("unix:")) {
+        for pair in option.split(',') {
+            match pair.split_once('=') {
+                Some(("path", path)) => return Ok(SocketAddr::from_pathname(path)?),
+                Some(("abstract", name)) => return Ok(SocketAddr::from_abstract_name(name)?),
+                _ => {}
+            }
+        }
+    }
+    let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") else {
+        bail!("No session bus (DBUS_SESSION_BUS_ADDRESS and XDG_RUNTIME_DIR are unset)");
+    };
+    Ok(SocketAddr::from_pathname(std::path::Path::new(&runtime_dir).join("bus"))?)
+}
+
+/// A connection to the session bus. Messages are sent from any thread and
+/// received on the one that owns the [`Incoming`] half.
+pub struct Bus {
+    /// The stream to write to and the last serial used.
+    writer: Mutex<(UnixStream, u32)>,
+    /// The name the bus gave us, e.g. ":1.42".
+    pub unique_name: String,
+}
+
+pub struct Incoming {
+    reader: BufReader<UnixStream>,
+}
+
+impl Incoming {
+    /// The next message we can read. One with a type we don't know, e.g.
+    /// a signal carrying an int64, is logged and skipped: it has been read
+    /// in full by then, so the ones after it are unaffected.
+    pub fn next(&mut self) -> Result<Message> {
+        loop {
+            let mut fixed = [0u8; 16];
+            self.reader.read_exact(&mut fixed).context("The session bus closed the connection")?;
+            // Without knowing the byte order, the lengths can't be trusted
+            if fixed[0] != b'l' {
+                bail!("Only little-endian messages are supported");
+            }
+            let body_length = u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]) as usize;
+            let fields_length = u32::from_le_bytes([fixed[12], fixed[13], fixed[14], fixed[15]]) as usize;
+            let length = (16 + fields_length).next_multiple_of(8) + body_length;
+            let mut data = fixed.to_vec();
+            data.resize(length, 0);
+            self.reader.read_exact(&mut data[16..])?;
+            match Message::decode(&data) {
+                Ok(message) => return Ok(message),
+                Err(e) => log::error(&format!("Skipped a D-Bus message: {:#}", e)),
+            }
+        }
+    }
+}
+
+impl Bus {
+    pub fn session() -> Result<(Bus, Incoming)> {
+        let address = session_address()?;
+        let mut stream = UnixStream::connect_addr(&address).context("Failed to connect to the session bus")?;
+        // The bus checks the uid we claim against the socket's peer
+        // SAFETY: getuid has no preconditions and can't fail
+        let uid = unsafe { libc::getuid() };
+        let hex_uid: String = uid.to_string().bytes().map(|byte| format!("{:02x}", byte)).collect();
+        stream.write_all(format!("\0AUTH EXTERNAL {}\r\n", hex_uid).as_bytes())?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut answer = String::new();
+        reader.read_line(&mut answer)?;
+        if !answer.starts_with("OK ") {
+            bail!("The session bus refused us: {}", answer.trim());
+        }
+        stream.write_all(b"BEGIN\r\n")?;
+        let mut bus = Bus { writer: Mutex::new((stream, 0)), unique_name: String::new() };
+        let mut incoming = Incoming { reader };
+        let hello = bus.send(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+            Vec::new(),
+        ))?;
+        loop {
+            let message = incoming.next()?;
+            if message.reply_serial == Some(hello) {
+                bus.unique_name = message.body.first().and_then(Value::as_str).unwrap_or_default().to_string();
+                return Ok((bus, incoming));
+            }
+        }
+    }
+
+    /// Sends `message`, returning the serial replies will refer to.
+    pub fn send(&self, message: Message) -> Result<u32> {
+        let Ok(mut writer) = self.writer.lock() else {
+            bail!("The session bus connection is unusable");
+        };
+        writer.1 += 1;
+        let serial = writer.1;
+        writer.0.write_all(&message.encode(serial)).context("Failed to write to the session bus")?;
+        Ok(serial)
+    }
+
+    /// Sends a method call and waits for its reply, passing over whatever
+    /// else arrives first. Gives up after `timeout` without a message.
+    pub fn call(&self, incoming: &mut Incoming, message: Message, timeout: Duration) -> Result<Message> {
+        incoming.reader.get_ref().set_read_timeout(Some(timeout))?;
+        let serial = self.send(message)?;
+        loop {
+            let reply = incoming.next()?;
+            if reply.reply_serial != Some(serial) {
+                continue;
+            }
+            if reply.kind == ERROR {
+                let text = reply.body.first().and_then(Value::as_str).unwrap_or_default();
+                bail!("{}: {}", reply.error_name.as_deref().unwrap_or("error"), text);
+            }
+            return Ok(reply);
+        }
+    }
+
+    /// Calls a method of the bus itself, e.g. RequestName.
+    pub fn call_bus(&self, member: &str, body: Vec<Value>) -> Result<u32> {
+        self.send(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            member,
+            body,
+        ))
+    }
+}
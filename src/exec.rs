@@ -0,0 +1,272 @@
+//! Runs system commands and reads system files either locally or, with
+//! `--host`, on another machine over ssh.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Result, bail};
+
+use crate::config::{self, Mode};
+
+static REMOTE_HOST: OnceLock<String> = OnceLock::new();
+
+/// The `[commands]` settings, once the config has been read.
+static COMMANDS: Mutex<Option<config::Commands>> = Mutex::new(None);
+/// --user or --admin, which outrank `mode` from the config.
+static MODE: Mutex<Option<Mode>> = Mutex::new(None);
+/// `mode` from the config, once read.
+static CONFIG_MODE: Mutex<Option<Mode>> = Mutex::new(None);
+/// Set while the TUI holds the terminal, where sudo can't ask for a
+/// password without drawing over it and losing the keys to it.
+static NO_PASSWORD_PROMPTS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_remote_host(host: String) {
+    let _ = REMOTE_HOST.set(host);
+}
+
+pub fn remote_host() -> Option<&'static str> {
+    REMOTE_HOST.get().map(String::as_str)
+}
+
+/// Takes `[commands]` and `mode` from `config`, e.g. once it's reloaded.
+pub fn configure(config: &config::Config) {
+    if let Ok(mut current) = COMMANDS.lock() {
+        *current = Some(config.commands.clone());
+    }
+    if let Ok(mut current) = CONFIG_MODE.lock() {
+        *current = Some(config.mode);
+    }
+}
+
+/// The `[commands]` settings, read from the config the first time when
+/// nothing has set them yet, e.g. for a subcommand.
+fn commands() -> config::Commands {
+    let Ok(mut current) = COMMANDS.lock() else {
+        return config::Commands::default();
+    };
+    current
+        .get_or_insert_with(|| config::Config::load().map(|config| config.commands).unwrap_or_default())
+        .clone()
+}
+
+pub fn set_mode(mode: Mode) {
+    if let Ok(mut current) = MODE.lock() {
+        *current = Some(mode);
+    }
+}
+
+/// Whether system-wide changes are allowed, from the flag or else the
+/// config.
+pub fn mode() -> Mode {
+    if let Ok(flag) = MODE.lock()
+        && let Some(mode) = *flag
+    {
+        return mode;
+    }
+    let Ok(mut current) = CONFIG_MODE.lock() else {
+        return Mode::default();
+    };
+    *current.get_or_insert_with(|| config::Config::load().map(|config| config.mode).unwrap_or_default())
+}
+
+/// The words that run `program`: its override from `[commands]`, or just
+/// its name.
+fn resolve(commands: &config::Commands, program: &str) -> Vec<String> {
+    match commands.programs.get(program) {
+        Some(words) if !words.is_empty() => words.clone(),
+        _ => vec![program.to_string()],
+    }
+}
+
+/// `words` run on this machine behind the `[commands]` prefix, with `env`
+/// set for the program.
+fn prefixed(commands: &config::Commands, env: &[(&str, &str)], words: Vec<String>) -> Command {
+    let mut all = commands.prefix.clone();
+    // Whatever the prefix starts doesn't pass our environment on
+    if !all.is_empty() && !env.is_empty() {
+        all.push("env".to_string());
+        all.extend(env.iter().map(|(key, value)| format!("{}={}", key, value)));
+    }
+    all.extend(words);
+    let mut command = Command::new(&all[0]);
+    command.args(&all[1..]);
+    if commands.prefix.is_empty() {
+        command.envs(env.iter().copied());
+    }
+    command
+}
+
+/// A command for `program` on this machine, run the way `[commands]` says.
+pub fn local(program: &str) -> Command {
+    let commands = commands();
+    prefixed(&commands, &[], resolve(&commands, program))
+}
+
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,@+".contains(c)) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn ssh(host: &str, remote_command: String) -> Command {
+    let mut command = local("ssh");
+    // Never stop to ask for a password behind the TUI, and share one
+    // connection across the many short commands we run
+    command.args([
+        "-o",
+        "BatchMode=yes",
+        "-o",
+        "ControlMaster=auto",
+        "-o",
+        "ControlPath=~/.ssh/levocale-%C",
+        "-o",
+        "ControlPersist=60",
+        host,
+        "--",
+    ]);
+    command.arg(remote_command);
+    command
+}
+
+/// A command for `program` with `args`, wrapped in ssh in remote mode.
+pub fn command<S: AsRef<str>>(program: &str, args: &[S]) -> Command {
+    command_with_env(&[], program, args)
+}
+
+/// Whether commands already run as root on the target machine, e.g. in an
+/// installer's chroot, which may not have sudo at all.
+fn is_root() -> bool {
+    static ROOT: OnceLock<bool> = OnceLock::new();
+    *ROOT.get_or_init(|| {
+        let output = command::<&str>("id", &["-u"]).output();
+        output.is_ok_and(|output| output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "0")
+    })
+}
+
+/// Makes sudo fail rather than ask for a password from here on; whoever
+/// calls this authenticates up front with [`sudo_needs_password`].
+pub fn forbid_password_prompts() {
+    NO_PASSWORD_PROMPTS.store(true, Ordering::Relaxed);
+}
+
+/// Whether [`privileged`] commands would have sudo ask for a password
+/// first, i.e. it's used here and has no credentials cached. Remote sudo
+/// can't ask over BatchMode ssh either way.
+pub fn sudo_needs_password() -> bool {
+    if mode() == Mode::User || remote_host().is_some() || is_root() {
+        return false;
+    }
+    let status = local("sudo").args(["-n", "true"]).stdin(Stdio::null()).stderr(Stdio::null()).status();
+    // Without sudo there's nothing to authenticate
+    status.is_ok_and(|status| !status.success())
+}
+
+/// `words` run as root, through sudo unless we're root already. User mode
+/// refuses, since it only changes the session and the user's own files.
+pub fn privileged<S: AsRef<str>>(words: &[S]) -> Result<Command> {
+    let program = words.first().map(AsRef::as_ref).unwrap_or_default();
+    if mode() == Mode::User {
+        bail!("{} needs root, which user mode doesn't use (--admin allows it)", program);
+    }
+    if is_root() {
+        return Ok(command(program, words.get(1..).unwrap_or_default()));
+    }
+    if NO_PASSWORD_PROMPTS.load(Ordering::Relaxed) {
+        let mut sudo_words = vec!["-n"];
+        sudo_words.extend(words.iter().map(AsRef::as_ref));
+        return Ok(command("sudo", &sudo_words));
+    }
+    Ok(command("sudo", words))
+}
+
+/// Like [`command`], with extra environment variables for the program.
+pub fn command_with_env<S: AsRef<str>>(env: &[(&str, &str)], program: &str, args: &[S]) -> Command {
+    let commands = commands();
+    match remote_host() {
+        None => {
+            let mut command = prefixed(&commands, env, resolve(&commands, program));
+            command.args(args.iter().map(AsRef::as_ref));
+            command
+        }
+        Some(host) => {
+            // ssh hands the remote shell a single string, so quote everything
+            let mut words: Vec<String> = Vec::new();
+            if !env.is_empty() {
+                words.push("env".to_string());
+                words.extend(env.iter().map(|(key, value)| shell_quote(&format!("{}={}", key, value))));
+            }
+            words.extend(resolve(&commands, program).iter().map(|word| shell_quote(word)));
+            words.extend(args.iter().map(|arg| shell_quote(arg.as_ref())));
+            ssh(host, words.join(" "))
+        }
+    }
+}
+
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    if remote_host().is_none() {
+        return fs::read_to_string(path);
+    }
+    let output = command("cat", &[path.to_string_lossy()]).output()?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let kind = if error.contains("No such file") { io::ErrorKind::NotFound } else { io::ErrorKind::Other };
+    Err(io::Error::new(kind, error))
+}
+
+pub fn exists(path: &Path) -> bool {
+    match remote_host() {
+        None => path.exists(),
+        Some(_) => command("test", &["-e".into(), path.to_string_lossy()])
+            .output()
+            .is_ok_and(|output| output.status.success()),
+    }
+}
+
+pub fn is_dir(path: &Path) -> bool {
+    match remote_host() {
+        None => path.is_dir(),
+        Some(_) => command("test", &["-d".into(), path.to_string_lossy()])
+            .output()
+            .is_ok_and(|output| output.status.success()),
+    }
+}
+
+/// File names in a directory; empty when it can't be read.
+pub fn read_dir_names(path: &Path) -> Vec<String> {
+    if remote_host().is_none() {
+        let Ok(entries) = fs::read_dir(path) else {
+            return Vec::new();
+        };
+        return entries.flatten().map(|entry| entry.file_name().to_string_lossy().into_owned()).collect();
+    }
+    match command("ls", &["-1".into(), path.to_string_lossy()]).output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The target machine's OS, named like `std::env::consts::OS`.
+pub fn os() -> &'static str {
+    static OS: OnceLock<String> = OnceLock::new();
+    OS.get_or_init(|| {
+        if remote_host().is_none() {
+            return std::env::consts::OS.to_string();
+        }
+        let output = command::<&str>("uname", &["-s"]).output();
+        let name = output.map(|output| String::from_utf8_lossy(&output.stdout).trim().to_lowercase());
+        match name.as_deref() {
+            Ok("darwin") => "macos".to_string(),
+            Ok(name) if !name.is_empty() => name.to_string(),
+            _ => "linux".to_string(),
+        }
+    })
+}
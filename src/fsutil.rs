@@ -0,0 +1,61 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+
+use crate::exec;
+
+/// Replaces `path` with `contents` without ever leaving a half-written file
+/// behind, keeping the previous version next to it as `<name>.bak`.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.levocale-tmp", file_name));
+    let mut file = fs::File::create(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    file.write_all(contents.as_bytes())
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    if path.exists() {
+        fs::copy(path, path.with_file_name(format!("{}.bak", file_name)))
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+    }
+    fs::rename(&tmp_path, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Writes a root-owned file through `sudo tee`, keeping the previous
+/// version as `<name>.bak` like [`write_atomic`] does.
+pub fn write_as_root(path: &Path, contents: &str) -> Result<()> {
+    let target = path.to_string_lossy();
+    if exec::exists(path) {
+        let backup = format!("{}.bak", target);
+        run_sudo(&mut exec::privileged(&["cp", "-p", &*target, &backup])?, None)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+    }
+    run_sudo(&mut exec::privileged(&["tee", &*target])?, Some(contents))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn run_sudo(command: &mut Command, stdin: Option<&str>) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut pipe) = child.stdin.take() {
+        pipe.write_all(stdin.unwrap_or_default().as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
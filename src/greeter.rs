@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{exec, fsutil, managed_block, xkb};
+
+const GREETD_SWAY_CONFIG: &str = "/etc/greetd/sway-config";
+const SDDM_XSETUP: &str = "/usr/share/sddm/scripts/Xsetup";
+
+/// A login screen whose keyboard setup lives in a file we can edit.
+pub enum Greeter {
+    /// greetd running its greeter (gtkgreet, regreet, ...) inside sway.
+    GreetdSway(PathBuf),
+    /// SDDM's X11 greeter, which runs Xsetup before showing up.
+    Sddm(PathBuf),
+}
+
+impl Greeter {
+    pub fn detect() -> Option<Greeter> {
+        if Path::new(GREETD_SWAY_CONFIG).exists() {
+            return Some(Greeter::GreetdSway(PathBuf::from(GREETD_SWAY_CONFIG)));
+        }
+        if Path::new(SDDM_XSETUP).exists() {
+            return Some(Greeter::Sddm(PathBuf::from(SDDM_XSETUP)));
+        }
+        None
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Greeter::GreetdSway(_) => "greetd",
+            Greeter::Sddm(_) => "SDDM",
+        }
+    }
+
+    /// Makes the greeter start with `layout` (XKB "layout(variant)"
+    /// notation) and `options`.
+    pub fn sync_keyboard(&self, layout: &str, options: &str) -> Result<()> {
+        let (layout, variant) = xkb::split_layout_variant(layout);
+        let (path, body) = match self {
+            Greeter::GreetdSway(path) => {
+                let mut body = String::from("input type:keyboard {\n");
+                for (setting, value) in [("xkb_layout", layout), ("xkb_variant", variant.unwrap_or("")), ("xkb_options", options)] {
+                    if !value.is_empty() {
+                        body.push_str(&format!("    {} {}\n", setting, value));
+                    }
+                }
+                body.push('}');
+                (path, body)
+            }
+            Greeter::Sddm(path) => {
+                let (layout, variant) = (exec::shell_quote(layout), exec::shell_quote(variant.unwrap_or("")));
+                let mut body = format!("setxkbmap -layout {} -variant {} -option ''", layout, variant);
+                if !options.is_empty() {
+                    body.push_str(&format!(" -option {}", exec::shell_quote(options)));
+                }
+                (path, body)
+            }
+        };
+        let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let updated = managed_block::upsert(&contents, "#", &body);
+        if updated == contents {
+            return Ok(());
+        }
+        fsutil::write_as_root(path, &updated)
+    }
+}
@@ -0,0 +1,99 @@
+//! The `[hooks]` commands, run through `sh -c`: `before_*` ones may veto a
+//! change, and `after_*` ones follow it once it worked, e.g. to restart
+//! fcitx5 or reload a bar that shows the layout.
+
+use std::process::{Command, Output, Stdio};
+
+use anyhow::{Result, bail};
+
+use crate::{backend, config, log};
+
+#[derive(Clone, Copy)]
+pub enum Changed {
+    Layout,
+    Locale,
+}
+
+impl Changed {
+    pub fn name(self) -> &'static str {
+        match self {
+            Changed::Layout => "layout",
+            Changed::Locale => "locale",
+        }
+    }
+
+    pub fn before(self, hooks: &config::Hooks) -> &[String] {
+        match self {
+            Changed::Layout => &hooks.before_layout,
+            Changed::Locale => &hooks.before_locale,
+        }
+    }
+
+    pub fn after(self, hooks: &config::Hooks) -> &[String] {
+        match self {
+            Changed::Layout => &hooks.after_layout,
+            Changed::Locale => &hooks.after_locale,
+        }
+    }
+}
+
+/// Runs `command` with the settings in effect right now in its environment,
+/// plus `extra`.
+fn run(command: &str, changed: Changed, extra: &[(&str, &str)]) -> std::io::Result<Output> {
+    let keyboard = backend::active();
+    log::run(
+        Command::new("sh")
+            .args(["-c", command])
+            .env("LEVOCALE_CHANGED", changed.name())
+            .env("LEVOCALE_LAYOUT", keyboard.current_layout().unwrap_or_default())
+            .env("LEVOCALE_OPTIONS", keyboard.options().unwrap_or_default())
+            .env("LEVOCALE_LOCALE", backend::active_locale().current_locale().unwrap_or_default())
+            .envs(extra.iter().copied())
+            .stdin(Stdio::null()),
+    )
+}
+
+/// The last line a hook wrote, preferring what it said on stderr.
+fn last_line(output: &Output) -> Option<String> {
+    [&output.stderr, &output.stdout].into_iter().find_map(|stream| {
+        let text = String::from_utf8_lossy(stream);
+        text.lines().rfind(|line| !line.trim().is_empty()).map(|line| line.trim().to_string())
+    })
+}
+
+/// Asks the `before_*` hooks whether switching to `to` may go ahead. The
+/// first one to exit non-zero stops it, and whatever it printed last is the
+/// reason given.
+pub fn run_before(hooks: &config::Hooks, changed: Changed, to: &str) -> Result<()> {
+    for command in changed.before(hooks) {
+        match run(command, changed, &[("LEVOCALE_TO", to)]) {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => match last_line(&output) {
+                Some(reason) => bail!("Blocked by a before-{} hook: {}", changed.name(), reason),
+                None => bail!("Blocked by before-{} hook {:?} ({})", changed.name(), command, output.status),
+            },
+            Err(e) => bail!("Couldn't run before-{} hook {:?}: {}", changed.name(), command, e),
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `after_*` hooks for `changed` one after another. Fails naming
+/// every hook that did and why.
+pub fn run_after(hooks: &config::Hooks, changed: Changed) -> Result<()> {
+    let mut failures = Vec::new();
+    for command in changed.after(hooks) {
+        match run(command, changed, &[]) {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => match last_line(&output) {
+                Some(line) => failures.push(format!("{:?} ({}: {})", command, output.status, line)),
+                None => failures.push(format!("{:?} ({})", command, output.status)),
+            },
+            Err(e) => failures.push(format!("{:?} ({})", command, e)),
+        }
+    }
+    if !failures.is_empty() {
+        bail!("{}", failures.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! `levocale import --from`: profiles, favorites and device rules merged in
+//! from another levocale config, e.g. another user's or one kept with
+//! dotfiles, so a new machine starts out set up. What's already here wins.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::{self, Config, DeviceRule};
+use crate::{exec, fsutil, profile};
+
+/// Whether `from` names a git repository to clone rather than a path.
+fn is_url(from: &str) -> bool {
+    from.contains("://") || from.starts_with("git@")
+}
+
+/// The levocale config directory in `root`: `root` itself, or the place it
+/// has in a home directory or a dotfiles repository.
+fn config_dir_in(root: &Path) -> Option<PathBuf> {
+    [root.to_path_buf(), root.join("levocale"), root.join(".config").join("levocale")]
+        .into_iter()
+        .find(|dir| dir.join("config.toml").is_file() || dir.join("profiles").is_dir())
+}
+
+/// A shallow clone of a dotfiles repository, removed once dropped.
+struct Checkout(PathBuf);
+
+impl Checkout {
+    fn fetch(url: &str) -> Result<Checkout> {
+        let dir = std::env::temp_dir().join(format!("levocale-import-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let output = exec::local("git")
+            .args(["clone", "--quiet", "--depth", "1", url])
+            .arg(&dir)
+            .output()
+            .context("Failed to run git")?;
+        let checkout = Checkout(dir);
+        if !output.status.success() {
+            bail!("Couldn't clone {}: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(checkout)
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Imports from `from`, a path or a git URL, printing a line per item:
+/// ✓ for what was added and – for what was already here.
+pub fn run(from: &str) -> Result<()> {
+    let checkout = if is_url(from) { Some(Checkout::fetch(from)?) } else { None };
+    let root = match &checkout {
+        Some(checkout) => checkout.0.clone(),
+        None => config::expand_home(Path::new(from)),
+    };
+    let Some(source) = config_dir_in(&root) else {
+        bail!("No levocale config in {} (looked for config.toml or profiles/)", from);
+    };
+    if source.canonicalize().ok() == config::config_dir().canonicalize().ok() {
+        bail!("{} is this user's own config", source.display());
+    }
+    let theirs = match fs::read_to_string(source.join("config.toml")) {
+        Ok(contents) => toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", source.join("config.toml").display()))?,
+        Err(_) => Config::default(),
+    };
+    let ours = Config::load()?;
+
+    import_profiles(&source.join("profiles"))?;
+
+    let mut favorites: Vec<String> = Vec::new();
+    for favorite in theirs.favorites {
+        if ours.favorites.contains(&favorite) || favorites.contains(&favorite) {
+            println!("– favorite {}: already a favorite", favorite);
+        } else {
+            println!("✓ favorite {}", favorite);
+            favorites.push(favorite);
+        }
+    }
+    let mut devices: Vec<(String, DeviceRule)> = Vec::new();
+    for (pattern, rule) in theirs.devices {
+        if ours.devices.contains_key(&pattern) {
+            println!("– device rule {}: there's one for it already", pattern);
+        } else {
+            println!("✓ device rule {}", pattern);
+            devices.push((pattern, rule));
+        }
+    }
+    if !favorites.is_empty() || !devices.is_empty() {
+        config::add_imported(&favorites, &devices)?;
+    }
+    Ok(())
+}
+
+/// Copies the profiles in `dir` that don't clash with ours by name.
+fn import_profiles(dir: &Path) -> Result<()> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let target = profile::profiles_dir().join(format!("{}.toml", name));
+        if target.exists() {
+            println!("– profile {}: there's one by that name already", name);
+            continue;
+        }
+        // Only what would load here is worth bringing over
+        if let Err(e) = profile::Profile::load(&path) {
+            println!("✗ profile {}: {:#}", name, e);
+            continue;
+        }
+        let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        fsutil::write_atomic(&target, &contents)?;
+        println!("✓ profile {}", name);
+    }
+    Ok(())
+}
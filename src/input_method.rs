@@ -0,0 +1,304 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+
+use crate::{config, exec, fsutil, locale_info, log};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Framework {
+    Fcitx5,
+    Ibus,
+}
+
+pub struct Engine {
+    pub name: String,
+    pub label: String,
+}
+
+fn command_stdout(program: &str, args: &[&str]) -> Option<String> {
+    let output = exec::local(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl Framework {
+    /// Finds the running input method framework, preferring fcitx5.
+    pub fn detect() -> Option<Framework> {
+        // fcitx5-remote prints 0 when the daemon isn't running
+        if let Some(state) = command_stdout("fcitx5-remote", &[])
+            && state != "0"
+        {
+            return Some(Framework::Fcitx5);
+        }
+        if command_stdout("ibus", &["engine"]).is_some() {
+            return Some(Framework::Ibus);
+        }
+        None
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Framework::Fcitx5 => "fcitx5",
+            Framework::Ibus => "ibus",
+        }
+    }
+
+    pub fn active_engine(self) -> Option<String> {
+        match self {
+            Framework::Fcitx5 => command_stdout("fcitx5-remote", &["-n"]),
+            Framework::Ibus => command_stdout("ibus", &["engine"]),
+        }
+    }
+
+    /// Engines the user has configured, in their configured order.
+    pub fn engines(self) -> Vec<Engine> {
+        match self {
+            Framework::Fcitx5 => fcitx5_engines(),
+            Framework::Ibus => ibus_engines(),
+        }
+    }
+
+    pub fn switch_engine(self, engine: &str) -> Result<()> {
+        let result = match self {
+            Framework::Fcitx5 => log::run(exec::local("fcitx5-remote").args(["-s", engine])),
+            Framework::Ibus => log::run(exec::local("ibus").args(["engine", engine])),
+        };
+        match result {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                bail!("Failed to switch input method: {}", error.trim())
+            }
+            Err(e) => bail!("Failed to execute {}: {}", self.name(), e),
+        }
+    }
+}
+
+/// A minimal INI document that keeps section and key order intact, which
+/// is all fcitx5's profile needs.
+#[derive(Default)]
+pub struct Ini {
+    pub sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Ini {
+    pub fn parse(contents: &str) -> Ini {
+        let mut ini = Ini::default();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                ini.sections.push((name.to_string(), Vec::new()));
+            } else if let Some((key, value)) = line.split_once('=')
+                && let Some((_, entries)) = ini.sections.last_mut()
+            {
+                entries.push((key.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        ini
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections
+            .iter()
+            .find(|(name, _)| name == section)?
+            .1
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let Some((_, entries)) = self.sections.iter_mut().find(|(name, _)| name == section) else {
+            self.sections.push((section.to_string(), vec![(key.to_string(), value.to_string())]));
+            return;
+        };
+        match entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, entries) in &self.sections {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("[{}]\n", name));
+            for (key, value) in entries {
+                out.push_str(&format!("{}={}\n", key, value));
+            }
+        }
+        out
+    }
+}
+
+pub fn fcitx5_profile_path() -> PathBuf {
+    config::xdg_config_home().join("fcitx5").join("profile")
+}
+
+/// Index of the group fcitx5 activates first, i.e. the "N" in "Groups/N".
+fn fcitx5_active_group(ini: &Ini) -> Option<String> {
+    let group = ini.get("GroupOrder", "0")?;
+    let index = ini
+        .sections
+        .iter()
+        .filter_map(|(name, _)| name.strip_prefix("Groups/").filter(|rest| !rest.contains('/')))
+        .find(|index| ini.get(&format!("Groups/{}", index), "Name") == Some(group))
+        .unwrap_or("0");
+    Some(index.to_string())
+}
+
+fn fcitx5_item_sections(ini: &Ini, group: &str) -> Vec<String> {
+    let item_prefix = format!("Groups/{}/Items/", group);
+    ini.sections
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(&item_prefix))
+        .cloned()
+        .collect()
+}
+
+fn load_fcitx5_profile() -> Result<Ini> {
+    let path = fcitx5_profile_path();
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Ini::parse(&contents)),
+        Err(e) => bail!("Failed to read {}: {}", path.display(), e),
+    }
+}
+
+fn fcitx5_engines() -> Vec<Engine> {
+    let Ok(ini) = load_fcitx5_profile() else {
+        return Vec::new();
+    };
+    // Only the first group is active at a time; list its items in order
+    let Some(group) = fcitx5_active_group(&ini) else {
+        return Vec::new();
+    };
+    fcitx5_item_sections(&ini, &group)
+        .iter()
+        .filter_map(|section| ini.get(section, "Name"))
+        .map(|name| Engine {
+            name: name.to_string(),
+            label: fcitx5_engine_label(name),
+        })
+        .collect()
+}
+
+/// The fcitx5 engine needed to type a locale's script, for languages where
+/// a keyboard layout alone isn't enough.
+pub fn engine_for_locale(locale_code: &str) -> Option<&'static str> {
+    let parts = locale_info::split_locale_code(locale_code);
+    let engine = match (parts.language, parts.territory) {
+        ("zh", Some("TW")) => "chewing",
+        ("zh", _) => "pinyin",
+        ("ja", _) => "mozc",
+        ("ko", _) => "hangul",
+        ("vi", _) => "unikey",
+        _ => return None,
+    };
+    Some(engine)
+}
+
+pub fn fcitx5_has_engine(engine: &str) -> bool {
+    fcitx5_engines().iter().any(|e| e.name == engine)
+}
+
+fn save_fcitx5_profile(ini: &Ini) -> Result<()> {
+    fsutil::write_atomic(&fcitx5_profile_path(), &ini.render())?;
+    // fcitx5 rewrites the profile from memory on exit, so make it pick up
+    // our version now rather than clobbering it later
+    let _ = log::run(exec::local("fcitx5-remote").arg("-r"));
+    Ok(())
+}
+
+pub fn fcitx5_add_engine(engine: &str) -> Result<()> {
+    let mut ini = load_fcitx5_profile()?;
+    let Some(group) = fcitx5_active_group(&ini) else {
+        bail!("The fcitx5 profile has no input method group");
+    };
+    let items = fcitx5_item_sections(&ini, &group);
+    if items.iter().any(|section| ini.get(section, "Name") == Some(engine)) {
+        return Ok(());
+    }
+    let new_section = (
+        format!("Groups/{}/Items/{}", group, items.len()),
+        vec![("Name".to_string(), engine.to_string()), ("Layout".to_string(), String::new())],
+    );
+    // Keep the group's items together, right after the last existing one
+    let position = items
+        .last()
+        .and_then(|last| ini.sections.iter().position(|(name, _)| name == last))
+        .map(|index| index + 1)
+        .unwrap_or(ini.sections.len());
+    ini.sections.insert(position, new_section);
+    save_fcitx5_profile(&ini)
+}
+
+pub fn fcitx5_remove_engine(engine: &str) -> Result<()> {
+    let mut ini = load_fcitx5_profile()?;
+    let Some(group) = fcitx5_active_group(&ini) else {
+        bail!("The fcitx5 profile has no input method group");
+    };
+    let items = fcitx5_item_sections(&ini, &group);
+    let names: Vec<&str> = items.iter().filter_map(|section| ini.get(section, "Name")).collect();
+    if engine.starts_with("keyboard-")
+        && names.iter().filter(|name| name.starts_with("keyboard-")).count() <= 1
+    {
+        bail!("fcitx5 needs at least one keyboard layout engine");
+    }
+    let Some(removed) = names.iter().position(|name| *name == engine) else {
+        return Ok(());
+    };
+    ini.sections.retain(|(name, _)| name != &items[removed]);
+    let group_section = format!("Groups/{}", group);
+    if ini.get(&group_section, "DefaultIM") == Some(engine) {
+        ini.set(&group_section, "DefaultIM", "");
+    }
+    // Item numbers must stay contiguous
+    for (index, section) in items.iter().enumerate().skip(removed + 1) {
+        if let Some((name, _)) = ini.sections.iter_mut().find(|(name, _)| name == section) {
+            *name = format!("Groups/{}/Items/{}", group, index - 1);
+        }
+    }
+    save_fcitx5_profile(&ini)
+}
+
+fn fcitx5_engine_label(name: &str) -> String {
+    match name.strip_prefix("keyboard-") {
+        Some(layout) => format!("Keyboard ({})", layout),
+        None => name.to_string(),
+    }
+}
+
+fn ibus_engines() -> Vec<Engine> {
+    // preload-engines is a GVariant string array: ['xkb:us::eng', 'mozc-jp']
+    let Some(preload) = command_stdout("gsettings", &["get", "org.freedesktop.ibus.general", "preload-engines"]) else {
+        return Vec::new();
+    };
+    let labels = command_stdout("ibus", &["list-engine"]).unwrap_or_default();
+    preload
+        .trim_start_matches("@as")
+        .trim()
+        .trim_matches(['[', ']'])
+        .split(',')
+        .map(|name| name.trim().trim_matches('\'').to_string())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            // list-engine lines look like "  mozc-jp - Mozc"
+            let label = labels
+                .lines()
+                .filter_map(|line| line.trim().split_once(" - "))
+                .find(|(engine, _)| *engine == name)
+                .map(|(_, label)| label.to_string())
+                .unwrap_or_else(|| name.clone());
+            Engine { name, label }
+        })
+        .collect()
+}
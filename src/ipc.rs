@@ -0,0 +1,89 @@
+//! The daemon's control socket. A client writes one request line per
+//! connection: `state` is answered with the current layout and locale as a
+//! JSON line, and `subscribe` keeps the connection open and gets a new line
+//! on every change, so bars and scripts don't each have to poll.
+//!
+//! The TUI has a socket of its own, which keeps it to one instance: a second
+//! launch asks the first to come forward and exits.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+
+use crate::config;
+
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+        Some(dir) => dir.join("levocale.sock"),
+        None => config::xdg_state_home().join("levocale").join("daemon.sock"),
+    }
+}
+
+/// Takes over the socket, clearing one left behind by a daemon that died,
+/// but not one a running daemon still answers on.
+pub fn bind() -> Result<UnixListener> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        bail!("Another daemon is already listening on {}", path.display());
+    }
+    bind_at(&path)
+}
+
+fn bind_at(path: &Path) -> Result<UnixListener> {
+    let _ = fs::remove_file(path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    UnixListener::bind(path).with_context(|| format!("Failed to listen on {}", path.display()))
+}
+
+pub fn instance_path() -> PathBuf {
+    socket_path().with_file_name("levocale-tui.sock")
+}
+
+/// Becomes the running TUI, or when there is one already, asks it to come
+/// forward and returns None.
+pub fn claim_instance() -> Result<Option<UnixListener>> {
+    let path = instance_path();
+    if let Ok(mut stream) = UnixStream::connect(&path) {
+        writeln!(stream, "raise").context("Failed to reach the running levocale")?;
+        return Ok(None);
+    }
+    let listener = bind_at(&path)?;
+    // Checked between frames, so it mustn't wait for a connection
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+/// Whether another launch asked this instance to come forward since the
+/// last call.
+pub fn raise_requested(listener: &UnixListener) -> bool {
+    let mut raise = false;
+    while let Ok((stream, _)) = listener.accept() {
+        // On macOS and the BSDs the stream inherits the listener's
+        // O_NONBLOCK, and the request may not be there yet
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(200)));
+        raise |= read_request(&stream).as_deref() == Some("raise");
+    }
+    raise
+}
+
+/// The request a client opened with.
+pub fn read_request(stream: &UnixStream) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).ok()?;
+    Some(line.trim().to_string())
+}
+
+/// Asks a running daemon for updates, returning its replies line by line;
+/// None when no daemon is listening.
+pub fn subscribe() -> Option<impl Iterator<Item = String>> {
+    let mut stream = UnixStream::connect(socket_path()).ok()?;
+    writeln!(stream, "subscribe").ok()?;
+    Some(BufReader::new(stream).lines().map_while(Result::ok))
+}
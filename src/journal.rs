@@ -0,0 +1,64 @@
+//! journald's native protocol: entries of named fields sent as datagrams to
+//! its socket, which keeps them searchable where plain lines on stderr
+//! would only become a MESSAGE.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixDatagram;
+use std::panic::Location;
+use std::sync::OnceLock;
+
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Whether stderr goes to the journal, as it does for a systemd service.
+/// systemd says so in JOURNAL_STREAM as the stream's "device:inode", which
+/// children with stderr sent elsewhere inherit, so it has to match.
+pub fn connected() -> bool {
+    static CONNECTED: OnceLock<bool> = OnceLock::new();
+    *CONNECTED.get_or_init(|| {
+        let Ok(stream) = std::env::var("JOURNAL_STREAM") else {
+            return false;
+        };
+        let Ok(stderr) = fs::metadata("/proc/self/fd/2") else {
+            return false;
+        };
+        stream == format!("{}:{}", stderr.dev(), stderr.ino())
+    })
+}
+
+/// Sends one entry. Without a journal to take it there's nothing to do.
+pub fn send(fields: &[(&str, &str)]) {
+    if let Ok(socket) = UnixDatagram::unbound() {
+        let _ = socket.send_to(&entry(fields), SOCKET);
+    }
+}
+
+/// The CODE_FILE, CODE_LINE and CODE_MODULE fields for `location`, with
+/// the module worked out from the file, e.g. "levocale::backend::localed"
+/// for src/backend/localed.rs.
+pub fn code_fields(location: &Location) -> [(&'static str, String); 3] {
+    let path = location.file().trim_start_matches("src/").trim_end_matches(".rs");
+    let module = match path {
+        "main" => "levocale".to_string(),
+        path => format!("levocale::{}", path.trim_end_matches("/mod").replace('/', "::")),
+    };
+    [("CODE_FILE", location.file().to_string()), ("CODE_LINE", location.line().to_string()), ("CODE_MODULE", module)]
+}
+
+/// `fields` as `NAME=value` lines, except that a value with a newline in
+/// it follows its name as a little-endian 64-bit length and the raw bytes.
+fn entry(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
@@ -0,0 +1,294 @@
+//! Compiled XKB keymaps: what each key of a layout actually produces,
+//! as resolved by libxkbcommon through `xkbcli compile-keymap`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+
+use crate::backend::{KeyboardBackend, X11};
+use crate::{exec, xkb};
+
+/// Keysyms 0x20-0x7e and 0xa0-0xff are their own code points, so their
+/// names are listed in code point order.
+const ASCII_NAMES: &str = "space exclam quotedbl numbersign dollar percent ampersand apostrophe parenleft \
+    parenright asterisk plus comma minus period slash 0 1 2 3 4 5 6 7 8 9 colon semicolon less equal greater \
+    question at A B C D E F G H I J K L M N O P Q R S T U V W X Y Z bracketleft backslash bracketright \
+    asciicircum underscore grave a b c d e f g h i j k l m n o p q r s t u v w x y z braceleft bar braceright \
+    asciitilde";
+const LATIN1_NAMES: &str = "nobreakspace exclamdown cent sterling currency yen brokenbar section diaeresis \
+    copyright ordfeminine guillemotleft notsign hyphen registered macron degree plusminus twosuperior \
+    threesuperior acute mu paragraph periodcentered cedilla onesuperior masculine guillemotright onequarter \
+    onehalf threequarters questiondown Agrave Aacute Acircumflex Atilde Adiaeresis Aring AE Ccedilla Egrave \
+    Eacute Ecircumflex Ediaeresis Igrave Iacute Icircumflex Idiaeresis ETH Ntilde Ograve Oacute Ocircumflex \
+    Otilde Odiaeresis multiply Oslash Ugrave Uacute Ucircumflex Udiaeresis Yacute THORN ssharp agrave aacute \
+    acircumflex atilde adiaeresis aring ae ccedilla egrave eacute ecircumflex ediaeresis igrave iacute \
+    icircumflex idiaeresis eth ntilde ograve oacute ocircumflex otilde odiaeresis division oslash ugrave \
+    uacute ucircumflex udiaeresis yacute thorn ydiaeresis";
+
+/// Dead keys, shown as the spacing form of their accent.
+const DEAD_KEYS: &[(&str, char)] = &[
+    ("dead_grave", '`'),
+    ("dead_acute", '´'),
+    ("dead_circumflex", '^'),
+    ("dead_tilde", '~'),
+    ("dead_macron", '¯'),
+    ("dead_breve", '˘'),
+    ("dead_abovedot", '˙'),
+    ("dead_diaeresis", '¨'),
+    ("dead_abovering", '°'),
+    ("dead_doubleacute", '˝'),
+    ("dead_caron", 'ˇ'),
+    ("dead_cedilla", '¸'),
+    ("dead_ogonek", '˛'),
+];
+
+/// The alphanumeric block, row by row from the number row down, as XKB key
+/// names. LSGT is the extra key left of Z on ISO keyboards.
+const ROWS: [&[&str]; 4] = [
+    &["TLDE", "AE01", "AE02", "AE03", "AE04", "AE05", "AE06", "AE07", "AE08", "AE09", "AE10", "AE11", "AE12"],
+    &["AD01", "AD02", "AD03", "AD04", "AD05", "AD06", "AD07", "AD08", "AD09", "AD10", "AD11", "AD12"],
+    &["AC01", "AC02", "AC03", "AC04", "AC05", "AC06", "AC07", "AC08", "AC09", "AC10", "AC11", "BKSL"],
+    &["LSGT", "AB01", "AB02", "AB03", "AB04", "AB05", "AB06", "AB07", "AB08", "AB09", "AB10"],
+];
+
+/// How far each row starts from the left edge, in characters; keys are
+/// three wide, so this gives the usual stagger.
+const ROW_INDENT: [usize; 4] = [0, 4, 5, 4];
+/// The same stagger in key widths, as on a real ISO keyboard, for images.
+const ROW_OFFSET: [f64; 4] = [0.0, 1.5, 1.75, 1.25];
+/// Side of a key in an image, in pixels, and the space around it.
+const KEY_SIZE: f64 = 60.0;
+const KEY_GAP: f64 = 4.0;
+const MARGIN: f64 = 20.0;
+const TITLE_HEIGHT: f64 = 40.0;
+
+pub struct Keymap {
+    /// The keysym names bound to each key, by key name without the angle
+    /// brackets, one per shift level of the first group.
+    keys: HashMap<String, Vec<String>>,
+    /// Names of the layout groups, e.g. "German (no dead keys)".
+    pub groups: Vec<String>,
+    /// Each section with the components it was built from, e.g.
+    /// ("symbols", "pc+de(nodeadkeys)+inet(evdev)").
+    pub components: Vec<(String, String)>,
+}
+
+/// Compiles a layout with libxkbcommon, the way compositors would load it.
+pub fn compile(layout: &str, variant: Option<&str>, options: &str) -> Result<String> {
+    let mut command = exec::local("xkbcli");
+    command.args(["compile-keymap", "--layout", layout]);
+    if let Some(variant) = variant {
+        command.args(["--variant", variant]);
+    }
+    if !options.is_empty() {
+        command.args(["--options", options]);
+    }
+    let output = command.output().context("xkbcli not found (it comes with libxkbcommon's tools)")?;
+    if !output.status.success() {
+        bail!("xkbcli couldn't compile {}: {}", layout, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The keymap `keyboard` is using: the keymap file it loaded, what the X
+/// server holds, or else its layout and options compiled.
+pub fn current(keyboard: &dyn KeyboardBackend) -> Result<String> {
+    if let Some(path) = keyboard.keymap_file() {
+        return fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path));
+    }
+    if keyboard.name() == "x11" {
+        return X11::dump_keymap();
+    }
+    let layout = keyboard.current_layout().context("Couldn't read the current layout")?;
+    let (layout, variant) = xkb::split_layout_variant(&layout);
+    compile(layout, variant, &keyboard.options().unwrap_or_default())
+}
+
+impl Keymap {
+    /// Reads the xkb_symbols section of a keymap as printed by xkbcli or
+    /// `xkbcomp -xkb`.
+    pub fn parse(keymap: &str) -> Keymap {
+        let mut components = Vec::new();
+        for section in ["keycodes", "types", "compat", "symbols"] {
+            let header = format!("xkb_{} \"", section);
+            if let Some((_, rest)) = keymap.split_once(&header)
+                && let Some((name, _)) = rest.split_once('"')
+            {
+                components.push((section.to_string(), name.to_string()));
+            }
+        }
+        let groups = keymap
+            .lines()
+            .filter_map(|line| {
+                let (_, rest) = line.trim().strip_prefix("name[")?.split_once("=\"")?;
+                Some(rest.trim_end_matches(';').trim_end_matches('"').to_string())
+            })
+            .collect();
+        let symbols = keymap.find("xkb_symbols").map_or(keymap, |start| &keymap[start..]);
+        let mut keys = HashMap::new();
+        for block in symbols.split("key <").skip(1) {
+            let Some((name, rest)) = block.split_once('>') else {
+                continue;
+            };
+            // The first list in the block is group 1, whether written as
+            // `[ ... ]` or `symbols[1]= [ ... ]`
+            let body = rest.split("};").next().unwrap_or(rest);
+            let Some(list) = body.split_once("= [").or_else(|| body.split_once('[')).map(|(_, list)| list) else {
+                continue;
+            };
+            let list = list.split(']').next().unwrap_or(list);
+            let levels = list.split(',').map(|keysym| keysym.trim().to_string()).collect();
+            keys.insert(name.to_string(), levels);
+        }
+        Keymap { keys, groups, components }
+    }
+
+    fn symbol(&self, key: &str, level: usize) -> Option<char> {
+        keysym_char(self.keys.get(key)?.get(level)?)
+    }
+
+    /// An ASCII picture of the alphanumeric block: each row of keys shows
+    /// its Shift level above the base level, then AltGr below when the
+    /// layout has a third level.
+    pub fn preview(&self) -> Vec<String> {
+        let has_altgr = ROWS.iter().flat_map(|row| row.iter()).any(|key| self.symbol(key, 2).is_some());
+        let levels: &[usize] = if has_altgr { &[1, 0, 2] } else { &[1, 0] };
+        let mut lines = Vec::new();
+        for (row, indent) in ROWS.iter().zip(ROW_INDENT) {
+            // ANSI keyboards lack LSGT, which moves the rest of the row over
+            let skipped = row.iter().take_while(|key| !self.keys.contains_key(**key)).count();
+            let keys: Vec<&str> = row.iter().copied().filter(|key| self.keys.contains_key(*key)).collect();
+            if keys.is_empty() {
+                continue;
+            }
+            for &level in levels {
+                let mut line = " ".repeat(indent + 3 * skipped);
+                for key in &keys {
+                    let symbol = self.symbol(key, level).map_or(" ".to_string(), |c| c.to_string());
+                    line.push_str(&format!("|{:^2}", symbol));
+                }
+                line.push('|');
+                lines.push(line);
+            }
+            lines.push(String::new());
+        }
+        lines.pop();
+        lines
+    }
+
+    /// An SVG diagram of the alphanumeric block for printing, each key
+    /// showing Shift and base on its left and the AltGr levels on its right
+    /// the way keycaps are labelled.
+    pub fn svg(&self, title: &str) -> String {
+        let width = 2.0 * MARGIN + 14.0 * KEY_SIZE;
+        let height = 2.0 * MARGIN + TITLE_HEIGHT + ROWS.len() as f64 * KEY_SIZE;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+             font-family=\"sans-serif\">\n<rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+             <text x=\"{m}\" y=\"{t}\" font-size=\"22\">{title}</text>\n",
+            w = width,
+            h = height,
+            m = MARGIN,
+            t = MARGIN + 24.0,
+            title = xml_escape(title),
+        );
+        // Shift and base on the left, AltGr+Shift and AltGr on the right
+        let labels =
+            [(1, 0.1, 0.4, "black"), (0, 0.1, 0.85, "black"), (3, 0.6, 0.4, "#2060c0"), (2, 0.6, 0.85, "#2060c0")];
+        for (row_index, (row, offset)) in ROWS.iter().zip(ROW_OFFSET).enumerate() {
+            // ANSI keyboards lack LSGT, which moves the rest of the row over
+            let skipped = row.iter().take_while(|key| !self.keys.contains_key(**key)).count();
+            let keys = row.iter().copied().filter(|key| self.keys.contains_key(*key));
+            let top = MARGIN + TITLE_HEIGHT + row_index as f64 * KEY_SIZE;
+            for (index, key) in keys.enumerate() {
+                let left = MARGIN + (offset + (skipped + index) as f64) * KEY_SIZE;
+                let side = KEY_SIZE - KEY_GAP;
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" rx=\"6\" fill=\"none\" stroke=\"black\"/>\n",
+                    left,
+                    top,
+                    s = side
+                ));
+                for (level, x, y, color) in labels {
+                    let Some(symbol) = self.symbol(key, level) else {
+                        continue;
+                    };
+                    // A letter's capital goes without saying, as does a
+                    // Shift level that's the same as the one below it
+                    let unshifted = if level % 2 == 1 { self.symbol(key, level - 1) } else { None };
+                    if unshifted.is_some_and(|below| below == symbol || below.to_uppercase().eq([symbol])) {
+                        continue;
+                    }
+                    svg.push_str(&format!(
+                        "<text x=\"{}\" y=\"{}\" font-size=\"18\" fill=\"{}\">{}</text>\n",
+                        left + x * side,
+                        top + y * side,
+                        color,
+                        xml_escape(&symbol.to_string())
+                    ));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The character a keysym types, if it types one.
+pub fn keysym_char(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c);
+    }
+    if let Some(index) = ASCII_NAMES.split_whitespace().position(|known| known == name) {
+        return char::from_u32(0x20 + index as u32);
+    }
+    if let Some(index) = LATIN1_NAMES.split_whitespace().position(|known| known == name) {
+        return char::from_u32(0xa0 + index as u32);
+    }
+    if let Some((_, c)) = DEAD_KEYS.iter().find(|(known, _)| *known == name) {
+        return Some(*c);
+    }
+    if name == "EuroSign" {
+        return Some('€');
+    }
+    // Unnamed code points are written U20AC, or 0x10020ac as a raw keysym
+    if let Some(hex) = name.strip_prefix('U')
+        && hex.len() >= 4
+        && let Ok(code) = u32::from_str_radix(hex, 16)
+    {
+        return char::from_u32(code);
+    }
+    if let Some(hex) = name.strip_prefix("0x")
+        && let Ok(keysym) = u32::from_str_radix(hex, 16)
+        && keysym >= 0x100_0000
+    {
+        return char::from_u32(keysym - 0x100_0000);
+    }
+    system_keysyms().get(name).copied()
+}
+
+/// Every named keysym with a Unicode equivalent, from the X11 headers when
+/// they're installed; covers the Cyrillic, Greek and other scripts.
+fn system_keysyms() -> &'static HashMap<String, char> {
+    static KEYSYMS: OnceLock<HashMap<String, char>> = OnceLock::new();
+    KEYSYMS.get_or_init(|| {
+        let contents = fs::read_to_string("/usr/include/X11/keysymdef.h").unwrap_or_default();
+        contents
+            .lines()
+            .filter_map(|line| {
+                // #define XK_Cyrillic_a 0x06c1  /* U+0430 CYRILLIC SMALL LETTER A */
+                let name = line.strip_prefix("#define XK_")?.split_whitespace().next()?;
+                let code = line.split("/* U+").nth(1)?.split_whitespace().next()?;
+                let c = char::from_u32(u32::from_str_radix(code, 16).ok()?)?;
+                Some((name.to_string(), c))
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,185 @@
+//! Key bindings for the main menu, remappable through the `[keys]` config
+//! table. Overlays such as prompts keep their fixed keys.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Result, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Select,
+    Toggle,
+    Search,
+    Refresh,
+    Retry,
+    Quit,
+}
+
+impl Action {
+    const ALL: [Action; 8] = [
+        Action::Up,
+        Action::Down,
+        Action::Select,
+        Action::Toggle,
+        Action::Search,
+        Action::Refresh,
+        Action::Retry,
+        Action::Quit,
+    ];
+
+    /// The setting's name in `[keys]`.
+    fn name(self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Select => "select",
+            Action::Toggle => "toggle",
+            Action::Search => "search",
+            Action::Refresh => "refresh",
+            Action::Retry => "retry",
+            Action::Quit => "quit",
+        }
+    }
+
+    fn configured(self, keys: &config::Keys) -> &[String] {
+        match self {
+            Action::Up => &keys.up,
+            Action::Down => &keys.down,
+            Action::Select => &keys.select,
+            Action::Toggle => &keys.toggle,
+            Action::Search => &keys.search,
+            Action::Refresh => &keys.refresh,
+            Action::Retry => &keys.retry,
+            Action::Quit => &keys.quit,
+        }
+    }
+}
+
+/// Keys the menu handles itself, which can't be bound to anything else.
+const FIXED: &[&str] = &[
+    "a", "A", "c", "C", "D", "e", "g", "i", "L", "n", "P", "s", "S", "t", "T", "v", "x", "y", "+", "-",
+    "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    "tab", "backtab", "delete", "ctrl+c", "ctrl+d",
+];
+
+type Key = (KeyCode, KeyModifiers);
+
+/// Reads a key as written in the config, e.g. "k", "enter", "ctrl+n" or
+/// "f5".
+fn parse(spec: &str) -> Result<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = spec;
+    // "+" on its own is the plus key, not a modifier separator
+    while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            other => bail!("Unknown modifier {:?} in key {:?}", other, spec),
+        };
+        rest = key;
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => match name.strip_prefix('f').and_then(|number| number.parse().ok()) {
+                Some(number @ 1..=12) => KeyCode::F(number),
+                _ => bail!("Unknown key {:?}", spec),
+            },
+        },
+    };
+    Ok((code, modifiers))
+}
+
+/// How a key is shown in the controls line, e.g. "↑" or "Ctrl+N".
+fn label(spec: &str) -> String {
+    let mut parts: Vec<String> = spec.split('+').filter(|part| !part.is_empty()).map(str::to_string).collect();
+    if parts.is_empty() {
+        return spec.to_string();
+    }
+    let key = parts.pop().unwrap_or_default();
+    let key = match key.to_lowercase().as_str() {
+        "up" => "↑".to_string(),
+        "down" => "↓".to_string(),
+        "left" => "←".to_string(),
+        "right" => "→".to_string(),
+        name if key.chars().count() > 1 => {
+            let mut chars = name.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+        }
+        _ => key,
+    };
+    parts.iter().map(|modifier| format!("{}+", label(modifier))).collect::<String>() + &key
+}
+
+pub struct Bindings {
+    actions: HashMap<Key, Action>,
+    /// The keys bound to each action, as written in the config.
+    specs: HashMap<Action, Vec<String>>,
+}
+
+impl Bindings {
+    /// Resolves the `[keys]` table, refusing unknown keys and any key bound
+    /// twice, whether to two actions or to one the menu already uses.
+    pub fn new(keys: &config::Keys) -> Result<Bindings> {
+        let fixed = FIXED.iter().map(|spec| parse(spec)).collect::<Result<HashSet<Key>>>()?;
+        let mut bindings = Bindings { actions: HashMap::new(), specs: HashMap::new() };
+        let mut bound_as: HashMap<Key, &str> = HashMap::new();
+        for action in Action::ALL {
+            for spec in action.configured(keys) {
+                let key = parse(spec)?;
+                if fixed.contains(&key) {
+                    bail!("[keys] {} = {:?} is already a built-in key", action.name(), spec);
+                }
+                if let Some(other) = bound_as.insert(key, action.name())
+                    && other != action.name()
+                {
+                    bail!("[keys] {:?} is bound to both {} and {}", spec, other, action.name());
+                }
+                bindings.actions.insert(key, action);
+                bindings.specs.entry(action).or_default().push(spec.clone());
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        // Shift is part of the character already, e.g. 'C'
+        let modifiers = key.modifiers - KeyModifiers::SHIFT;
+        self.actions.get(&(key.code, modifiers)).copied()
+    }
+
+    /// The keys to mention for `action` in the controls line, e.g. "q/Esc".
+    pub fn label(&self, action: Action) -> String {
+        match self.specs.get(&action) {
+            Some(specs) => specs.iter().map(|spec| label(spec)).collect::<Vec<_>>().join("/"),
+            None => "(unbound)".to_string(),
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings::new(&config::Keys::default()).expect("the default keys are valid")
+    }
+}
@@ -0,0 +1,200 @@
+//! `levocale listen`: prints the layout and locale whenever either changes,
+//! one line at a time, for status bars that follow a command's output such
+//! as eww's `deflisten` or waybar's custom modules. `levocale watch` is the
+//! same for people: timestamped lines saying what changed.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{backend, daemon, exec, ipc};
+
+/// How often to look again when nothing announces changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct State {
+    pub layout: String,
+    pub locale: String,
+}
+
+impl State {
+    pub fn current() -> State {
+        State {
+            layout: backend::active().current_layout().unwrap_or_default(),
+            locale: backend::active_locale().current_locale().unwrap_or_default(),
+        }
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Hyprland announces every layout switch on its event socket.
+fn watch_hyprland(ticks: mpsc::Sender<()>) -> bool {
+    let Ok(stream) = daemon::hyprland_socket().and_then(|path| Ok(UnixStream::connect(path)?)) else {
+        return false;
+    };
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            // activelayout>>at-translated-set-2-keyboard,German
+            if line.starts_with("activelayout>>") && ticks.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    true
+}
+
+/// localed signals a property change whenever the system locale is set.
+fn watch_localed(ticks: mpsc::Sender<()>) -> bool {
+    let monitor = exec::local("gdbus")
+        .args(["monitor", "--system", "--dest", "org.freedesktop.locale1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut monitor) = monitor else {
+        return false;
+    };
+    let Some(stdout) = monitor.stdout.take() else {
+        return false;
+    };
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.contains(".PropertiesChanged") && ticks.send(()).is_err() {
+                break;
+            }
+        }
+        let _ = monitor.kill();
+    });
+    true
+}
+
+/// Sends a tick whenever the layout or locale may have changed: on
+/// Hyprland's and localed's events where they're available, and every few
+/// seconds otherwise.
+pub fn watch(ticks: mpsc::Sender<()>) {
+    let hyprland = backend::active().name() == "hyprland" && watch_hyprland(ticks.clone());
+    let localed = watch_localed(ticks.clone());
+    if hyprland && localed {
+        return;
+    }
+    thread::spawn(move || {
+        while ticks.send(()).is_ok() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// Calls `changed` with the previous state, if any, and the new one each
+/// time the layout or locale changes, until it fails.
+fn follow(mut changed: impl FnMut(Option<&State>, &State) -> io::Result<()>) -> Result<()> {
+    let mut last: Option<State> = None;
+    // A running daemon is watching already; the remote locale is ours to watch
+    if exec::remote_host().is_none()
+        && let Some(updates) = ipc::subscribe()
+    {
+        for line in updates {
+            let Ok(state) = serde_json::from_str::<State>(&line) else {
+                continue;
+            };
+            // Whoever reads the output went away, and with it the reason to run
+            if changed(last.as_ref(), &state).is_err() {
+                return Ok(());
+            }
+            last = Some(state);
+        }
+        // The daemon stopped; carry on without it
+    }
+    let (sender, ticks) = mpsc::channel();
+    watch(sender);
+    loop {
+        let state = State::current();
+        if last.as_ref() != Some(&state) {
+            if changed(last.as_ref(), &state).is_err() {
+                return Ok(());
+            }
+            last = Some(state);
+        }
+        if ticks.recv().is_err() {
+            return Ok(());
+        }
+    }
+}
+
+pub fn run(json: bool) -> Result<()> {
+    let mut stdout = io::stdout();
+    follow(|_, state| {
+        let line = if json { state.json() } else { format!("{} {}", state.layout, state.locale) };
+        writeln!(stdout, "{}", line)
+    })
+}
+
+pub const DEFAULT_WATCH_FORMAT: &str = "{time} {changed}";
+
+/// The current time in UTC, e.g. "2024-05-01T12:03:04.250Z".
+pub fn timestamp() -> String {
+    let since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, time) = ((seconds / 86_400) as i64, seconds % 86_400);
+    // Howard Hinnant's days-to-civil conversion
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// `levocale watch`: a line per change in `format`, where {time},
+/// {changed}, {layout} and {locale} are filled in.
+pub fn watch_changes(format: &str) -> Result<()> {
+    let mut stdout = io::stdout();
+    follow(|last, state| {
+        let changed = match last {
+            None => format!("started with layout {}, locale {}", state.layout, state.locale),
+            Some(last) => {
+                let mut changes = Vec::new();
+                if last.layout != state.layout {
+                    changes.push(format!("layout {} → {}", last.layout, state.layout));
+                }
+                if last.locale != state.locale {
+                    changes.push(format!("locale {} → {}", last.locale, state.locale));
+                }
+                changes.join(", ")
+            }
+        };
+        let line = format
+            .replace("{time}", &timestamp())
+            .replace("{changed}", &changed)
+            .replace("{layout}", &state.layout)
+            .replace("{locale}", &state.locale);
+        writeln!(stdout, "{}", line)
+    })
+}
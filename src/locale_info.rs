@@ -0,0 +1,431 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{exec, log};
+
+pub const LC_CATEGORIES: [&str; 12] = [
+    "LC_CTYPE",
+    "LC_NUMERIC",
+    "LC_TIME",
+    "LC_COLLATE",
+    "LC_MONETARY",
+    "LC_MESSAGES",
+    "LC_PAPER",
+    "LC_NAME",
+    "LC_ADDRESS",
+    "LC_TELEPHONE",
+    "LC_MEASUREMENT",
+    "LC_IDENTIFICATION",
+];
+
+const LOCALE_DIR: &str = "/usr/lib/locale";
+const LOCALE_ARCHIVE: &str = "/usr/lib/locale/locale-archive";
+/// The first word of a locale-archive, in the machine's byte order.
+const ARCHIVE_MAGIC: u32 = 0xde02_0109;
+/// Where musl looks for message translations unless MUSL_LOCPATH says
+/// otherwise; Alpine's musl-locales installs them here.
+pub const MUSL_LOCPATH: &str = "/usr/share/i18n/locales/musl";
+/// Locale definitions and charmaps, the sources localedef compiles from.
+const I18N_LOCALES: &str = "/usr/share/i18n/locales";
+const I18N_CHARMAPS: &str = "/usr/share/i18n/charmaps";
+/// glibc's list of every locale its sources can build.
+const SUPPORTED: &str = "/usr/share/i18n/SUPPORTED";
+
+pub struct LocaleParts<'a> {
+    pub language: &'a str,
+    pub territory: Option<&'a str>,
+    pub codeset: Option<&'a str>,
+    pub modifier: Option<&'a str>,
+}
+
+pub fn split_locale_code(locale_code: &str) -> LocaleParts<'_> {
+    let (rest, modifier) = match locale_code.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale_code, None),
+    };
+    let (rest, codeset) = match rest.split_once('.') {
+        Some((rest, codeset)) => (rest, Some(codeset)),
+        None => (rest, None),
+    };
+    let (language, territory) = match rest.split_once('_') {
+        Some((language, territory)) => (language, Some(territory)),
+        None => (rest, None),
+    };
+    LocaleParts { language, territory, codeset, modifier }
+}
+
+/// Why a locale name was refused before it reached a backend.
+#[derive(Debug)]
+pub enum LocaleError {
+    /// Doesn't follow the language[_territory][.codeset][@modifier] grammar.
+    Malformed { locale: String, problem: &'static str },
+    /// Well-formed, but neither installed nor offered by the backend.
+    Unavailable(String),
+}
+
+impl fmt::Display for LocaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocaleError::Malformed { locale, problem } => write!(f, "{:?} is not a locale name: {}", locale, problem),
+            LocaleError::Unavailable(locale) => write!(f, "{} isn't available on this system", locale),
+        }
+    }
+}
+
+impl std::error::Error for LocaleError {}
+
+/// Checks a locale name against the language[_territory][.codeset][@modifier]
+/// grammar, also allowing a script between language and territory as in
+/// FreeBSD's "zh_Hans_CN.UTF-8".
+pub fn check_syntax(locale_code: &str) -> Result<(), LocaleError> {
+    let malformed = |problem| Err(LocaleError::Malformed { locale: locale_code.to_string(), problem });
+    if locale_code.is_empty() {
+        return malformed("it is empty");
+    }
+    let parts = split_locale_code(locale_code);
+    let is_name = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if matches!(parts.language, "C" | "POSIX") {
+        if parts.territory.is_some() {
+            return malformed("C and POSIX take no territory");
+        }
+    } else if !(2..=3).contains(&parts.language.len()) || !parts.language.chars().all(|c| c.is_ascii_lowercase()) {
+        return malformed("the language should be two or three lowercase letters, e.g. \"de\"");
+    }
+    if let Some(territory) = parts.territory {
+        let (script, territory) = match territory.split_once('_') {
+            Some((script, territory)) => (Some(script), territory),
+            None => (None, territory),
+        };
+        if let Some(script) = script {
+            let mut chars = script.chars();
+            let titlecase = chars.next().is_some_and(|c| c.is_ascii_uppercase()) && chars.all(|c| c.is_ascii_lowercase());
+            if script.len() != 4 || !titlecase {
+                return malformed("a script should be four letters, e.g. \"Latn\"");
+            }
+        }
+        let letters = territory.len() == 2 && territory.chars().all(|c| c.is_ascii_uppercase());
+        let digits = territory.len() == 3 && territory.chars().all(|c| c.is_ascii_digit());
+        if !letters && !digits {
+            return malformed("the territory should be two capital letters, e.g. \"DE\"");
+        }
+    }
+    if parts.codeset.is_some_and(|codeset| !is_name(codeset)) {
+        return malformed("the codeset after '.' should be a name like \"UTF-8\"");
+    }
+    if parts.modifier.is_some_and(|modifier| !is_name(modifier)) {
+        return malformed("the modifier after '@' should be a name like \"euro\"");
+    }
+    Ok(())
+}
+
+/// Normalizes a codeset the way glibc does when naming locale directories,
+/// e.g. "UTF-8" becomes "utf8" and "8859-1" becomes "iso88591".
+pub fn normalize_codeset(codeset: &str) -> String {
+    let normalized: String = codeset
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if normalized.chars().all(|c| c.is_ascii_digit()) {
+        format!("iso{}", normalized)
+    } else {
+        normalized
+    }
+}
+
+pub fn is_utf8(codeset: &str) -> bool {
+    normalize_codeset(codeset) == "utf8"
+}
+
+/// The on-disk name glibc uses for a locale, with the codeset normalized.
+pub fn normalized_locale_name(locale_code: &str) -> String {
+    let parts = split_locale_code(locale_code);
+    let mut name = parts.language.to_string();
+    if let Some(territory) = parts.territory {
+        name.push('_');
+        name.push_str(territory);
+    }
+    if let Some(codeset) = parts.codeset {
+        name.push('.');
+        name.push_str(&normalize_codeset(codeset));
+    }
+    if let Some(modifier) = parts.modifier {
+        name.push('@');
+        name.push_str(modifier);
+    }
+    name
+}
+
+/// One line of `locale` output.
+pub struct LocaleVariable {
+    pub name: String,
+    /// Empty when unset.
+    pub value: String,
+    /// Set in the environment rather than implied by LANG or LC_ALL, which
+    /// `locale` marks by quoting the value.
+    pub explicit: bool,
+}
+
+/// The locale settings programs in this session see, as `locale` reports
+/// them: LANG, LANGUAGE, each LC_* category and LC_ALL.
+pub fn session_variables() -> Vec<LocaleVariable> {
+    let Ok(output) = exec::command::<&str>("locale", &[]).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            Some(LocaleVariable {
+                name: name.to_string(),
+                value: value.trim_matches('"').to_string(),
+                explicit: !value.is_empty() && !value.starts_with('"'),
+            })
+        })
+        .collect()
+}
+
+pub enum LocaleSource {
+    Builtin,
+    Archive,
+    Directory(PathBuf),
+    NotInstalled,
+}
+
+pub struct LocaleDetails {
+    pub charmap: Option<String>,
+    pub source: LocaleSource,
+    pub installed_categories: Vec<&'static str>,
+}
+
+/// musl's translations for `locale_code`, if there are any. Without them
+/// the locale still works, but messages stay in English.
+pub fn musl_translations(locale_code: &str) -> Option<PathBuf> {
+    let dir = std::env::var_os("MUSL_LOCPATH")
+        .filter(|_| exec::remote_host().is_none())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(MUSL_LOCPATH));
+    let path = dir.join(locale_code);
+    exec::exists(&path).then_some(path)
+}
+
+/// Locales that are generated and ready to use, as `locale -a` lists them.
+pub fn installed_locales() -> Vec<String> {
+    let Ok(output) = exec::command("locale", &["-a"]).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Every locale that could be compiled here, with its charset, e.g.
+/// ("de_DE.UTF-8", "UTF-8"). Distributions ship SUPPORTED either as
+/// "de_DE.UTF-8 UTF-8" lines or in glibc's own "de_DE.UTF-8/UTF-8 \\"
+/// makefile form; both are read.
+pub fn supported_locales() -> Vec<(String, String)> {
+    let contents = exec::read_to_string(Path::new(SUPPORTED)).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_end_matches('\\').trim();
+            // Comments, and the SUPPORTED-LOCALES= header of the makefile form
+            if line.starts_with('#') || line.contains('=') {
+                return None;
+            }
+            let (name, charset) = line.split_once(['/', ' ', '\t'])?;
+            Some((name.to_string(), charset.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Locale definitions localedef can start from, e.g. "en_DK".
+pub fn locale_sources() -> Vec<String> {
+    let mut sources: Vec<String> = exec::read_dir_names(Path::new(I18N_LOCALES))
+        .into_iter()
+        // Leave out the translit_* and iso14651_t1 files they include
+        .filter(|name| name.contains('_') && !name.starts_with("translit_") && !name.starts_with("iso"))
+        .collect();
+    sources.sort();
+    sources
+}
+
+/// Charmaps localedef knows, e.g. "UTF-8" or "ISO-8859-15".
+pub fn charmaps() -> Vec<String> {
+    let mut charmaps: Vec<String> = exec::read_dir_names(Path::new(I18N_CHARMAPS))
+        .into_iter()
+        .map(|name| name.trim_end_matches(".gz").to_string())
+        .collect();
+    charmaps.sort();
+    charmaps
+}
+
+/// Compiles `name` from the `source` definition and `charmap`. It goes in a
+/// directory of its own under /usr/lib/locale rather than the archive,
+/// which locale-gen rebuilds from scratch, and is found there like any
+/// installed locale.
+pub fn compile_locale(source: &str, charmap: &str, name: &str) -> Result<()> {
+    check_syntax(name)?;
+    let output = log::run(&mut exec::privileged(&["localedef", "--no-archive", "-i", source, "-f", charmap, name])?)
+        .context("Failed to run localedef")?;
+    if !output.status.success() {
+        bail!("localedef failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// The `index`th 32-bit word of `bytes`.
+fn word(bytes: &[u8], index: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[index * 4..index * 4 + 4]);
+    u32::from_ne_bytes(word)
+}
+
+fn read_at(file: &mut File, offset: u32, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    file.seek(SeekFrom::Start(offset.into()))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The locale names in a locale-archive, read from its name hash table the
+/// way `localedef --list-archive` does. Only the header, the table and the
+/// names are read, not the locale data, which runs to hundreds of
+/// megabytes on distributions that ship every locale.
+fn read_archive(path: &Path) -> io::Result<Vec<String>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a locale archive");
+    let mut file = File::open(path)?;
+    // magic, serial, then the offset, used count and size of the name
+    // hash table and of the string table
+    let header = read_at(&mut file, 0, 8 * 4)?;
+    if word(&header, 0) != ARCHIVE_MAGIC {
+        return Err(invalid());
+    }
+    let (table_offset, table_size) = (word(&header, 2), word(&header, 4) as usize);
+    let (strings_offset, strings_used) = (word(&header, 5), word(&header, 6) as usize);
+    // Each entry is a hash value, a name offset and a record offset, which
+    // is zero for free slots
+    let table = read_at(&mut file, table_offset, table_size * 3 * 4)?;
+    let strings = read_at(&mut file, strings_offset, strings_used)?;
+    let mut names = Vec::new();
+    for entry in table.chunks_exact(3 * 4) {
+        if word(entry, 2) == 0 {
+            continue;
+        }
+        let start = word(entry, 1).checked_sub(strings_offset).ok_or_else(invalid)? as usize;
+        let name = strings.get(start..).ok_or_else(invalid)?;
+        let end = name.iter().position(|&byte| byte == 0).ok_or_else(invalid)?;
+        names.push(String::from_utf8_lossy(&name[..end]).into_owned());
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Every compiled locale: those in the archive and those in directories of
+/// their own, without asking localectl or `locale`.
+pub fn compiled_locales() -> Vec<String> {
+    let mut locales: Vec<String> = archive_locales().into_iter().collect();
+    locales.extend(
+        exec::read_dir_names(Path::new(LOCALE_DIR))
+            .into_iter()
+            .filter(|name| exec::exists(&Path::new(LOCALE_DIR).join(name).join("LC_CTYPE"))),
+    );
+    locales.sort();
+    locales.dedup();
+    locales
+}
+
+pub fn archive_locales() -> HashSet<String> {
+    // Reading the archive beats spawning localedef, but only works here
+    if exec::remote_host().is_none()
+        && let Ok(names) = read_archive(Path::new(LOCALE_ARCHIVE))
+    {
+        return names.into_iter().collect();
+    }
+    let mut locales = HashSet::new();
+    if let Ok(output) = exec::command("localedef", &["--list-archive"]).output() {
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let line = line.trim();
+            if !line.is_empty() {
+                locales.insert(line.to_string());
+            }
+        }
+    }
+    locales
+}
+
+fn category_present(dir: &Path, category: &str) -> bool {
+    if category == "LC_MESSAGES" {
+        exec::exists(&dir.join(category).join("SYS_LC_MESSAGES"))
+    } else {
+        exec::exists(&dir.join(category))
+    }
+}
+
+/// Whether glibc can actually load `category` from an installed locale.
+/// Only that category is pointed at it, so `locale` complaining that it
+/// can't set LC_ALL means this one failed.
+fn category_loads(locale_code: &str, category: &str) -> bool {
+    let mut env = vec![("LC_ALL", ""), ("LANG", "")];
+    env.extend(LC_CATEGORIES.iter().map(|name| (*name, if *name == category { locale_code } else { "" })));
+    match exec::command_with_env::<&str>(&env, "locale", &[]).output() {
+        Ok(output) => output.stderr.is_empty(),
+        // Without `locale` there's nothing to go on, so don't cry wolf
+        Err(_) => true,
+    }
+}
+
+fn query_charmap(locale_code: &str) -> Option<String> {
+    let output = exec::command_with_env(&[("LC_ALL", locale_code)], "locale", &["charmap"]).output().ok()?;
+    // glibc warns on stderr and silently falls back to ASCII when the locale
+    // is missing, so only trust the answer when it was quiet
+    if !output.status.success() || !output.stderr.is_empty() {
+        return None;
+    }
+    let charmap = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!charmap.is_empty()).then_some(charmap)
+}
+
+pub fn locale_details(locale_code: &str, archive: &HashSet<String>) -> LocaleDetails {
+    let normalized = normalized_locale_name(locale_code);
+    let candidates = [locale_code.to_string(), normalized.clone()];
+    let directory = candidates
+        .iter()
+        .map(|name| Path::new(LOCALE_DIR).join(name))
+        .find(|path| exec::is_dir(path));
+
+    let (source, installed_categories) = if locale_code == "C" || locale_code == "POSIX" {
+        (LocaleSource::Builtin, LC_CATEGORIES.to_vec())
+    } else if let Some(dir) = directory {
+        let categories = LC_CATEGORIES
+            .iter()
+            .copied()
+            .filter(|category| category_present(&dir, category) && category_loads(locale_code, category))
+            .collect();
+        (LocaleSource::Directory(dir), categories)
+    } else if archive.contains(locale_code) || archive.contains(&normalized) {
+        // The archive stores each locale as a unit, but it can be damaged
+        let categories = LC_CATEGORIES
+            .iter()
+            .copied()
+            .filter(|category| category_loads(locale_code, category))
+            .collect();
+        (LocaleSource::Archive, categories)
+    } else {
+        (LocaleSource::NotInstalled, Vec::new())
+    };
+
+    LocaleDetails {
+        charmap: query_charmap(locale_code),
+        source,
+        installed_categories,
+    }
+}
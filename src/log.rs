@@ -0,0 +1,118 @@
+//! What levocale did recently: the commands it ran to change settings, what
+//! they printed, and the messages it showed. Kept in memory for the TUI's
+//! log screen.
+
+use std::collections::VecDeque;
+use std::io;
+use std::panic::Location;
+use std::process::{Command, Output};
+use std::sync::Mutex;
+
+use crate::{exec, journal, listen};
+
+/// Entries kept before the oldest are dropped.
+const CAPACITY: usize = 500;
+/// Lines kept of what a command printed on each stream.
+const OUTPUT_LINES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Command,
+    Output,
+    Message,
+    Error,
+}
+
+impl Kind {
+    /// The syslog level journald files it under.
+    fn priority(self) -> &'static str {
+        match self {
+            Kind::Command | Kind::Output => "7",
+            Kind::Message => "6",
+            Kind::Error => "3",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    /// UTC, e.g. "12:03:04".
+    pub time: String,
+    pub kind: Kind,
+    pub text: String,
+}
+
+static ENTRIES: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+
+/// Keeps `text` for the log screen and, when stderr is the journal, sends
+/// it there too with where it came from.
+#[track_caller]
+pub fn record(kind: Kind, text: &str) {
+    if journal::connected() {
+        let code = journal::code_fields(Location::caller());
+        let mut fields = vec![("MESSAGE", text), ("PRIORITY", kind.priority()), ("SYSLOG_IDENTIFIER", "levocale")];
+        fields.extend(code.iter().map(|(name, value)| (*name, value.as_str())));
+        journal::send(&fields);
+    }
+    let timestamp = listen::timestamp();
+    let time = timestamp.get(11..19).unwrap_or_default().to_string();
+    if let Ok(mut entries) = ENTRIES.lock() {
+        entries.push_back(Entry { time, kind, text: text.to_string() });
+        if entries.len() > CAPACITY {
+            entries.pop_front();
+        }
+    }
+}
+
+/// Reports progress from a long-running command such as the daemon: on
+/// stdout, unless it's going to the journal already.
+#[track_caller]
+pub fn info(text: &str) {
+    record(Kind::Message, text);
+    if !journal::connected() {
+        println!("{}", text);
+    }
+}
+
+/// Like [`info`], for errors on stderr.
+#[track_caller]
+pub fn error(text: &str) {
+    record(Kind::Error, text);
+    if !journal::connected() {
+        eprintln!("levocale: {}", text);
+    }
+}
+
+pub fn entries() -> Vec<Entry> {
+    ENTRIES.lock().map(|entries| entries.iter().cloned().collect()).unwrap_or_default()
+}
+
+/// The last few non-empty lines of a stream.
+fn tail(stream: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(stream);
+    let lines: Vec<&str> = text.lines().map(str::trim_end).filter(|line| !line.trim().is_empty()).collect();
+    lines[lines.len().saturating_sub(OUTPUT_LINES)..].iter().map(|line| line.to_string()).collect()
+}
+
+/// Runs `command` like [`Command::output`], recording it and its outcome.
+#[track_caller]
+pub fn run(command: &mut Command) -> io::Result<Output> {
+    let words: Vec<String> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|word| exec::shell_quote(&word.to_string_lossy()))
+        .collect();
+    record(Kind::Command, &words.join(" "));
+    let result = command.output();
+    match &result {
+        Ok(output) => {
+            for line in tail(&output.stdout).into_iter().chain(tail(&output.stderr)) {
+                record(Kind::Output, &line);
+            }
+            if !output.status.success() {
+                record(Kind::Error, &output.status.to_string());
+            }
+        }
+        Err(e) => record(Kind::Error, &e.to_string()),
+    }
+    result
+}
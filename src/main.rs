@@ -1,232 +1,2528 @@
+mod accounts;
+mod audit;
+mod backend;
+mod cli;
+mod clipboard;
+mod config;
+mod daemon;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod exec;
+mod fsutil;
+mod glob;
+mod greeter;
+mod hooks;
+mod import;
+mod input_method;
+mod ipc;
+mod journal;
+mod keymap;
+mod keys;
+mod listen;
+mod locale_info;
+mod log;
+mod managed_block;
+mod messages;
+mod nixos;
+mod notifications;
+mod plain;
+mod profile;
+mod region;
+mod seat;
+mod spellcheck;
+mod state;
+mod system;
+mod theme;
+mod translations;
+#[cfg(target_os = "linux")]
+mod tray;
+mod xkb;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
 use std::io;
-use std::process::Command;
+use std::os::unix::net::UnixListener;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use anyhow::{Result, bail};
+use std::sync::{Arc, LazyLock, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result, anyhow, bail};
 use ratatui::{
     backend::CrosstermBackend,
-    widgets::{Block, Borders, Paragraph},
-    layout::{Layout, Constraint, Direction, Alignment},
-    style::{Style, Color, Modifier},
-    Terminal,
+    widgets::{Block, Borders, Clear, Paragraph, Tabs, Wrap},
+    layout::{Layout, Constraint, Direction, Alignment, Rect},
+    Frame, Terminal, TerminalOptions, Viewport,
 };
+use ratatui::text::{Line, Span};
+use unicode_bidi::{BidiInfo, Level};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
-struct MenuItem {
-    label: String,
-    description: String,
-    action: Box<dyn Fn() -> Result<()>>,
-}
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Section {
+    /// Locales and layouts for the timezone's country, on the first run.
+    Suggested,
+    /// One of the config's `[[groups]]`, by position.
+    Group(usize),
+    Keyboard,
+    Options,
+    InputMethod,
+    Locale,
+    Profiles,
+}
+
+impl Section {
+    const ALL: [Section; 6] = [
+        Section::Suggested,
+        Section::Keyboard,
+        Section::Options,
+        Section::InputMethod,
+        Section::Locale,
+        Section::Profiles,
+    ];
+
+    fn tab_title(self) -> &'static str {
+        match self {
+            Section::Suggested => "Suggested",
+            // Named in the config; see AppState::tab_title
+            Section::Group(_) => "Group",
+            Section::Keyboard => "Keyboard",
+            Section::Options => "Options",
+            Section::InputMethod => "Input",
+            Section::Locale => "Locale",
+            Section::Profiles => "Profiles",
+        }
+    }
+
+    fn from_title(title: &str) -> Option<Section> {
+        Section::ALL.into_iter().find(|section| section.tab_title() == title)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ItemKind {
+    Header(Section),
+    Layout(String),
+    /// A compiled .xkb keymap file, by path.
+    Keymap(String),
+    ClearKeymap,
+    XkbOption(String),
+    InputMethod(String),
+    Locale(String),
+    /// A saved profile, by name.
+    Profile(String),
+}
+
+impl ItemKind {
+    fn from_favorite(entry: &str) -> Option<ItemKind> {
+        match entry.split_once(':')? {
+            ("layout", code) => Some(ItemKind::Layout(code.to_string())),
+            ("keymap", path) => Some(ItemKind::Keymap(path.to_string())),
+            ("locale", code) => Some(ItemKind::Locale(code.to_string())),
+            ("profile", name) => Some(ItemKind::Profile(name.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The favorites syntax extended to every kind of entry, for the state
+    /// file.
+    fn key(&self) -> String {
+        match self {
+            ItemKind::Header(Section::Group(index)) => format!("group:{}", index),
+            ItemKind::Header(section) => format!("section:{}", section.tab_title()),
+            ItemKind::Layout(code) => format!("layout:{}", code),
+            ItemKind::Keymap(path) => format!("keymap:{}", path),
+            ItemKind::ClearKeymap => "clear-keymap".to_string(),
+            ItemKind::XkbOption(option) => format!("option:{}", option),
+            ItemKind::InputMethod(engine) => format!("engine:{}", engine),
+            ItemKind::Locale(code) => format!("locale:{}", code),
+            ItemKind::Profile(name) => format!("profile:{}", name),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<ItemKind> {
+        if key == "clear-keymap" {
+            return Some(ItemKind::ClearKeymap);
+        }
+        match key.split_once(':')? {
+            ("section", title) => Section::from_title(title).map(ItemKind::Header),
+            ("group", index) => index.parse().ok().map(|index| ItemKind::Header(Section::Group(index))),
+            ("option", option) => Some(ItemKind::XkbOption(option.to_string())),
+            ("engine", engine) => Some(ItemKind::InputMethod(engine.to_string())),
+            _ => ItemKind::from_favorite(key),
+        }
+    }
+
+    fn code(&self) -> Option<&str> {
+        match self {
+            ItemKind::Header(_) | ItemKind::ClearKeymap => None,
+            ItemKind::Layout(code)
+            | ItemKind::Keymap(code)
+            | ItemKind::XkbOption(code)
+            | ItemKind::InputMethod(code)
+            | ItemKind::Locale(code)
+            | ItemKind::Profile(code) => Some(code),
+        }
+    }
+
+    fn apply(&self, config: &config::Config, xkb_registry: &xkb::XkbRegistry) -> Result<()> {
+        match self {
+            ItemKind::Header(_) => Ok(()),
+            ItemKind::Layout(code) => switch_to_keyboard_layout(code, config, xkb_registry),
+            ItemKind::Keymap(path) => switch_keymap_file(Some(path)),
+            ItemKind::ClearKeymap => switch_keymap_file(None),
+            ItemKind::XkbOption(option) => toggle_xkb_option(option, config),
+            ItemKind::InputMethod(engine) => switch_input_method(engine),
+            ItemKind::Locale(code) => set_locale(code),
+            ItemKind::Profile(name) => apply_profile(name, config),
+        }
+    }
+
+    /// What applying this changes for the `[hooks]`, and the value it
+    /// switches to.
+    fn change(&self) -> Option<(hooks::Changed, String)> {
+        match self {
+            ItemKind::Layout(code) | ItemKind::Keymap(code) | ItemKind::XkbOption(code) => {
+                Some((hooks::Changed::Layout, code.clone()))
+            }
+            ItemKind::ClearKeymap => Some((hooks::Changed::Layout, String::new())),
+            ItemKind::Locale(code) => Some((hooks::Changed::Locale, code.clone())),
+            ItemKind::Header(_) | ItemKind::InputMethod(_) | ItemKind::Profile(_) => None,
+        }
+    }
+
+    /// Whether applying can take long enough to want a spinner, e.g. while
+    /// locale-gen runs or sudo waits for a password.
+    fn is_slow(&self) -> bool {
+        matches!(self, ItemKind::Locale(_) | ItemKind::Profile(_))
+    }
+
+    fn progress_label(&self) -> String {
+        match self {
+            ItemKind::Locale(code) => format!("Setting the locale to {}", code),
+            ItemKind::Profile(name) => format!("Applying profile {}", name),
+            _ => format!("Applying {}", self.code().unwrap_or_default()),
+        }
+    }
+
+    fn section(&self) -> Section {
+        match self {
+            ItemKind::Header(section) => *section,
+            ItemKind::Layout(_) | ItemKind::Keymap(_) | ItemKind::ClearKeymap => Section::Keyboard,
+            ItemKind::XkbOption(_) => Section::Options,
+            ItemKind::InputMethod(_) => Section::InputMethod,
+            ItemKind::Locale(_) => Section::Locale,
+            ItemKind::Profile(_) => Section::Profiles,
+        }
+    }
+}
+
+struct MenuItem {
+    label: String,
+    description: String,
+    kind: ItemKind,
+    /// A locale whose display name isn't in `label` yet; names are only
+    /// worked out for entries that get drawn, searched or sorted by name.
+    unnamed: bool,
+}
+
+impl MenuItem {
+    /// Completes the label of an `unnamed` entry.
+    fn name(&mut self) {
+        if !self.unnamed {
+            return;
+        }
+        if let ItemKind::Locale(code) = &self.kind {
+            let display_name = locale_display_name(code);
+            self.label = match native_language_name(code) {
+                Some(native) => format!("{}{} · {}", self.label, display_name, native),
+                None => format!("{}{}", self.label, display_name),
+            };
+        }
+        self.unnamed = false;
+    }
+}
+
+enum PromptAction {
+    Apply(ItemKind),
+    InstallPackage(spellcheck::PackageManager, String),
+    EnableEngine(String),
+    DisableEngine(String),
+}
+
+/// What the status panel and menu markers show, read in one go.
+struct Status {
+    layout: String,
+    locale: String,
+    backend: &'static str,
+    options: String,
+    keymap: Option<String>,
+    input_method: Option<input_method::Framework>,
+    engine: String,
+    profile: Option<String>,
+    session_locale: Vec<locale_info::LocaleVariable>,
+    archive_locales: HashSet<String>,
+    installed_locales: HashSet<String>,
+}
+
+impl Status {
+    fn read() -> Status {
+        let keyboard = backend::active();
+        let input_method = input_method::Framework::detect();
+        let snapshot = profile::Profile::current();
+        Status {
+            layout: get_current_keyboard_layout(),
+            locale: get_current_locale(),
+            backend: keyboard.name(),
+            options: keyboard.options().unwrap_or_default(),
+            keymap: keyboard.keymap_file(),
+            input_method,
+            engine: input_method.and_then(|framework| framework.active_engine()).unwrap_or_default(),
+            profile: profile::names().into_iter().find(|name| {
+                profile::Profile::named(name).is_ok_and(|profile| profile.is_satisfied_by(&snapshot))
+            }),
+            session_locale: locale_info::session_variables(),
+            // Installed locales may have changed underneath us
+            archive_locales: locale_info::archive_locales(),
+            installed_locales: locale_info::installed_locales()
+                .iter()
+                .map(|name| locale_info::normalized_locale_name(name))
+                .collect(),
+        }
+    }
+}
+
+/// Keyboards and LANG found disagreeing, e.g. "hyprland de, x11 us,
+/// console us". Only pointed out: which of them is right is the user's call.
+struct Mismatch(String);
+
+/// A change that failed, kept so the retry key can run it again as it was.
+enum Retry {
+    Apply(ItemKind),
+    /// The changes that were picked in a profile's preview.
+    Profile(String, Vec<profile::Change>),
+}
+
+/// A yes/no question shown over the menu; confirming runs `on_confirm`.
+struct Prompt {
+    message: String,
+    on_confirm: PromptAction,
+}
+
+/// Free typing to try a layout's dead keys and AltGr combinations.
+struct TypingTest {
+    text: String,
+    /// The layout to go back to if the new one doesn't work out.
+    revert_to: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DeviceField {
+    Layout,
+    Options,
+}
+
+/// The attached keyboards and their `[devices]` rules, edited one field at
+/// a time.
+struct DevicesScreen {
+    devices: Vec<String>,
+    selected: usize,
+    /// The field being typed for the highlighted keyboard, if any.
+    editing: Option<(DeviceField, String)>,
+}
+
+/// What applying a profile would change, each change picked or left out
+/// before anything is applied.
+struct ProfilePreview {
+    name: String,
+    changes: Vec<profile::Change>,
+    chosen: Vec<bool>,
+    selected: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LocaleField {
+    Source,
+    Charmap,
+    Name,
+}
+
+/// A custom locale to compile with localedef: the definition it starts
+/// from, the charmap, and the name to install it under.
+struct LocaleForm {
+    source: String,
+    charmap: String,
+    /// Empty means the source and charmap joined, e.g. "en_DK.UTF-8".
+    name: String,
+    field: LocaleField,
+}
+
+impl LocaleForm {
+    fn name(&self) -> String {
+        if self.name.is_empty() {
+            format!("{}.{}", self.source, self.charmap)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    fn text_mut(&mut self) -> &mut String {
+        match self.field {
+            LocaleField::Source => &mut self.source,
+            LocaleField::Charmap => &mut self.charmap,
+            LocaleField::Name => &mut self.name,
+        }
+    }
+
+    /// Completes the field being typed to the first source or charmap it
+    /// starts.
+    fn complete(&mut self) {
+        let candidates = match self.field {
+            LocaleField::Source => locale_info::locale_sources(),
+            LocaleField::Charmap => locale_info::charmaps(),
+            LocaleField::Name => return,
+        };
+        let text = self.text_mut();
+        let prefix = text.to_lowercase();
+        if let Some(candidate) = candidates.into_iter().find(|candidate| candidate.to_lowercase().starts_with(&prefix)) {
+            *text = candidate;
+        }
+    }
+
+    fn next_field(&mut self) {
+        self.field = match self.field {
+            LocaleField::Source => LocaleField::Charmap,
+            LocaleField::Charmap => LocaleField::Name,
+            LocaleField::Name => LocaleField::Source,
+        };
+    }
+
+    fn prev_field(&mut self) {
+        self.field = match self.field {
+            LocaleField::Source => LocaleField::Name,
+            LocaleField::Charmap => LocaleField::Source,
+            LocaleField::Name => LocaleField::Charmap,
+        };
+    }
+}
+
+/// Follow-up run on the UI thread with a background action's outcome.
+type JobFinish = Box<dyn FnOnce(&mut AppState, Result<()>)>;
+
+/// An action running on a worker thread while the UI keeps drawing; `finish`
+/// gets its outcome back on the UI thread.
+struct Job {
+    label: String,
+    started: Instant,
+    outcome: mpsc::Receiver<Result<()>>,
+    finish: JobFinish,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ViewMode {
+    List,
+    Split,
+    Tabs,
+}
+
+impl ViewMode {
+    const ALL: [ViewMode; 3] = [ViewMode::List, ViewMode::Split, ViewMode::Tabs];
+
+    fn name(self) -> &'static str {
+        match self {
+            ViewMode::List => "list",
+            ViewMode::Split => "split",
+            ViewMode::Tabs => "tabs",
+        }
+    }
+}
+
+struct MenuList {
+    items: Vec<MenuItem>,
+    selected: usize,
+    scroll_offset: usize,
+    visible_items: usize,
+}
+
+impl MenuList {
+    fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            selected: 0,
+            scroll_offset: 0,
+            visible_items: 10, // Conservative estimate, will be adjusted in render
+        }
+    }
+
+    fn set_items(&mut self, items: Vec<MenuItem>) {
+        self.items = items;
+        self.selected = self.selected.min(self.items.len().saturating_sub(1));
+        self.adjust_scroll();
+    }
+
+    fn selected_item(&self) -> Option<&MenuItem> {
+        self.items.get(self.selected)
+    }
+
+    fn move_up(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+        } else {
+            self.selected = self.items.len().saturating_sub(1);
+        }
+        self.adjust_scroll();
+    }
+
+    fn move_down(&mut self) {
+        if self.selected < self.items.len().saturating_sub(1) {
+            self.selected += 1;
+        } else {
+            self.selected = 0;
+        }
+        self.adjust_scroll();
+    }
+
+    fn adjust_scroll(&mut self) {
+        self.adjust_scroll_for_height(self.visible_items);
+    }
+
+    fn adjust_scroll_for_height(&mut self, visible_items: usize) {
+        if visible_items == 0 {
+            return;
+        }
+        self.visible_items = visible_items;
+        // Keep selected item visible
+        if self.selected < self.scroll_offset {
+            self.scroll_offset = self.selected;
+        } else if self.selected >= self.scroll_offset + visible_items {
+            self.scroll_offset = self.selected.saturating_sub(visible_items - 1);
+        }
+        // Ensure we don't scroll past the end
+        let max_scroll = self.items.len().saturating_sub(visible_items);
+        self.scroll_offset = self.scroll_offset.min(max_scroll);
+    }
+}
+
+struct AppState {
+    view_mode: ViewMode,
+    panes: Vec<MenuList>,
+    focus: usize,
+    collapsed: HashSet<Section>,
+    current_layout: String,
+    current_locale: String,
+    kb_options: String,
+    /// Name of the keyboard backend in use.
+    backend: &'static str,
+    current_keymap: Option<String>,
+    input_method: Option<input_method::Framework>,
+    current_engine: String,
+    /// The saved profile whose settings are all in effect, if any.
+    current_profile: Option<String>,
+    /// Name being typed for a new profile, while that prompt is open.
+    profile_name_input: Option<String>,
+    /// Text being searched for while the search line is open, with the
+    /// entry to go back to if the search is abandoned.
+    search_input: Option<(String, Option<ItemKind>)>,
+    typing_test: Option<TypingTest>,
+    devices_screen: Option<DevicesScreen>,
+    locale_form: Option<LocaleForm>,
+    profile_preview: Option<ProfilePreview>,
+    /// While the log screen is open, how many lines it is scrolled up from
+    /// the newest.
+    log_scroll: Option<usize>,
+    /// config.toml's modification time when it was last read.
+    config_modified: Option<std::time::SystemTime>,
+    show_details: bool,
+    /// Whether the status panel lists every locale variable of the session.
+    show_environment: bool,
+    session_locale: Vec<locale_info::LocaleVariable>,
+    details_cache: HashMap<ItemKind, Vec<String>>,
+    /// Shared with the jobs that apply changes in the background.
+    xkb_registry: Arc<xkb::XkbRegistry>,
+    archive_locales: HashSet<String>,
+    /// Normalized names of the generated locales; empty when `locale -a`
+    /// couldn't tell.
+    installed_locales: HashSet<String>,
+    /// Whether the locale list includes ones that would have to be
+    /// generated first, rather than only the installed ones.
+    all_locales: bool,
+    config: config::Config,
+    keys: keys::Bindings,
+    platform: system::Platform,
+    recent: Vec<ItemKind>,
+    prompts: VecDeque<Prompt>,
+    /// The action in flight, if any; keys are ignored until it finishes.
+    job: Option<Job>,
+    /// Drawn below the prompt (--inline) rather than on the alternate screen.
+    inline: bool,
+    /// The screen was handed to something else, e.g. sudo's password
+    /// prompt, and has to be drawn from scratch.
+    needs_clear: bool,
+    failed: Option<Retry>,
+    mismatch: Option<Mismatch>,
+    /// The mismatch check in flight, which asks X11 and localectl and so
+    /// runs off the UI thread.
+    mismatch_check: Option<mpsc::Receiver<Option<Mismatch>>>,
+    /// The periodic status refresh in flight.
+    refresh: Option<mpsc::Receiver<Status>>,
+    /// Set by --read-only: everything is shown, nothing is changed.
+    read_only: bool,
+    /// The timezone and the country it's in, when suggestions for that
+    /// country are on offer.
+    region: Option<(String, String)>,
+    suggested: Vec<ItemKind>,
+    should_quit: bool,
+}
+
+impl AppState {
+    fn new() -> Self {
+        Self {
+            view_mode: ViewMode::List,
+            panes: vec![MenuList::new()],
+            focus: 0,
+            collapsed: HashSet::new(),
+            current_layout: String::new(),
+            current_locale: String::new(),
+            kb_options: String::new(),
+            backend: "",
+            current_keymap: None,
+            input_method: None,
+            current_engine: String::new(),
+            current_profile: None,
+            profile_name_input: None,
+            search_input: None,
+            typing_test: None,
+            devices_screen: None,
+            locale_form: None,
+            profile_preview: None,
+            log_scroll: None,
+            config_modified: None,
+            show_details: true,
+            show_environment: false,
+            session_locale: Vec::new(),
+            details_cache: HashMap::new(),
+            xkb_registry: Arc::new(xkb::load_registry()),
+            archive_locales: HashSet::new(),
+            installed_locales: HashSet::new(),
+            all_locales: true,
+            config: config::Config::default(),
+            keys: keys::Bindings::default(),
+            platform: system::Platform::detect(),
+            recent: Vec::new(),
+            prompts: VecDeque::new(),
+            job: None,
+            inline: false,
+            needs_clear: false,
+            failed: None,
+            mismatch: None,
+            mismatch_check: None,
+            refresh: None,
+            read_only: false,
+            region: None,
+            suggested: Vec::new(),
+            should_quit: false,
+        }
+    }
+
+    fn refresh_status(&mut self) {
+        // Whatever a background refresh finds would be older than this
+        self.refresh = None;
+        self.show_status(Status::read());
+    }
+
+    /// Reads the status on a worker thread, for [`poll_refresh`] to show
+    /// once it's in, so the periodic refresh never stalls the interface.
+    ///
+    /// [`poll_refresh`]: AppState::poll_refresh
+    fn start_refresh(&mut self) {
+        let (sender, refresh) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(Status::read());
+        });
+        self.refresh = Some(refresh);
+    }
+
+    /// Shows the background refresh's status once it's in, keeping the
+    /// selection; called every tick.
+    fn poll_refresh(&mut self) {
+        let Some(refresh) = &self.refresh else {
+            return;
+        };
+        let status = match refresh.try_recv() {
+            Ok(status) => Some(status),
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.refresh = None;
+        if let Some(status) = status {
+            let selected = self.pane().selected_item().map(|item| item.kind.clone());
+            self.show_status(status);
+            self.build_menu();
+            if let Some(kind) = selected {
+                self.select_item(&kind);
+            }
+        }
+    }
+
+    fn show_status(&mut self, status: Status) {
+        self.current_layout = status.layout;
+        self.current_locale = status.locale;
+        self.backend = status.backend;
+        self.kb_options = status.options;
+        self.current_keymap = status.keymap;
+        self.input_method = status.input_method;
+        self.current_engine = status.engine;
+        self.current_profile = status.profile;
+        self.session_locale = status.session_locale;
+        self.archive_locales = status.archive_locales;
+        self.installed_locales = status.installed_locales;
+        // Only this machine's keyboards can be compared
+        if self.config.mismatch_warning && exec::remote_host().is_none() {
+            let (layout, locale) = (self.current_layout.clone(), self.current_locale.clone());
+            let (sender, check) = mpsc::channel();
+            thread::spawn(move || {
+                let _ = sender.send(find_mismatch(&layout, &locale));
+            });
+            self.mismatch_check = Some(check);
+        } else {
+            self.mismatch = None;
+        }
+        self.details_cache.clear();
+    }
+
+    fn pane(&self) -> &MenuList {
+        &self.panes[self.focus]
+    }
+
+    fn pane_mut(&mut self) -> &mut MenuList {
+        &mut self.panes[self.focus]
+    }
+
+    fn section_title(&self, section: Section) -> String {
+        match section {
+            Section::Suggested => "Suggested for your region".to_string(),
+            Section::Group(index) => self.group(index).map_or_else(String::new, |group| group.name.clone()),
+            Section::Keyboard => "Keyboard Layouts".to_string(),
+            Section::Options => "Keyboard Options".to_string(),
+            Section::InputMethod => match self.input_method {
+                Some(framework) => format!("Input Methods ({})", framework.name()),
+                None => "Input Methods".to_string(),
+            },
+            Section::Locale if self.all_locales => "System Locales".to_string(),
+            Section::Locale => "System Locales (installed)".to_string(),
+            Section::Profiles => "Profiles".to_string(),
+        }
+    }
+
+    fn section_current(&self, section: Section) -> &str {
+        match section {
+            Section::Suggested => self.region.as_ref().map_or("none", |(timezone, _)| timezone.as_str()),
+            Section::Group(index) => match self.group(index) {
+                Some(group) if group.layouts.contains(&self.current_layout) => &self.current_layout,
+                _ => "none",
+            },
+            Section::Keyboard => self.current_keymap.as_deref().unwrap_or(&self.current_layout),
+            Section::Options if self.kb_options.is_empty() => "none",
+            Section::Options => &self.kb_options,
+            Section::InputMethod => &self.current_engine,
+            Section::Locale => &self.current_locale,
+            Section::Profiles => self.current_profile.as_deref().unwrap_or("none"),
+        }
+    }
+
+    fn current_item(&self, section: Section) -> ItemKind {
+        match section {
+            Section::Suggested => ItemKind::Header(Section::Suggested),
+            Section::Group(_) if self.section_current(section) == "none" => ItemKind::Header(section),
+            Section::Group(_) => ItemKind::Layout(self.current_layout.clone()),
+            Section::Keyboard => match &self.current_keymap {
+                Some(path) => ItemKind::Keymap(path.clone()),
+                None => ItemKind::Layout(self.current_layout.clone()),
+            },
+            Section::Options => match self.kb_options.split(',').next() {
+                Some(option) => ItemKind::XkbOption(option.to_string()),
+                None => ItemKind::Header(Section::Options),
+            },
+            Section::InputMethod => ItemKind::InputMethod(self.current_engine.clone()),
+            Section::Locale => ItemKind::Locale(self.current_locale.clone()),
+            Section::Profiles => match &self.current_profile {
+                Some(name) => ItemKind::Profile(name.clone()),
+                None => ItemKind::Header(Section::Profiles),
+            },
+        }
+    }
+
+    fn section_header(&self, section: Section) -> MenuItem {
+        let expand_symbol = if self.section_expanded(section) { "▼" } else { "▶" };
+        MenuItem {
+            label: format!("{} {}", expand_symbol, self.section_title(section)),
+            description: format!("Current: {}", self.section_current(section)),
+            kind: ItemKind::Header(section),
+            unnamed: false,
+        }
+    }
+
+    fn group(&self, index: usize) -> Option<&config::LayoutGroup> {
+        self.config.groups.get(index)
+    }
+
+    /// What a section's tab is called.
+    fn tab_title(&self, section: Section) -> String {
+        match section {
+            Section::Group(_) => self.section_title(section),
+            _ => section.tab_title().to_string(),
+        }
+    }
+
+    /// A layout outside the full list, named from the XKB rules.
+    fn layout_entry(&self, code: &str) -> MenuItem {
+        let is_current = self.current_keymap.is_none() && code == self.current_layout;
+        let prefix = if is_current { "● " } else { "  " };
+        MenuItem {
+            label: format!("{}{}", prefix, self.xkb_registry.describe(code).unwrap_or(code)),
+            description: format!("Layout: {}", code),
+            kind: ItemKind::Layout(code.to_string()),
+            unnamed: false,
+        }
+    }
+
+    fn section_entries(&self, section: Section) -> Vec<MenuItem> {
+        let mut items = Vec::new();
+        match section {
+            Section::Suggested => {
+                for kind in &self.suggested {
+                    let item = match kind {
+                        ItemKind::Locale(code) => MenuItem {
+                            label: if *code == self.current_locale { "● " } else { "  " }.to_string(),
+                            description: code.clone(),
+                            kind: kind.clone(),
+                            unnamed: true,
+                        },
+                        _ => self.layout_entry(kind.code().unwrap_or_default()),
+                    };
+                    items.push(item);
+                }
+                // Left in the order they were suggested in rather than sorted
+                return items;
+            }
+            Section::Group(index) => {
+                // In the order the config lists them
+                let layouts = self.group(index).map(|group| group.layouts.as_slice()).unwrap_or_default();
+                return layouts.iter().map(|code| self.layout_entry(code)).collect();
+            }
+            Section::Keyboard => {
+                // A keymap file overrides the rules-based layout entirely
+                let layout_active = self.current_keymap.is_none();
+                for (layout_code, display_name) in get_available_keyboard_layouts() {
+                    let is_current = layout_active && layout_code == self.current_layout;
+                    let prefix = if is_current { "● " } else { "  " };
+                    items.push(MenuItem {
+                        label: format!("{}{}", prefix, display_name),
+                        description: format!("Layout: {}", layout_code),
+                        kind: ItemKind::Layout(layout_code.clone()),
+                        unnamed: false,
+                    });
+                }
+                for layout in &self.xkb_registry.user_layouts {
+                    let variants = layout.variants.iter().map(|variant| {
+                        (format!("{}({})", layout.code, variant.name), &variant.description)
+                    });
+                    for (layout_code, description) in
+                        std::iter::once((layout.code.clone(), &layout.description)).chain(variants)
+                    {
+                        let is_current = layout_active && layout_code == self.current_layout;
+                        let prefix = if is_current { "● " } else { "  " };
+                        items.push(MenuItem {
+                            label: format!("{}{}", prefix, description),
+                            description: format!("Custom layout: {}", layout_code),
+                            kind: ItemKind::Layout(layout_code),
+                            unnamed: false,
+                        });
+                    }
+                }
+                for path in xkb::keymap_files(&self.config.keymap_dir()) {
+                    let path = path.to_string_lossy().into_owned();
+                    let is_current = self.current_keymap.as_deref() == Some(path.as_str());
+                    let prefix = if is_current { "● " } else { "  " };
+                    items.push(MenuItem {
+                        label: format!("{}{}", prefix, keymap_name(&path)),
+                        description: format!("Keymap file: {}", path),
+                        kind: ItemKind::Keymap(path),
+                        unnamed: false,
+                    });
+                }
+                if self.current_keymap.is_some() {
+                    items.push(MenuItem {
+                        label: "  Clear keymap file".to_string(),
+                        description: "Return to rules-based layouts".to_string(),
+                        kind: ItemKind::ClearKeymap,
+                        unnamed: false,
+                    });
+                }
+            }
+            Section::Options => {
+                for toggle in xkb::COMMON_OPTIONS {
+                    let checkbox = if xkb::has_option(&self.kb_options, toggle.option) { "[x]" } else { "[ ]" };
+                    items.push(MenuItem {
+                        label: format!("{} {}", checkbox, toggle.label),
+                        description: format!("Option: {}", toggle.option),
+                        kind: ItemKind::XkbOption(toggle.option.to_string()),
+                        unnamed: false,
+                    });
+                }
+            }
+            Section::InputMethod => {
+                let Some(framework) = self.input_method else {
+                    return items;
+                };
+                for engine in framework.engines() {
+                    let is_current = engine.name == self.current_engine;
+                    let prefix = if is_current { "● " } else { "  " };
+                    items.push(MenuItem {
+                        label: format!("{}{}", prefix, engine.label),
+                        description: format!("Engine: {}", engine.name),
+                        kind: ItemKind::InputMethod(engine.name),
+                        unnamed: false,
+                    });
+                }
+            }
+            Section::Locale => {
+                let locales = get_available_locales();
+                let locales = if self.all_locales { with_supported_locales(locales) } else { locales };
+                for locale_code in locales {
+                    let installed = self.locale_installed(&locale_code);
+                    if !installed && !self.all_locales {
+                        continue;
+                    }
+                    let is_current = locale_code == self.current_locale;
+                    let prefix = if is_current { "● " } else { "  " };
+                    let description = if installed {
+                        locale_code.clone()
+                    } else {
+                        format!("{} · needs generating", locale_code)
+                    };
+                    items.push(MenuItem {
+                        // The name follows once it's needed
+                        label: prefix.to_string(),
+                        description,
+                        kind: ItemKind::Locale(locale_code.clone()),
+                        unnamed: true,
+                    });
+                }
+            }
+            Section::Profiles => {
+                for name in profile::names() {
+                    let is_current = self.current_profile.as_deref() == Some(name.as_str());
+                    let prefix = if is_current { "● " } else { "  " };
+                    items.push(MenuItem {
+                        label: format!("{}{}", prefix, name),
+                        description: format!("Profile: {}", name),
+                        kind: ItemKind::Profile(name),
+                        unnamed: false,
+                    });
+                }
+            }
+        }
+        // The option toggles keep their hand-picked order
+        if section != Section::Options {
+            self.sort_entries(&mut items);
+        }
+        items
+    }
+
+    fn locale_installed(&self, locale_code: &str) -> bool {
+        // musl has nothing to generate
+        self.platform == system::Platform::Musl
+            || matches!(locale_code, "C" | "POSIX")
+            || self.installed_locales.is_empty()
+            || self.installed_locales.contains(&locale_info::normalized_locale_name(locale_code))
+    }
+
+    /// Switches the locale list between the installed locales and every
+    /// one the system supports.
+    fn toggle_all_locales(&mut self) {
+        if self.platform == system::Platform::Musl {
+            notify("musl has no locales to generate; every one is usable");
+            return;
+        }
+        // Backends that only list what's installed get the rest from
+        // SUPPORTED; without one there may be nothing more to show
+        if !self.all_locales
+            && with_supported_locales(get_available_locales()).iter().all(|code| self.locale_installed(code))
+        {
+            notify("Every locale this system offers is installed already");
+            return;
+        }
+        self.all_locales = !self.all_locales;
+        let selected = self.pane().selected_item().map(|item| item.kind.clone());
+        self.build_menu();
+        // The highlighted locale may have just been hidden
+        self.select_header(Section::Locale);
+        if let Some(kind) = selected {
+            self.select_item(&kind);
+        }
+        if self.all_locales {
+            notify("Showing every supported locale");
+        } else {
+            notify("Showing installed locales only");
+        }
+    }
+
+    /// Puts a section's entries in the order picked in the config. Clearing
+    /// the keymap file stays last whatever the order.
+    fn sort_entries(&self, items: &mut [MenuItem]) {
+        let name = |item: &MenuItem| match &item.kind {
+            // Same order as the finished label, which starts with the name
+            ItemKind::Locale(code) if item.unnamed => locale_display_name(code).to_lowercase(),
+            _ => item.label.trim_start_matches('●').trim().to_lowercase(),
+        };
+        match self.config.sort {
+            config::SortOrder::Name => {
+                items.sort_by_cached_key(|item| (item.kind == ItemKind::ClearKeymap, name(item)))
+            }
+            config::SortOrder::Code => items.sort_by_cached_key(|item| {
+                (item.kind == ItemKind::ClearKeymap, item.kind.code().unwrap_or_default().to_lowercase())
+            }),
+            config::SortOrder::Native => items.sort_by_cached_key(|item| {
+                let native = match &item.kind {
+                    ItemKind::Locale(code) => native_language_name(code).map(str::to_lowercase),
+                    _ => None,
+                };
+                (item.kind == ItemKind::ClearKeymap, native.unwrap_or_else(|| name(item)))
+            }),
+            config::SortOrder::Recent => items.sort_by_cached_key(|item| {
+                let recent = self.recent.iter().position(|kind| *kind == item.kind);
+                (item.kind == ItemKind::ClearKeymap, recent.unwrap_or(usize::MAX), name(item))
+            }),
+        }
+    }
+
+    /// Reads config.toml again after it changed on disk. A file that no
+    /// longer loads leaves the settings in use as they are.
+    fn reload_config(&mut self) {
+        let config = match config::Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                notify_failure(&format!("Config not reloaded: {:#}", e));
+                return;
+            }
+        };
+        // Our own edits, e.g. the sort order, are in effect already
+        if config == self.config {
+            return;
+        }
+        // A theme picked with T stays until the config names another one
+        if config.theme != self.config.theme {
+            theme::set(&config.theme);
+        }
+        self.keys = keys::Bindings::new(&config.keys).unwrap_or_default();
+        notifications::configure(config.notifications.clone());
+        exec::configure(&config);
+        backend::configure(config.backend.clone());
+        self.config = config;
+        let selected = self.pane().selected_item().map(|item| item.kind.clone());
+        self.build_menu();
+        if let Some(kind) = selected {
+            self.select_item(&kind);
+        }
+        notify("Reloaded the config");
+    }
+
+    fn cycle_sort(&mut self) {
+        let order = self.config.sort.next();
+        self.config.sort = order;
+        let selected = self.pane().selected_item().map(|item| item.kind.clone());
+        self.build_menu();
+        if let Some(kind) = selected {
+            self.select_item(&kind);
+        }
+        // The order is the viewer's own business, but the config isn't
+        let sorted = messages::text(
+            messages::Message::Sorted,
+            &messages::Values { name: order.name(), ..Default::default() },
+        );
+        if self.read_only {
+            notify(&sorted);
+            return;
+        }
+        match config::save_sort(order) {
+            Ok(()) => notify(&sorted),
+            Err(e) => notify_failure(&format!("Sorted by {}, but couldn't save it: {:#}", order.name(), e)),
+        }
+    }
+
+    /// The sections on offer: only the system locale makes sense for a
+    /// remote host, since keyboards and input methods belong to this session.
+    fn sections(&self) -> Vec<Section> {
+        if exec::remote_host().is_some() {
+            return vec![Section::Locale, Section::Profiles];
+        }
+        let mut sections = Section::ALL.to_vec();
+        if self.suggested.is_empty() {
+            sections.retain(|section| *section != Section::Suggested);
+        }
+        // Groups come before the full lists, after any suggestions
+        let at = sections.iter().position(|section| *section == Section::Keyboard).unwrap_or_default();
+        sections.splice(at..at, self.group_sections());
+        sections
+    }
+
+    /// Whether the `[filter]` lets `kind` into the menu.
+    fn shown(&self, kind: &ItemKind) -> bool {
+        match kind.code() {
+            Some(code) => self.config.filter.allows(code, &kind.key()),
+            // Headers and clearing the keymap aren't entries of their own
+            None => true,
+        }
+    }
+
+    fn group_sections(&self) -> Vec<Section> {
+        (0..self.config.groups.len()).map(Section::Group).collect()
+    }
+
+    /// Which sections each pane shows in the current view mode.
+    fn pane_sections(&self) -> Vec<Vec<Section>> {
+        if exec::remote_host().is_some() {
+            return vec![self.sections()];
+        }
+        match self.view_mode {
+            ViewMode::List => vec![self.sections()],
+            ViewMode::Split => {
+                let (keyboard, system) = self.sections().into_iter().partition(|section| {
+                    !matches!(section, Section::Locale | Section::Profiles)
+                });
+                vec![keyboard, system]
+            }
+            ViewMode::Tabs => self.sections().into_iter().map(|section| vec![section]).collect(),
+        }
+    }
+
+    fn build_menu(&mut self) {
+        let pane_sections = self.pane_sections();
+        self.panes.resize_with(pane_sections.len(), MenuList::new);
+        for (index, sections) in pane_sections.iter().enumerate() {
+            let sections: Vec<(Section, Vec<MenuItem>)> = sections
+                .iter()
+                .map(|section| {
+                    let mut entries = self.section_entries(*section);
+                    entries.retain(|item| self.shown(&item.kind));
+                    (*section, entries)
+                })
+                .filter(|(_, entries)| !entries.is_empty())
+                .collect();
+            // A pane holding a single section doesn't need a header for it
+            let with_headers = sections.len() > 1;
+            let mut items = Vec::new();
+            for (section, entries) in sections {
+                if with_headers {
+                    items.push(self.section_header(section));
+                    if !self.section_expanded(section) {
+                        continue;
+                    }
+                }
+                items.extend(entries);
+            }
+            self.panes[index].set_items(items);
+        }
+        self.focus = self.focus.min(self.panes.len() - 1);
+    }
+
+    fn pane_title(&self, index: usize) -> String {
+        match self.view_mode {
+            ViewMode::List => "📋 Options".to_string(),
+            ViewMode::Split | ViewMode::Tabs => {
+                let section = self.pane_sections()[index][0];
+                format!("📋 {} ({})", self.section_title(section), self.section_current(section))
+            }
+        }
+    }
+
+    fn toggle_view_mode(&mut self) {
+        self.view_mode = match self.view_mode {
+            ViewMode::List => ViewMode::Split,
+            ViewMode::Split => ViewMode::Tabs,
+            ViewMode::Tabs => ViewMode::List,
+        };
+        // Selections don't carry over between layouts
+        self.panes = vec![MenuList::new()];
+        self.focus = 0;
+        self.build_menu();
+    }
+
+    fn focus_next_pane(&mut self) {
+        self.focus = (self.focus + 1) % self.panes.len();
+    }
+
+    fn focus_prev_pane(&mut self) {
+        self.focus = (self.focus + self.panes.len() - 1) % self.panes.len();
+    }
+
+    fn toggle_section(&mut self) {
+        let Some(ItemKind::Header(section)) = self.pane().selected_item().map(|item| item.kind.clone()) else {
+            return;
+        };
+        if !self.collapsed.remove(&section) {
+            self.collapsed.insert(section);
+        }
+        self.build_menu();
+        self.select_header(section);
+    }
+
+    fn set_all_sections_expanded(&mut self, expanded: bool) {
+        let section = self.pane().selected_item().map(|item| item.kind.section());
+        if expanded {
+            self.collapsed.clear();
+        } else {
+            self.collapsed.extend(self.sections());
+        }
+        self.build_menu();
+        // The selected entry may have been folded away, so fall back to its
+        // section header
+        if !expanded && let Some(section) = section {
+            self.select_header(section);
+        }
+    }
+
+    fn select_header(&mut self, section: Section) {
+        let pane = self.pane_mut();
+        if let Some(i) = pane
+            .items
+            .iter()
+            .position(|item| item.kind == ItemKind::Header(section))
+        {
+            pane.selected = i;
+        }
+        pane.adjust_scroll();
+    }
+
+    fn jump_to_current(&mut self) {
+        let Some(section) = self.pane().selected_item().map(|item| item.kind.section()) else {
+            return;
+        };
+        let target = self.current_item(section);
+        if !self.section_expanded(section) {
+            self.collapsed.remove(&section);
+            self.build_menu();
+        }
+        let pane = self.pane_mut();
+        if let Some(i) = pane.items.iter().position(|item| item.kind == target) {
+            pane.selected = i;
+            pane.adjust_scroll();
+        }
+    }
+
+    fn section_expanded(&self, section: Section) -> bool {
+        !self.collapsed.contains(&section)
+    }
+
+    fn selected_details(&mut self) -> Vec<String> {
+        let Some(item) = self.pane().selected_item() else {
+            return Vec::new();
+        };
+        let kind = item.kind.clone();
+        if let Some(lines) = self.details_cache.get(&kind) {
+            return lines.clone();
+        }
+        let lines = match &kind {
+            ItemKind::Header(Section::Suggested) => match &self.region {
+                Some((timezone, country)) => vec![
+                    format!("Timezone: {}", timezone),
+                    format!("Country: {}", country),
+                    "Locales and layouts commonly used there, offered on the first run".to_string(),
+                ],
+                None => Vec::new(),
+            },
+            ItemKind::Header(Section::Group(index)) => vec![
+                format!("Group: {}", self.section_title(Section::Group(*index))),
+                format!("Layouts: {}", self.group(*index).map_or(0, |group| group.layouts.len())),
+                format!("Current layout: {}", self.current_layout),
+                "Groups are set up under [[groups]] in config.toml".to_string(),
+            ],
+            ItemKind::Header(Section::Keyboard) => self.keyboard_details(),
+            ItemKind::Keymap(path) => vec![
+                format!("Keymap file: {}", keymap_name(path)),
+                format!("Path: {}", path),
+                "Overrides the layout, variant and options while loaded".to_string(),
+            ],
+            ItemKind::ClearKeymap => vec![
+                format!("Loaded keymap: {}", self.current_keymap.as_deref().unwrap_or("none")),
+                format!("Layout after clearing: {}", self.current_layout),
+            ],
+            ItemKind::Header(Section::Options) => vec![
+                format!("Options in effect: {}", self.section_current(Section::Options)),
+                format!("Known options: {}", self.xkb_registry.options.len()),
+            ],
+            ItemKind::XkbOption(option) => vec![
+                format!("Option: {}", option),
+                format!(
+                    "Description: {}",
+                    self.xkb_registry.option_description(option).unwrap_or("—")
+                ),
+                format!(
+                    "Enabled: {}",
+                    if xkb::has_option(&self.kb_options, option) { "yes" } else { "no" }
+                ),
+            ],
+            ItemKind::Header(Section::InputMethod) => vec![
+                format!(
+                    "Framework: {}",
+                    self.input_method.map_or("none detected", |framework| framework.name())
+                ),
+                format!("Active engine: {}", self.current_engine),
+            ],
+            ItemKind::Header(Section::Locale) => {
+                let mut lines = vec![
+                    format!("Current locale: {}", self.current_locale),
+                    format!("Backend: {}", backend::active_locale().name()),
+                ];
+                match self.platform {
+                    system::Platform::Generic => {}
+                    system::Platform::NixOs => {
+                        lines.push(format!("Platform: {}", self.platform.name()));
+                        lines.push(format!("Changes are written to {}", nixos::snippet_path().display()));
+                    }
+                    system::Platform::Ostree => {
+                        lines.push(format!("Platform: {}", self.platform.name()));
+                        lines.push("Unavailable: locale-gen, localedef (/usr is read-only)".to_string());
+                        lines.push("Add locales with: rpm-ostree install glibc-langpack-<lang>".to_string());
+                    }
+                    system::Platform::Musl => {
+                        lines.push(format!("Platform: {}", self.platform.name()));
+                        lines.push("Nothing to generate: musl accepts any locale name".to_string());
+                        lines.push("LANG only picks message translations, from MUSL_LOCPATH".to_string());
+                    }
+                }
+                lines
+            }
+            ItemKind::Layout(code) => self.layout_details(code),
+            ItemKind::InputMethod(engine) => vec![
+                format!("Engine: {}", engine),
+                format!(
+                    "Framework: {}",
+                    self.input_method.map_or("none detected", |framework| framework.name())
+                ),
+                format!("Active: {}", if *engine == self.current_engine { "yes" } else { "no" }),
+            ],
+            ItemKind::Locale(code) => self.locale_details(code),
+            ItemKind::Header(Section::Profiles) => vec![
+                format!("Directory: {}", profile::profiles_dir().display()),
+                format!("Saved profiles: {}", profile::names().len()),
+                format!("In effect: {}", self.section_current(Section::Profiles)),
+                "Press P to save the current settings as a profile".to_string(),
+            ],
+            ItemKind::Profile(name) => profile_details(name),
+        };
+        self.details_cache.insert(kind, lines.clone());
+        lines
+    }
+
+    /// The keyboard as configured, then as libxkbcommon resolves it, for
+    /// working out why a layout behaves oddly.
+    fn keyboard_details(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Backend: {}", backend::active().name()),
+            format!("Seat: {}", seat::effective()),
+            format!("Current layout: {}", self.current_layout),
+            format!("Keymap file: {}", self.current_keymap.as_deref().unwrap_or("none")),
+            format!("Known layouts: {}", self.xkb_registry.layouts.len()),
+        ];
+        if let Some(seat) = seat::target() {
+            let keyboards = seat::keyboards(&seat);
+            if keyboards.is_empty() {
+                lines.push(format!("No keyboards attached to {}", seat));
+            } else {
+                lines.push(format!("Keyboards on {}: {}", seat, keyboards.join(", ")));
+            }
+        }
+        let (layout, variant) = xkb::split_layout_variant(&self.current_layout);
+        if let Some(description) = self.xkb_registry.describe(&self.current_layout) {
+            lines.push(format!("Description: {}", description));
+        }
+
+        lines.push(String::new());
+        if self.kb_options.is_empty() {
+            lines.push("Options in effect: none".to_string());
+        } else {
+            lines.push("Options in effect:".to_string());
+            for option in self.kb_options.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+                match self.xkb_registry.option_description(option) {
+                    Some(description) => lines.push(format!("  {} — {}", option, description)),
+                    None => lines.push(format!("  {} (not in the rules database)", option)),
+                }
+            }
+        }
+
+        lines.push(String::new());
+        // A loaded keymap file replaces whatever the rules would produce
+        let compiled = match &self.current_keymap {
+            Some(path) => std::fs::read_to_string(path).map_err(anyhow::Error::from),
+            None => keymap::compile(layout, variant, &self.kb_options),
+        };
+        match compiled {
+            Ok(compiled) => {
+                let resolved = keymap::Keymap::parse(&compiled);
+                lines.push("Resolved keymap:".to_string());
+                for (index, group) in resolved.groups.iter().enumerate() {
+                    lines.push(format!("  Group {}: {}", index + 1, group));
+                }
+                for (section, components) in &resolved.components {
+                    lines.push(format!("  {}: {}", section, components));
+                }
+            }
+            Err(e) => lines.push(format!("Resolved keymap: unavailable ({:#})", e)),
+        }
+        lines
+    }
+
+    fn layout_details(&self, code: &str) -> Vec<String> {
+        let mut lines = vec![format!("Code: {}", code)];
+        let (code, variant) = xkb::split_layout_variant(code);
+        let Some(layout) = self.xkb_registry.layout(code) else {
+            lines.push("Not found in the XKB rules database".to_string());
+            return lines;
+        };
+        lines.push(format!("Description: {}", layout.description));
+        if let Some(variant) = variant {
+            lines.push(format!("Variant: {}", variant));
+        }
+        if self.xkb_registry.is_user_layout(code) {
+            lines.push(format!(
+                "Source: {}",
+                config::xdg_config_home().join("xkb/symbols").join(code).display()
+            ));
+        }
+        lines.push(String::new());
+        match keymap::compile(code, variant, "") {
+            Ok(compiled) => {
+                lines.push("Preview (Shift, base, AltGr):".to_string());
+                lines.extend(keymap::Keymap::parse(&compiled).preview());
+            }
+            Err(e) => lines.push(format!("No preview: {:#}", e)),
+        }
+        lines.push(String::new());
+        if layout.variants.is_empty() {
+            lines.push("Variants: none".to_string());
+        } else {
+            lines.push(format!("Variants ({}):", layout.variants.len()));
+            for variant in &layout.variants {
+                lines.push(format!("  {} — {}", variant.name, variant.description));
+            }
+        }
+        lines
+    }
+
+    fn locale_details(&self, code: &str) -> Vec<String> {
+        if self.platform == system::Platform::Musl {
+            return self.musl_locale_details(code);
+        }
+        let parts = locale_info::split_locale_code(code);
+        let details = locale_info::locale_details(code, &self.archive_locales);
+        let mut lines = vec![format!("Code: {}", code)];
+        // An installed locale knows its charmap; otherwise ask the backend
+        // what it would build, or go by the name
+        let charmap = details.charmap.clone().or_else(|| backend::active_locale().charmap(code));
+        if let Some(charmap) = charmap.as_deref().or(parts.codeset)
+            && !locale_info::is_utf8(charmap)
+            && !matches!(details.source, locale_info::LocaleSource::Builtin)
+        {
+            lines.push(format!("⚠ NOT UTF-8 ({}): text outside this charset can't be shown", charmap));
+            if let Some(territory) = parts.territory {
+                lines.push(format!("  Consider {}_{}.UTF-8 instead", parts.language, territory));
+            }
+        }
+        let installed = !matches!(details.source, locale_info::LocaleSource::NotInstalled);
+        let missing: Vec<&str> = locale_info::LC_CATEGORIES
+            .into_iter()
+            .filter(|category| !details.installed_categories.contains(category))
+            .collect();
+        if installed && !missing.is_empty() {
+            lines.push(format!("⚠ INCOMPLETE: {} won't load", missing.join(", ")));
+            lines.push("  Those categories would fall back to C; reinstall the locale".to_string());
+        }
+        lines.extend([
+            format!("Language: {}", parts.language),
+            format!("Territory: {}", parts.territory.unwrap_or("—")),
+            format!("Codeset: {}", parts.codeset.unwrap_or("— (none in the name)")),
+            match (&details.charmap, &charmap) {
+                (Some(charmap), _) => format!("Charmap: {}", charmap),
+                (None, Some(charmap)) => format!("Charmap: {} once installed", charmap),
+                (None, None) if installed => "Charmap: unknown".to_string(),
+                (None, None) => "Charmap: unknown until installed".to_string(),
+            },
+        ]);
+        if let Some(modifier) = parts.modifier {
+            lines.push(format!("Modifier: {}", modifier));
+        }
+        lines.push(match &details.source {
+            locale_info::LocaleSource::Builtin => "Source: built into libc".to_string(),
+            locale_info::LocaleSource::Archive => "Source: locale-archive".to_string(),
+            locale_info::LocaleSource::Directory(dir) => format!("Source: {}", dir.display()),
+            locale_info::LocaleSource::NotInstalled if self.platform == system::Platform::Ostree => format!(
+                "Source: not installed (rpm-ostree install glibc-langpack-{})",
+                parts.language
+            ),
+            locale_info::LocaleSource::NotInstalled => "Source: not installed".to_string(),
+        });
+        lines.push(String::new());
+        lines.push(format!(
+            "LC categories ({}/{}):",
+            details.installed_categories.len(),
+            locale_info::LC_CATEGORIES.len()
+        ));
+        for category in locale_info::LC_CATEGORIES {
+            let mark = if details.installed_categories.contains(&category) { "✓" } else { "✗" };
+            lines.push(format!("  {} {}", mark, category));
+        }
+        lines
+    }
+
+    /// musl has no compiled locales, so there's no charmap, source or
+    /// categories to check, only whether translations exist.
+    fn musl_locale_details(&self, code: &str) -> Vec<String> {
+        let parts = locale_info::split_locale_code(code);
+        let mut lines = vec![
+            format!("Code: {}", code),
+            format!("Language: {}", parts.language),
+            format!("Territory: {}", parts.territory.unwrap_or("—")),
+            format!("Codeset: {}", parts.codeset.unwrap_or("— (none in the name)")),
+        ];
+        if let Some(modifier) = parts.modifier {
+            lines.push(format!("Modifier: {}", modifier));
+        }
+        lines.push(match locale_info::musl_translations(code) {
+            Some(path) => format!("Translations: {}", path.display()),
+            None => "Translations: none, messages stay in English".to_string(),
+        });
+        lines.push(String::new());
+        lines.push("musl always uses UTF-8, and keeps C's date,".to_string());
+        lines.push("number and sorting rules whatever the locale".to_string());
+        lines
+    }
+
+    fn execute_selected(&mut self) -> Result<bool> {
+        let Some(item) = self.pane().selected_item() else {
+            return Ok(false);
+        };
+        // Check if it's a header (expandable section)
+        if matches!(item.kind, ItemKind::Header(_)) {
+            self.toggle_section();
+            return Ok(false);
+        }
+        // Execute regular action
+        let kind = item.kind.clone();
+        self.pick_entry(kind);
+        Ok(false)
+    }
+
+    /// Applies an entry chosen in the menu; profiles are previewed first.
+    fn pick_entry(&mut self, kind: ItemKind) {
+        match kind {
+            ItemKind::Profile(name) => self.open_profile_preview(&name),
+            kind => self.apply_entry(kind),
+        }
+    }
+
+    fn open_profile_preview(&mut self, name: &str) {
+        let profile = match profile::Profile::named(name) {
+            Ok(profile) => profile,
+            Err(e) => {
+                notify_failure(&format!("{:#}", e));
+                return;
+            }
+        };
+        let changes = profile.changes();
+        if changes.is_empty() {
+            let values = messages::Values { code: name, name, ..Default::default() };
+            notify(&messages::text(messages::Message::ProfileInEffect, &values));
+            return;
+        }
+        let chosen = vec![true; changes.len()];
+        self.profile_preview = Some(ProfilePreview { name: name.to_string(), changes, chosen, selected: 0 });
+    }
+
+    /// Applies the changes left picked in the preview, in the background.
+    fn apply_profile_preview(&mut self) {
+        let Some(preview) = self.profile_preview.take() else {
+            return;
+        };
+        let changes: Vec<profile::Change> = preview
+            .changes
+            .into_iter()
+            .zip(preview.chosen)
+            .filter_map(|(change, chosen)| chosen.then_some(change))
+            .collect();
+        if changes.is_empty() {
+            notify("Nothing picked, so nothing changed");
+            return;
+        }
+        self.apply_profile_changes(preview.name, changes);
+    }
+
+    fn apply_profile_changes(&mut self, name: String, changes: Vec<profile::Change>) {
+        let kind = ItemKind::Profile(name.clone());
+        let config = self.config.clone();
+        let (work_name, work_changes) = (name.clone(), changes.clone());
+        self.run_in_background(
+            kind.progress_label(),
+            move || report_profile(&work_name, &profile::apply_changes(&work_changes, &config)),
+            move |app, result| {
+                app.finish_apply(kind, result, move |app, result| {
+                    if result.is_err() {
+                        app.failed = Some(Retry::Profile(name, changes));
+                    }
+                })
+            },
+        );
+    }
+
+    /// Runs the last change that failed again, e.g. once sudo has been
+    /// sorted out.
+    fn retry_failed(&mut self) {
+        match self.failed.take() {
+            Some(Retry::Apply(kind)) => self.apply(kind),
+            Some(Retry::Profile(name, changes)) => self.apply_profile_changes(name, changes),
+            None => notify("Nothing has failed, so there's nothing to retry"),
+        }
+    }
+
+    /// Applies an entry picked by the user, offering to bring the keyboard
+    /// layout along when a locale change leaves the two mismatched.
+    fn apply_entry(&mut self, kind: ItemKind) {
+        self.apply_then(kind.clone(), move |app, result| {
+            if result.is_ok()
+                && let ItemKind::Locale(locale_code) = &kind
+                && exec::remote_host().is_none()
+                && let Some(layout_code) = locale_to_keyboard_layout(locale_code)
+                && layout_code != app.current_layout
+            {
+                // Ask about the layout before any follow-ups queued by apply()
+                app.prompts.push_front(Prompt {
+                    message: format!("Also switch keyboard to {}?", layout_code),
+                    on_confirm: PromptAction::Apply(ItemKind::Layout(layout_code)),
+                });
+            }
+        });
+    }
+
+    fn answer_prompt(&mut self, confirmed: bool) -> Result<()> {
+        let Some(prompt) = self.prompts.pop_front() else {
+            return Ok(());
+        };
+        if !confirmed {
+            return Ok(());
+        }
+        match prompt.on_confirm {
+            PromptAction::Apply(kind) => {
+                self.apply(kind);
+                Ok(())
+            }
+            PromptAction::InstallPackage(manager, package) => {
+                let label = format!("Installing {}", package);
+                let work_package = package.clone();
+                self.run_in_background(label, move || manager.install(&work_package), move |_, result| {
+                    let values = messages::Values { code: &package, name: &package, ..Default::default() };
+                    match result {
+                        Ok(()) if manager.needs_reboot() => {
+                            notify(&messages::text(messages::Message::Layered, &values))
+                        }
+                        Ok(()) => notify(&messages::text(messages::Message::Installed, &values)),
+                        Err(e) => notify_failure(&format!("{:#}", e)),
+                    }
+                });
+                Ok(())
+            }
+            PromptAction::EnableEngine(engine) => {
+                let result = input_method::fcitx5_add_engine(&engine);
+                match &result {
+                    Ok(()) => {
+                        let values =
+                            messages::Values { code: &engine, name: &engine, backend: "fcitx5", ..Default::default() };
+                        notify(&messages::text(messages::Message::EngineEnabled, &values))
+                    }
+                    Err(e) => notify_failure(&format!("{:#}", e)),
+                }
+                self.refresh_status();
+                self.build_menu();
+                result
+            }
+            PromptAction::DisableEngine(engine) => {
+                let result = input_method::fcitx5_remove_engine(&engine);
+                match &result {
+                    Ok(()) => {
+                        let values =
+                            messages::Values { code: &engine, name: &engine, backend: "fcitx5", ..Default::default() };
+                        notify(&messages::text(messages::Message::EngineRemoved, &values))
+                    }
+                    Err(e) => notify_failure(&format!("{:#}", e)),
+                }
+                self.refresh_status();
+                self.build_menu();
+                result
+            }
+        }
+    }
+
+    fn suggest_input_method(&mut self, locale_code: &str) {
+        if self.input_method != Some(input_method::Framework::Fcitx5) {
+            return;
+        }
+        if let Some(engine) = input_method::engine_for_locale(locale_code)
+            && !input_method::fcitx5_has_engine(engine)
+        {
+            self.prompts.push_back(Prompt {
+                message: format!("Enable the {} input method in fcitx5?", engine),
+                on_confirm: PromptAction::EnableEngine(engine.to_string()),
+            });
+        }
+    }
+
+    fn toggle_compose(&mut self) -> Result<()> {
+        if self.refuse_change() {
+            return Ok(());
+        }
+        let result = toggle_compose_key(&self.config);
+        if result.is_ok() {
+            self.persist_keyboard();
+            self.sync_greeter();
+        }
+        self.refresh_status();
+        self.build_menu();
+        result
+    }
+
+    /// Writes the live keyboard settings to the compositor config when the
+    /// user has asked for changes to outlast a restart.
+    fn persist_keyboard(&self) {
+        if !self.config.persist_keyboard {
+            return;
+        }
+        if let Err(e) = backend::active().persist() {
+            notify_failure(&format!("{:#}", e));
+        }
+    }
+
+    /// Keeps the NixOS snippet in step with the selection; `announce` tells
+    /// the user where it went.
+    fn write_nixos_snippet(&self, locale_code: &str, announce: bool) -> Result<()> {
+        let backend = backend::active();
+        let layout = backend.current_layout().unwrap_or_default();
+        let options = backend.options().unwrap_or_default();
+        match nixos::write_snippet(locale_code, &layout, &options) {
+            Ok(path) => {
+                if announce {
+                    notify(&format!("NixOS: add the snippet in {} to configuration.nix", path.display()));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                notify_failure(&format!("{:#}", e));
+                Err(e)
+            }
+        }
+    }
+
+    /// Mirrors the keyboard settings onto the login screen, if enabled.
+    fn sync_greeter(&self) {
+        if !self.config.sync_greeter {
+            return;
+        }
+        let Some(greeter) = greeter::Greeter::detect() else {
+            notify_failure("No supported greeter found to sync the layout to");
+            return;
+        };
+        let backend = backend::active();
+        let layout = backend.current_layout().unwrap_or_default();
+        let options = backend.options().unwrap_or_default();
+        if let Err(e) = greeter.sync_keyboard(&layout, &options) {
+            notify_failure(&format!("Failed to update the {} greeter: {:#}", greeter.name(), e));
+        }
+    }
+
+    fn save_profile(&mut self, name: &str) -> Result<()> {
+        let result = profile::Profile::current().save(name);
+        match &result {
+            Ok(path) => {
+                let path = path.display().to_string();
+                let values = messages::Values { code: name, name, detail: &path, ..Default::default() };
+                notify(&messages::text(messages::Message::ProfileSaved, &values))
+            }
+            Err(e) => notify_failure(&format!("{:#}", e)),
+        }
+        self.refresh_status();
+        self.build_menu();
+        result.map(|_| ())
+    }
+
+    /// Switches to the highlighted layout and opens the typing test, which
+    /// can switch back; on any other entry it just tests the current one.
+    fn start_typing_test(&mut self) {
+        match self.pane().selected_item().map(|item| item.kind.clone()) {
+            Some(ItemKind::Layout(code)) if code != self.current_layout => {
+                let previous = self.current_layout.clone();
+                self.apply_then(ItemKind::Layout(code), move |app, result| {
+                    if result.is_ok() {
+                        app.typing_test = Some(TypingTest { text: String::new(), revert_to: Some(previous) });
+                    }
+                });
+            }
+            _ => self.typing_test = Some(TypingTest { text: String::new(), revert_to: None }),
+        }
+    }
+
+    fn finish_typing_test(&mut self, keep: bool) {
+        let Some(test) = self.typing_test.take() else {
+            return;
+        };
+        match test.revert_to {
+            Some(previous) if !keep => self.apply(ItemKind::Layout(previous)),
+            Some(_) => notify(&format!("Keeping {}", self.current_layout)),
+            None => {}
+        }
+    }
+
+    /// Points keyboard changes at the next logind seat, wrapping around to
+    /// our own.
+    fn next_seat(&mut self) {
+        let seats = seat::list();
+        if seats.len() < 2 {
+            notify(&format!("{} is the only seat", seat::own()));
+            return;
+        }
+        let current = seat::effective();
+        let next = seats.iter().position(|seat| *seat == current).map_or(0, |index| (index + 1) % seats.len());
+        seat::set_target(&seats[next]);
+        let message = match seat::target() {
+            Some(seat) => {
+                messages::text(messages::Message::SeatTargeted, &messages::Values { name: &seat, ..Default::default() })
+            }
+            None => {
+                let own = seat::own();
+                messages::text(messages::Message::SeatReset, &messages::Values { name: &own, ..Default::default() })
+            }
+        };
+        notify(&message);
+        self.reload();
+    }
+
+    /// Opens the custom locale form, starting from the highlighted locale's
+    /// definition when there is one.
+    fn open_locale_form(&mut self) {
+        if self.refuse_change() {
+            return;
+        }
+        let unavailable = match self.platform {
+            system::Platform::NixOs => Some("on NixOS, add it to i18n.supportedLocales instead"),
+            system::Platform::Ostree => Some("/usr is read-only on rpm-ostree"),
+            system::Platform::Musl => Some("musl has no localedef"),
+            system::Platform::Generic => None,
+        };
+        if let Some(reason) = unavailable {
+            notify_failure(&format!("Can't compile custom locales: {}", reason));
+            return;
+        }
+        let sources = locale_info::locale_sources();
+        if sources.is_empty() {
+            notify_failure("No locale definitions in /usr/share/i18n/locales (install the locale sources)");
+            return;
+        }
+        let source = match self.pane().selected_item().map(|item| &item.kind) {
+            Some(ItemKind::Locale(code)) => {
+                let parts = locale_info::split_locale_code(code);
+                let base = match parts.territory {
+                    Some(territory) => format!("{}_{}", parts.language, territory),
+                    None => parts.language.to_string(),
+                };
+                if sources.contains(&base) { base } else { String::new() }
+            }
+            _ => String::new(),
+        };
+        self.locale_form = Some(LocaleForm {
+            source,
+            charmap: "UTF-8".to_string(),
+            name: String::new(),
+            field: LocaleField::Source,
+        });
+    }
+
+    /// Compiles the locale the form describes in the background, then
+    /// highlights it in the list.
+    fn compile_custom_locale(&mut self) {
+        let Some(form) = &self.locale_form else {
+            return;
+        };
+        if !locale_info::locale_sources().contains(&form.source) {
+            notify_failure(&format!("No locale definition called {:?} (Tab completes)", form.source));
+            return;
+        }
+        if !locale_info::charmaps().contains(&form.charmap) {
+            notify_failure(&format!("No charmap called {:?} (Tab completes)", form.charmap));
+            return;
+        }
+        let (source, charmap, name) = (form.source.clone(), form.charmap.clone(), form.name());
+        self.locale_form = None;
+        let label = format!("Compiling {}", name);
+        let locale = name.clone();
+        self.run_in_background(
+            label,
+            move || locale_info::compile_locale(&source, &charmap, &locale),
+            move |app, result| {
+                match result {
+                    Ok(()) => notify(&format!("Compiled {}; it's in the locale list now", name)),
+                    Err(e) => notify_failure(&format!("{:#}", e)),
+                }
+                app.refresh_status();
+                app.build_menu();
+                app.select_item(&ItemKind::Locale(name));
+            },
+        );
+    }
+
+    fn open_devices_screen(&mut self) {
+        let devices = backend::active().keyboards();
+        if devices.is_empty() {
+            notify_failure(&format!("{} doesn't list keyboards", backend::active().name()));
+            return;
+        }
+        self.devices_screen = Some(DevicesScreen { devices, selected: 0, editing: None });
+    }
+
+    /// Starts typing `field` for the highlighted keyboard, beginning from
+    /// what its own rule says.
+    fn edit_device_field(&mut self, field: DeviceField) {
+        if self.refuse_change() {
+            return;
+        }
+        let Some(screen) = &mut self.devices_screen else {
+            return;
+        };
+        let rule = self.config.devices.get(&screen.devices[screen.selected]);
+        let value = match field {
+            DeviceField::Layout => rule.and_then(|rule| rule.layout_code()),
+            DeviceField::Options => rule.and_then(|rule| rule.options.clone()),
+        };
+        screen.editing = Some((field, value.unwrap_or_default()));
+    }
+
+    /// Saves one field of a keyboard's rule to the config and applies the
+    /// result to that keyboard right away. An empty value clears the field,
+    /// and the rule goes once nothing is left in it.
+    fn set_device_field(&mut self, device: &str, field: DeviceField, value: &str) {
+        let mut rule = self.config.devices.get(device).cloned().unwrap_or_default();
+        let value = value.trim();
+        match field {
+            DeviceField::Layout if value.is_empty() => (rule.layout, rule.variant) = (None, None),
+            DeviceField::Layout => {
+                let (layout, variant) = xkb::split_layout_variant(value);
+                if !self.xkb_registry.layouts.is_empty() && self.xkb_registry.layout(layout).is_none() {
+                    notify_failure(&format!("Unknown layout {}", layout));
+                    return;
+                }
+                rule.layout = Some(layout.to_string());
+                rule.variant = variant.map(str::to_string);
+            }
+            DeviceField::Options => rule.options = Some(value.to_string()).filter(|options| !options.is_empty()),
+        }
+        if rule.is_empty() {
+            self.remove_device_rule(device);
+            return;
+        }
+        if let Err(e) = config::save_device_rule(device, Some(&rule)) {
+            notify_failure(&format!("{:#}", e));
+            return;
+        }
+        let keyboard = backend::active();
+        match daemon::apply_device_rule(keyboard.as_ref(), device, &rule) {
+            Ok(()) => notify(&format!("{}: {}", device, daemon::describe_rule(&rule))),
+            Err(e) => notify_failure(&format!("Saved, but {}: {:#}", device, e)),
+        }
+        self.config.devices.insert(device.to_string(), rule);
+    }
+
+    fn remove_device_rule(&mut self, device: &str) {
+        if self.refuse_change() {
+            return;
+        }
+        if !self.config.devices.contains_key(device) {
+            notify(&format!("{} has no rule of its own", device));
+            return;
+        }
+        match config::save_device_rule(device, None) {
+            // The compositor keeps what it was given until the keyboard is replugged
+            Ok(()) => notify(&format!("Removed the rule for {}", device)),
+            Err(e) => {
+                notify_failure(&format!("{:#}", e));
+                return;
+            }
+        }
+        self.config.devices.remove(device);
+    }
+
+    /// Puts the highlighted entry's code on the clipboard for pasting into
+    /// config files.
+    fn copy_selected_code(&self) {
+        let Some(code) = self.pane().selected_item().and_then(|item| item.kind.code().map(str::to_string)) else {
+            notify("Nothing to copy here");
+            return;
+        };
+        match clipboard::copy(&code) {
+            Ok(()) => notify(&messages::text(
+                messages::Message::Copied,
+                &messages::Values { code: &code, name: &code, ..Default::default() },
+            )),
+            Err(e) => notify_failure(&format!("{:#}", e)),
+        }
+    }
 
-struct AppState {
-    menu_items: Vec<MenuItem>,
-    selected: usize,
-    scroll_offset: usize,
-    keyboard_section_expanded: bool,
-    locale_section_expanded: bool,
-    current_layout: String,
-    current_locale: String,
-    should_quit: bool,
-}
+    /// Draws the selected layout as an SVG in the home directory, for
+    /// printing a reference sheet.
+    fn export_layout_image(&self) {
+        let Some(ItemKind::Layout(code)) = self.pane().selected_item().map(|item| &item.kind) else {
+            notify("Select a layout to export an image of");
+            return;
+        };
+        let (layout, variant) = xkb::split_layout_variant(code);
+        let compiled = match keymap::compile(layout, variant, "") {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                notify_failure(&format!("{:#}", e));
+                return;
+            }
+        };
+        let title = match self.xkb_registry.describe(code) {
+            Some(description) => format!("{} ({})", description, code),
+            None => code.clone(),
+        };
+        let file_name: String =
+            code.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect();
+        let path = config::home_dir().join(format!("levocale-{}.svg", file_name.trim_end_matches('-')));
+        match fs::write(&path, keymap::Keymap::parse(&compiled).svg(&title)) {
+            Ok(()) => {
+                let detail = path.display().to_string();
+                let values = messages::Values { code, name: &title, detail: &detail, ..Default::default() };
+                notify(&messages::text(messages::Message::ImageSaved, &values))
+            }
+            Err(e) => notify_failure(&format!("Failed to write {}: {}", path.display(), e)),
+        }
+    }
 
-impl AppState {
-    fn new() -> Self {
-        Self {
-            menu_items: Vec::new(),
-            selected: 0,
-            scroll_offset: 0,
-            keyboard_section_expanded: true,
-            locale_section_expanded: true,
-            current_layout: String::new(),
-            current_locale: String::new(),
-            should_quit: false,
+    /// Works out the locales and layouts to suggest from the timezone.
+    fn suggest_for_region(&mut self) {
+        let Some(timezone) = region::timezone() else {
+            return;
+        };
+        let Some(country) = region::country(&timezone) else {
+            return;
+        };
+        let locales = region::locales_for(&country, &get_available_locales());
+        // Layouts are mostly named after countries, e.g. "ch" for Switzerland
+        let own_layout = Some(country.to_lowercase()).filter(|code| self.xkb_registry.layout(code).is_some());
+        let mut layouts: Vec<String> = Vec::new();
+        for layout in own_layout.into_iter().chain(locales.iter().filter_map(|code| locale_to_keyboard_layout(code))) {
+            if !layouts.contains(&layout) {
+                layouts.push(layout);
+            }
         }
+        let layouts = layouts.into_iter().map(ItemKind::Layout);
+        self.suggested = locales.into_iter().map(ItemKind::Locale).chain(layouts).collect();
+        self.region = Some((timezone, country));
     }
 
-    fn refresh_status(&mut self) {
-        self.current_layout = get_current_keyboard_layout();
-        self.current_locale = get_current_locale();
+    /// What to remember for next time.
+    fn state(&self) -> state::State {
+        // Groups by name, in case they're reordered before the next run
+        let mut collapsed: Vec<String> = self
+            .collapsed
+            .iter()
+            .map(|section| match section {
+                Section::Group(_) => format!("group:{}", self.section_title(*section)),
+                _ => section.tab_title().to_string(),
+            })
+            .collect();
+        collapsed.sort();
+        state::State {
+            selected: self.pane().selected_item().map(|item| item.kind.key()),
+            collapsed,
+            view_mode: Some(self.view_mode.name().to_string()),
+            all_locales: Some(self.all_locales),
+            recent: self.recent.iter().map(ItemKind::key).collect(),
+            // Kept up to date by the X11 backend itself
+            x11_keymaps: state::State::load().x11_keymaps,
+        }
     }
 
-    fn build_menu(&mut self) {
-        self.menu_items.clear();
-        let available_locales = get_available_locales();
-        let available_keyboard_layouts = get_available_keyboard_layouts();
-
-        // Add keyboard layout section
-        if !available_keyboard_layouts.is_empty() {
-            let expand_symbol = if self.keyboard_section_expanded { "▼" } else { "▶" };
-            self.menu_items.push(MenuItem {
-                label: format!("{} Keyboard Layouts", expand_symbol),
-                description: format!("Current: {}", self.current_layout),
-                action: Box::new(|| Ok(())),
-            });
+    /// Puts back what [`AppState::state`] saved and rebuilds the menu.
+    fn restore_state(&mut self, state: state::State) {
+        if let Some(mode) = ViewMode::ALL.into_iter().find(|mode| Some(mode.name()) == state.view_mode.as_deref()) {
+            self.view_mode = mode;
+        }
+        if let Some(all_locales) = state.all_locales {
+            self.all_locales = all_locales;
+        }
+        self.collapsed = state
+            .collapsed
+            .iter()
+            .filter_map(|title| match title.strip_prefix("group:") {
+                Some(name) => self.config.groups.iter().position(|group| group.name == name).map(Section::Group),
+                None => Section::from_title(title),
+            })
+            .collect();
+        self.recent = state.recent.iter().filter_map(|key| ItemKind::from_key(key)).collect();
+        self.build_menu();
+        if let Some(kind) = state.selected.as_deref().and_then(ItemKind::from_key) {
+            self.select_item(&kind);
+        }
+    }
 
-            if self.keyboard_section_expanded {
-                for (layout_code, display_name) in available_keyboard_layouts {
-                    let layout_code_clone = layout_code.clone();
-                    let is_current = layout_code == self.current_layout;
-                    let prefix = if is_current { "● " } else { "  " };
-                    self.menu_items.push(MenuItem {
-                        label: format!("{}{}", prefix, display_name),
-                        description: format!("Layout: {}", layout_code),
-                        action: Box::new(move || switch_to_keyboard_layout(&layout_code_clone)),
-                    });
-                }
+    /// Focuses the pane holding `kind` and highlights it there.
+    fn select_item(&mut self, kind: &ItemKind) {
+        for (index, pane) in self.panes.iter_mut().enumerate() {
+            if let Some(position) = pane.items.iter().position(|item| item.kind == *kind) {
+                pane.selected = position;
+                pane.adjust_scroll();
+                self.focus = index;
+                return;
             }
         }
+    }
 
-        // Add locale section
-        let expand_symbol = if self.locale_section_expanded { "▼" } else { "▶" };
-        self.menu_items.push(MenuItem {
-            label: format!("{} System Locales", expand_symbol),
-            description: format!("Current: {}", self.current_locale),
-            action: Box::new(|| Ok(())),
-        });
+    /// Highlights the first entry whose label or code contains `text`,
+    /// ignoring case, in any pane.
+    fn find(&mut self, text: &str) {
+        let text = text.to_lowercase();
+        if text.is_empty() {
+            return;
+        }
+        for item in self.panes.iter_mut().flat_map(|pane| &mut pane.items) {
+            item.name();
+        }
+        let matches = |item: &MenuItem| {
+            item.label.to_lowercase().contains(&text)
+                || item.kind.code().is_some_and(|code| code.to_lowercase().contains(&text))
+        };
+        let found = self.panes.iter().flat_map(|pane| &pane.items).find(|item| matches(item)).map(|item| item.kind.clone());
+        if let Some(kind) = found {
+            self.select_item(&kind);
+        }
+    }
 
-        if self.locale_section_expanded {
-            for (locale_code, display_name) in available_locales {
-                let locale_code_clone = locale_code.clone();
-                let is_current = locale_code == self.current_locale;
-                let prefix = if is_current { "● " } else { "  " };
-                self.menu_items.push(MenuItem {
-                    label: format!("{}{}", prefix, display_name),
-                    description: locale_code.clone(),
-                    action: Box::new(move || set_locale(&locale_code_clone)),
-                });
+    /// Opens or closes the section of the highlighted entry; from inside a
+    /// section that closes it and lands on its header.
+    fn toggle_selected_section(&mut self) {
+        match self.pane().selected_item().map(|item| item.kind.clone()) {
+            Some(ItemKind::Header(_)) => self.toggle_section(),
+            Some(kind) => {
+                let section = kind.section();
+                self.collapsed.insert(section);
+                self.build_menu();
+                self.select_header(section);
             }
+            None => {}
         }
     }
 
-    fn move_up(&mut self) {
-        if self.selected > 0 {
-            self.selected -= 1;
-        } else {
-            self.selected = self.menu_items.len().saturating_sub(1);
+    /// Re-reads the current settings, keeping the highlighted entry even if
+    /// the menu around it changed.
+    fn reload(&mut self) {
+        let selected = self.pane().selected_item().map(|item| item.kind.clone());
+        self.refresh_status();
+        self.build_menu();
+        if let Some(kind) = selected {
+            self.select_item(&kind);
         }
-        self.adjust_scroll();
     }
 
-    fn move_down(&mut self) {
-        if self.selected < self.menu_items.len().saturating_sub(1) {
-            self.selected += 1;
-        } else {
-            self.selected = 0;
+    fn remove_selected_engine(&mut self) {
+        if self.input_method != Some(input_method::Framework::Fcitx5) || self.refuse_change() {
+            return;
+        }
+        if let Some(ItemKind::InputMethod(engine)) = self.pane().selected_item().map(|item| item.kind.clone()) {
+            self.prompts.push_back(Prompt {
+                message: format!("Remove {} from the fcitx5 profile?", engine),
+                on_confirm: PromptAction::DisableEngine(engine),
+            });
         }
-        self.adjust_scroll();
     }
 
-    fn adjust_scroll(&mut self) {
-        // Calculate visible area (items that can fit in the menu area)
-        let visible_items = 10; // Conservative estimate, will be adjusted in render
-        // Keep selected item visible
-        if self.selected < self.scroll_offset {
-            self.scroll_offset = self.selected;
-        } else if self.selected >= self.scroll_offset + visible_items {
-            self.scroll_offset = self.selected.saturating_sub(visible_items - 1);
+    /// On ostree systems a missing locale can't be generated, only layered
+    /// in from Fedora's per-language glibc packages.
+    fn suggest_langpack(&mut self, locale_code: &str) {
+        if self.platform != system::Platform::Ostree {
+            return;
         }
-        // Ensure we don't scroll past the end
-        let max_scroll = self.menu_items.len().saturating_sub(visible_items);
-        self.scroll_offset = self.scroll_offset.min(max_scroll);
+        let details = locale_info::locale_details(locale_code, &self.archive_locales);
+        if !matches!(details.source, locale_info::LocaleSource::NotInstalled) {
+            return;
+        }
+        let package = format!("glibc-langpack-{}", locale_info::split_locale_code(locale_code).language);
+        self.prompts.push_back(Prompt {
+            message: format!(
+                "{} isn't installed and locale-gen is unavailable on rpm-ostree. Layer {}?",
+                locale_code, package
+            ),
+            on_confirm: PromptAction::InstallPackage(spellcheck::PackageManager::RpmOstree, package),
+        });
     }
 
-    fn adjust_scroll_for_height(&mut self, visible_items: usize) {
-        if visible_items == 0 {
+    fn suggest_dictionary(&mut self, locale_code: &str) {
+        let parts = locale_info::split_locale_code(locale_code);
+        if matches!(parts.language, "C" | "POSIX") || spellcheck::has_dictionary(parts.language) {
             return;
         }
-        // Keep selected item visible
-        if self.selected < self.scroll_offset {
-            self.scroll_offset = self.selected;
-        } else if self.selected >= self.scroll_offset + visible_items {
-            self.scroll_offset = self.selected.saturating_sub(visible_items - 1);
+        let display_name = locale_code_to_display_name(locale_code);
+        match spellcheck::PackageManager::detect() {
+            Some(manager) => {
+                let package = manager.dictionary_package(parts.language, parts.territory);
+                self.prompts.push_back(Prompt {
+                    message: format!(
+                        "No spell-check dictionary for {}. Install {} with {}?",
+                        display_name,
+                        package,
+                        manager.name()
+                    ),
+                    on_confirm: PromptAction::InstallPackage(manager, package),
+                });
+            }
+            None => notify(&format!(
+                "No spell-check dictionary for {} (look for a hunspell-{} package)",
+                display_name, parts.language
+            )),
         }
-        // Ensure we don't scroll past the end
-        let max_scroll = self.menu_items.len().saturating_sub(visible_items);
-        self.scroll_offset = self.scroll_offset.min(max_scroll);
     }
 
-    fn toggle_section(&mut self) {
-        if self.menu_items.is_empty() {
+    fn apply(&mut self, kind: ItemKind) {
+        self.apply_then(kind, |_, _| {});
+    }
+
+    /// Applies `kind`, then runs `then` with the outcome once the usual
+    /// follow-ups are done. Slow changes run in the background, so `then`
+    /// may be called after this returns.
+    fn apply_then(&mut self, kind: ItemKind, then: impl FnOnce(&mut AppState, &Result<()>) + 'static) {
+        if self.refuse_change() {
             return;
         }
-        let item = &self.menu_items[self.selected];
-        if item.label.contains("Keyboard Layouts") {
-            self.keyboard_section_expanded = !self.keyboard_section_expanded;
-            self.build_menu();
-            // Keep selection on the keyboard header
-            for (i, item) in self.menu_items.iter().enumerate() {
-                if item.label.contains("Keyboard Layouts") {
-                    self.selected = i;
-                    break;
+        // Before hooks may take their time or wait on the user, so they run
+        // in the background too, ahead of the change
+        let hooks = self.config.hooks.clone();
+        let before = kind.change().filter(|(changed, _)| !changed.before(&hooks).is_empty());
+        let hooks_label = before.as_ref().map(|(changed, _)| format!("Asking the before-{} hooks", changed.name()));
+        let ask_hooks = move || match before {
+            Some((changed, to)) => hooks::run_before(&hooks, changed, &to).inspect_err(|e| {
+                notify_failure(&format!("{:#}", e));
+            }),
+            None => Ok(()),
+        };
+        // /etc is generated on NixOS; a snippet is handed over instead
+        let nixos_locale = matches!(kind, ItemKind::Locale(_)) && self.platform == system::Platform::NixOs;
+        if kind.is_slow() && !nixos_locale {
+            let label = kind.progress_label();
+            let work_kind = kind.clone();
+            let config = self.config.clone();
+            let xkb_registry = Arc::clone(&self.xkb_registry);
+            self.run_in_background(
+                label,
+                move || ask_hooks().and_then(|()| work_kind.apply(&config, &xkb_registry)),
+                move |app, result| app.finish_apply(kind, result, then),
+            );
+            return;
+        }
+        if let Some(label) = hooks_label {
+            self.spawn_job(label, ask_hooks, move |app, result| match result {
+                Ok(()) => app.apply_now(kind, then),
+                Err(e) => app.finish_apply(kind, Err(e), then),
+            });
+            return;
+        }
+        self.apply_now(kind, then);
+    }
+
+    /// Applies `kind` right here, once the before hooks have agreed.
+    fn apply_now(&mut self, kind: ItemKind, then: impl FnOnce(&mut AppState, &Result<()>)) {
+        let result = match &kind {
+            ItemKind::Locale(locale_code) if self.platform == system::Platform::NixOs => {
+                self.write_nixos_snippet(locale_code, true)
+            }
+            _ => kind.apply(&self.config, &self.xkb_registry),
+        };
+        self.finish_apply(kind, result, then);
+    }
+
+    fn finish_apply(
+        &mut self,
+        kind: ItemKind,
+        result: Result<()>,
+        then: impl FnOnce(&mut AppState, &Result<()>),
+    ) {
+        // A later success makes an older failure stale, even of another entry
+        self.failed = result.is_err().then(|| Retry::Apply(kind.clone()));
+        if result.is_ok() {
+            if let ItemKind::Locale(locale_code) = &kind {
+                if self.config.accountsservice
+                    && let Err(e) = accounts::set_language(locale_code)
+                {
+                    notify_failure(&format!("{:#}", e));
                 }
+                self.suggest_langpack(locale_code);
+                if self.platform == system::Platform::Musl {
+                    notify("musl only translates messages; formats and sorting stay as in C");
+                }
+                // Input methods belong to the local session, not the host
+                if exec::remote_host().is_none() {
+                    self.suggest_input_method(locale_code);
+                }
+                self.suggest_dictionary(locale_code);
             }
-        } else if item.label.contains("System Locales") {
-            self.locale_section_expanded = !self.locale_section_expanded;
-            self.build_menu();
-            // Keep selection on the locale header
-            for (i, item) in self.menu_items.iter().enumerate() {
-                if item.label.contains("System Locales") {
-                    self.selected = i;
-                    break;
+            // Profiles run the hooks as one of their steps
+            if let Some((changed, _)) = kind.change() {
+                // Hooks may take a while, e.g. restarting an input method
+                let hooks = self.config.hooks.clone();
+                thread::spawn(move || {
+                    if let Err(e) = hooks::run_after(&hooks, changed) {
+                        notify_failure(&format!("After-{} hook failed: {:#}", changed.name(), e));
+                    }
+                });
+            }
+            if matches!(kind.section(), Section::Keyboard | Section::Options) {
+                self.persist_keyboard();
+                self.sync_greeter();
+                if self.platform == system::Platform::NixOs {
+                    let locale = self.current_locale.clone();
+                    let _ = self.write_nixos_snippet(&locale, false);
                 }
             }
+            // Toggles flip back and forth, so they make poor shortcuts
+            if !matches!(kind, ItemKind::XkbOption(_) | ItemKind::ClearKeymap) {
+                self.recent.retain(|recent| recent != &kind);
+                self.recent.insert(0, kind);
+                self.recent.truncate(9);
+            }
         }
-        self.adjust_scroll();
+        // Refresh status after any action
+        self.refresh_status();
+        self.build_menu();
+        then(self, &result);
     }
 
-    fn execute_selected(&mut self) -> Result<bool> {
-        if self.menu_items.is_empty() {
-            return Ok(false);
+    /// Whether --read-only forbids changing anything, saying so if it does.
+    fn refuse_change(&self) -> bool {
+        if self.read_only {
+            notify_failure("Read-only: nothing can be changed from here");
         }
-        let item = &self.menu_items[self.selected];
-        // Check if it's a header (expandable section)
-        if item.label.contains("Keyboard Layouts") || item.label.contains("System Locales") {
-            self.toggle_section();
-            return Ok(false);
+        self.read_only
+    }
+
+    /// Runs `work` on a worker thread and `finish` here once it is done.
+    fn run_in_background(
+        &mut self,
+        label: String,
+        work: impl FnOnce() -> Result<()> + Send + 'static,
+        finish: impl FnOnce(&mut AppState, Result<()>) + 'static,
+    ) {
+        // Everything run this way changes something
+        if self.refuse_change() {
+            return;
+        }
+        if exec::sudo_needs_password()
+            && let Err(e) = self.authenticate_sudo()
+        {
+            notify_failure(&format!("{} skipped: {:#}", label, e));
+            return;
+        }
+        self.spawn_job(label, work, finish);
+    }
+
+    /// Starts `work` as the job in flight, as [`run_in_background`] does but
+    /// without the checks, for work that changes nothing itself.
+    ///
+    /// [`run_in_background`]: AppState::run_in_background
+    fn spawn_job(
+        &mut self,
+        label: String,
+        work: impl FnOnce() -> Result<()> + Send + 'static,
+        finish: impl FnOnce(&mut AppState, Result<()>) + 'static,
+    ) {
+        let (sender, outcome) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(work());
+        });
+        self.job = Some(Job {
+            label,
+            started: Instant::now(),
+            outcome,
+            finish: Box::new(finish),
+        });
+    }
+
+    /// Has sudo ask for the password on the plain terminal, with the TUI
+    /// put away meanwhile, so the job's own sudo has no need to.
+    fn authenticate_sudo(&mut self) -> Result<()> {
+        disable_raw_mode()?;
+        if !self.inline {
+            execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+        }
+        println!();
+        let status = exec::local("sudo").args(["-v", "-p", "levocale needs root for this; password for %p: "]).status();
+        enable_raw_mode()?;
+        if !self.inline {
+            execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        }
+        self.needs_clear = true;
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            Ok(_) => bail!("sudo didn't get a password"),
+            Err(e) => bail!("Failed to run sudo: {}", e),
+        }
+    }
+
+    /// Hands a finished job's outcome to its follow-up; called every tick.
+    fn poll_job(&mut self) {
+        let Some(job) = &self.job else {
+            return;
+        };
+        let outcome = match job.outcome.try_recv() {
+            Ok(outcome) => outcome,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow!("{} stopped unexpectedly", job.label)),
+        };
+        if let Some(job) = self.job.take() {
+            (job.finish)(self, outcome);
+        }
+    }
+
+    /// Applies the selected locale together with the keyboard layout its
+    /// language maps to, for users who keep the two in sync.
+    fn apply_locale_with_layout(&mut self) {
+        let Some(ItemKind::Locale(locale_code)) = self.pane().selected_item().map(|item| item.kind.clone()) else {
+            return;
+        };
+        self.apply_then(ItemKind::Locale(locale_code.clone()), move |app, locale_result| {
+            let layout_code = locale_to_keyboard_layout(&locale_code);
+            let locale_mark = if locale_result.is_ok() { "✓" } else { "✗" };
+            let summarize = move |layout_summary: String| {
+                let values = messages::Values {
+                    code: &locale_code,
+                    name: &layout_summary,
+                    detail: locale_mark,
+                    ..Default::default()
+                };
+                notify(&messages::text(messages::Message::LocaleAndLayout, &values))
+            };
+            match layout_code {
+                Some(layout_code) => app.apply_then(ItemKind::Layout(layout_code.clone()), move |_, layout_result| {
+                    let mark = if layout_result.is_ok() { "✓" } else { "✗" };
+                    summarize(format!("{} {}", layout_code, mark))
+                }),
+                None => summarize("no matching layout".to_string()),
+            }
+        });
+    }
+
+    /// Takes the mismatch check's answer once it has one; called every tick.
+    fn poll_mismatch(&mut self) {
+        let Some(check) = &self.mismatch_check else {
+            return;
+        };
+        match check.try_recv() {
+            Ok(mismatch) => self.mismatch = mismatch,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {}
+        }
+        self.mismatch_check = None;
+    }
+
+    /// Puts the compositor, X11, the console and LANG on the selected
+    /// locale and its language's layout, in the background.
+    fn sync_selected(&mut self) {
+        let Some(ItemKind::Locale(locale)) = self.pane().selected_item().map(|item| item.kind.clone()) else {
+            notify("Pick a locale to sync everything to");
+            return;
+        };
+        let layout = match sync_layout(&locale) {
+            Ok(layout) => layout,
+            Err(e) => {
+                notify_failure(&format!("{:#}", e));
+                return;
+            }
+        };
+        let config = self.config.clone();
+        self.run_in_background(
+            format!("Syncing everything to {}", locale),
+            move || report_sync(&locale, &profile::sync_all(&layout, &locale, &config)),
+            |app, result| {
+                if result.is_ok() {
+                    app.failed = None;
+                }
+                app.refresh_status();
+                app.build_menu();
+            },
+        );
+    }
+
+    /// Favorites from the config first, topped up with recently applied
+    /// entries, capped at the nine digit keys.
+    fn shortcuts(&self) -> Vec<ItemKind> {
+        let mut shortcuts: Vec<ItemKind> = self
+            .config
+            .favorites
+            .iter()
+            .filter_map(|entry| ItemKind::from_favorite(entry))
+            .collect();
+        for kind in &self.recent {
+            if !shortcuts.contains(kind) {
+                shortcuts.push(kind.clone());
+            }
+        }
+        let sections = self.sections();
+        shortcuts.retain(|kind| sections.contains(&kind.section()) && self.shown(kind));
+        shortcuts.truncate(9);
+        shortcuts
+    }
+
+    fn apply_shortcut(&mut self, index: usize) {
+        if let Some(kind) = self.shortcuts().get(index) {
+            self.pick_entry(kind.clone());
         }
-        // Execute regular action
-        let result = (item.action)();
-        // Refresh status after any action
-        self.refresh_status();
-        self.build_menu();
-        result.map(|_| false)
     }
 }
 
+#[track_caller]
 fn notify(msg: &str) {
-    let _ = Command::new("notify-send")
-        .arg("Levocale")
-        .arg(msg)
-        .arg("-t")
-        .arg("2000")
-        .spawn();
+    notifications::success(msg);
+}
+
+/// Like [`notify`], for errors, which can be configured separately.
+#[track_caller]
+fn notify_failure(msg: &str) {
+    notifications::failure(msg);
 }
 
 fn get_current_keyboard_layout() -> String {
-    // Try hyprctl first
-    if let Ok(output) = Command::new("hyprctl").args(["devices"]).output() {
+    // Ask the active backend for the layout code first
+    if let Some(layout) = backend::active().current_layout() {
+        return layout;
+    }
+    // Fall back to the active keymap hyprctl reports
+    if let Ok(output) = exec::local("hyprctl").args(["devices"]).output() {
         let output_str = String::from_utf8_lossy(&output.stdout);
         // Look for keyboard section and active layout
         for line in output_str.lines() {
-            if line.contains("active keymap:") {
-                if let Some(layout) = line.split("active keymap:").nth(1) {
-                    return layout.trim().to_string();
-                }
+            if line.contains("active keymap:")
+                && let Some(layout) = line.split("active keymap:").nth(1)
+            {
+                return layout.trim().to_string();
             }
         }
     }
     // Fallback to setxkbmap
-    if let Ok(output) = Command::new("setxkbmap").args(["-query"]).output() {
+    if let Ok(output) = exec::local("setxkbmap").args(["-query"]).output() {
         let output_str = String::from_utf8_lossy(&output.stdout);
         for line in output_str.lines() {
-            if line.starts_with("layout:") {
-                if let Some(layout) = line.split(':').nth(1) {
-                    return layout.trim().to_string();
-                }
+            if line.starts_with("layout:")
+                && let Some(layout) = line.split(':').nth(1)
+            {
+                return layout.trim().to_string();
             }
         }
     }
@@ -234,27 +2530,25 @@ fn get_current_keyboard_layout() -> String {
 }
 
 fn get_current_locale() -> String {
+    // Our own environment says nothing about another machine
+    if exec::remote_host().is_some() {
+        return backend::active_locale().current_locale().unwrap_or_else(|| "unknown".to_string());
+    }
     // Try reading from locale command first (more reliable)
-    if let Ok(output) = Command::new("locale").output() {
+    if let Ok(output) = exec::local("locale").output() {
         let output_str = String::from_utf8_lossy(&output.stdout);
         for line in output_str.lines() {
-            if line.starts_with("LANG=") {
-                if let Some(locale) = line.split('=').nth(1) {
-                    return locale.trim_matches('"').to_string();
-                }
+            if line.starts_with("LANG=")
+                && let Some(locale) = line.split('=').nth(1)
+                && !locale.trim_matches('"').is_empty()
+            {
+                return locale.trim_matches('"').to_string();
             }
         }
     }
-    // Fallback to localectl
-    if let Ok(output) = Command::new("localectl").args(["status"]).output() {
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        for line in output_str.lines() {
-            if line.trim().starts_with("LANG=") {
-                if let Some(locale) = line.split('=').nth(1) {
-                    return locale.trim().to_string();
-                }
-            }
-        }
+    // Fallback to the system setting
+    if let Some(locale) = backend::active_locale().current_locale() {
+        return locale;
     }
     // Last resort: check environment variable
     std::env::var("LANG").unwrap_or_else(|_| "unknown".to_string())
@@ -288,6 +2582,9 @@ fn locale_to_keyboard_layout(locale_code: &str) -> Option<String> {
                 "hu" => "hu".to_string(),  // Hungarian
                 "tr" => "tr".to_string(),  // Turkish
                 "ar" => "ara".to_string(), // Arabic
+                "he" => "il".to_string(),  // Hebrew
+                "fa" => "ir".to_string(),  // Persian
+                "ur" => "pk".to_string(),  // Urdu
                 "hi" => "in".to_string(),  // Hindi (India layout)
                 "th" => "th".to_string(),  // Thai
                 "vi" => "vn".to_string(),  // Vietnamese
@@ -307,11 +2604,13 @@ fn locale_to_keyboard_layout(locale_code: &str) -> Option<String> {
 }
 
 fn get_available_keyboard_layouts() -> Vec<(String, String)> {
+    if let Some(layouts) = backend::active().available_layouts() {
+        return layouts;
+    }
     let mut layouts = Vec::new();
-    let available_locales = get_available_locales();
-    for (locale_code, display_name) in available_locales {
+    for locale_code in get_available_locales() {
         if let Some(layout_code) = locale_to_keyboard_layout(&locale_code) {
-            layouts.push((layout_code, display_name));
+            layouts.push((layout_code, locale_display_name(&locale_code)));
         }
     }
     // Remove duplicates (e.g., if multiple English locales map to "us")
@@ -320,53 +2619,210 @@ fn get_available_keyboard_layouts() -> Vec<(String, String)> {
     layouts
 }
 
-fn switch_to_keyboard_layout(layout_code: &str) -> Result<()> {
-    let result = Command::new("hyprctl")
-        .args(["keyword", "input:kb_layout", layout_code])
-        .output();
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                notify(&format!("Keyboard layout set to: {}", layout_code));
-                Ok(())
-            } else {
-                let error = String::from_utf8_lossy(&output.stderr);
-                notify(&format!("Failed to set keyboard layout: {}", error.trim()));
-                bail!("Failed to set keyboard layout: {}", error.trim())
+/// Reports an applied keyboard change, flagging backends that only pick it
+/// up after a restart.
+fn notify_keyboard_change(backend: &dyn backend::KeyboardBackend, message: &str) {
+    if backend.needs_restart() {
+        let values = messages::Values { backend: backend.name(), detail: message, ..Default::default() };
+        notify(&messages::text(messages::Message::RestartNeeded, &values));
+    } else {
+        notify(message);
+    }
+}
+
+/// Makes the same change on the `mirror_keyboard` backends and adds how
+/// each went to `message`, e.g. "… (x11 ✓, console ✗)". Their errors are
+/// announced separately.
+fn with_mirrors(message: String, config: &config::Config, layout: Option<&str>, options: Option<&str>) -> String {
+    let results = backend::mirror_keyboard(config, layout, options);
+    if results.is_empty() {
+        return message;
+    }
+    let mut marks = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(()) => marks.push(format!("{} ✓", name)),
+            Err(e) => {
+                notify_failure(&format!("{}: {:#}", name, e));
+                marks.push(format!("{} ✗", name));
             }
         }
+    }
+    format!("{} ({})", message, marks.join(", "))
+}
+
+fn switch_to_keyboard_layout(
+    layout_code: &str,
+    config: &config::Config,
+    xkb_registry: &xkb::XkbRegistry,
+) -> Result<()> {
+    let backend = backend::active();
+    match backend.set_layout(layout_code) {
+        Ok(()) => {
+            let values = messages::Values {
+                code: layout_code,
+                name: xkb_registry.describe(layout_code).unwrap_or(layout_code),
+                backend: backend.name(),
+                ..Default::default()
+            };
+            let message = messages::text(messages::Message::LayoutSet, &values);
+            notify_keyboard_change(backend.as_ref(), &with_mirrors(message, config, Some(layout_code), None));
+            Ok(())
+        }
         Err(e) => {
-            notify(&format!("Failed to execute hyprctl: {}", e));
-            bail!("Failed to execute hyprctl: {}", e)
+            notify_failure(&format!("{:#}", e));
+            Err(e)
         }
     }
 }
 
-fn get_available_locales() -> Vec<(String, String)> {
-    let mut locales = Vec::new();
-    if let Ok(output) = Command::new("localectl").args(["list-locales"]).output() {
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        for line in output_str.lines() {
-            let locale_code = line.trim().to_string();
-            if !locale_code.is_empty() {
-                // Create a display name from the locale code
-                let display_name = locale_code_to_display_name(&locale_code);
-                locales.push((locale_code, display_name));
+fn keymap_name(path: &str) -> &str {
+    std::path::Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(path)
+}
+
+fn switch_keymap_file(path: Option<&str>) -> Result<()> {
+    let backend = backend::active();
+    match backend.set_keymap_file(path) {
+        Ok(()) => {
+            match path {
+                Some(path) => {
+                    let name = keymap_name(path);
+                    let values = messages::Values { code: path, name, backend: backend.name(), ..Default::default() };
+                    notify(&messages::text(messages::Message::KeymapLoaded, &values))
+                }
+                None => notify(&messages::text(messages::Message::KeymapCleared, &messages::Values::default())),
             }
+            Ok(())
+        }
+        Err(e) => {
+            notify_failure(&format!("{:#}", e));
+            Err(e)
+        }
+    }
+}
+
+fn compose_key(options: &str) -> Option<&str> {
+    options
+        .split(',')
+        .find_map(|option| option.trim().strip_prefix("compose:"))
+}
+
+fn toggle_xkb_option(option: &str, config: &config::Config) -> Result<()> {
+    let backend = backend::active();
+    let options = backend.options().unwrap_or_default();
+    let enable = !xkb::has_option(&options, option);
+    let new_options = xkb::toggle_option(&options, option);
+    match backend.set_options(&new_options) {
+        Ok(()) => {
+            let kind = if enable { messages::Message::OptionEnabled } else { messages::Message::OptionDisabled };
+            let values = messages::Values { code: option, name: option, backend: backend.name(), ..Default::default() };
+            let message = messages::text(kind, &values);
+            notify_keyboard_change(backend.as_ref(), &with_mirrors(message, config, None, Some(&new_options)));
+            Ok(())
+        }
+        Err(e) => {
+            notify_failure(&format!("{:#}", e));
+            Err(e)
+        }
+    }
+}
+
+fn toggle_compose_key(config: &config::Config) -> Result<()> {
+    let compose = &config.compose_key;
+    let backend = backend::active();
+    let options = backend.options().unwrap_or_default();
+    let enable = compose_key(&options).is_none();
+    // Drop whichever compose:* option is set so we never end up with two
+    let mut new_options = xkb::remove_options_with_prefix(&options, "compose:");
+    if enable {
+        new_options = xkb::merge_option(&new_options, &format!("compose:{}", compose), true);
+    }
+    match backend.set_options(&new_options) {
+        Ok(()) => {
+            let kind = if enable { messages::Message::ComposeEnabled } else { messages::Message::ComposeDisabled };
+            let values =
+                messages::Values { code: compose, name: compose, backend: backend.name(), ..Default::default() };
+            let message = messages::text(kind, &values);
+            notify_keyboard_change(backend.as_ref(), &with_mirrors(message, config, None, Some(&new_options)));
+            Ok(())
+        }
+        Err(e) => {
+            notify_failure(&format!("{:#}", e));
+            Err(e)
         }
     }
-    // If localectl fails, return a minimal fallback
+}
+
+fn switch_input_method(engine: &str) -> Result<()> {
+    let Some(framework) = input_method::Framework::detect() else {
+        notify_failure("No input method framework is running");
+        bail!("No input method framework is running")
+    };
+    match framework.switch_engine(engine) {
+        Ok(()) => {
+            let values =
+                messages::Values { code: engine, name: engine, backend: framework.name(), ..Default::default() };
+            notify(&messages::text(messages::Message::InputMethodSet, &values));
+            Ok(())
+        }
+        Err(e) => {
+            notify_failure(&format!("{:#}", e));
+            Err(e)
+        }
+    }
+}
+
+fn get_available_locales() -> Vec<String> {
+    let mut locales = backend::active_locale().available_locales();
+    // Backends already fall back on glibc's SUPPORTED list; this is for
+    // systems that have neither
     if locales.is_empty() {
-        locales.push(("en_US.UTF-8".to_string(), "English (US)".to_string()));
-        locales.push(("C.UTF-8".to_string(), "C (POSIX)".to_string()));
+        locales.push("en_US.UTF-8".to_string());
+        locales.push("C.UTF-8".to_string());
+    }
+    locales
+}
+
+/// `locales` topped up with the rest of what glibc's SUPPORTED list could
+/// generate, whatever the backend offers itself.
+fn with_supported_locales(mut locales: Vec<String>) -> Vec<String> {
+    let mut seen: HashSet<String> = locales.iter().map(|code| locale_info::normalized_locale_name(code)).collect();
+    for (name, _) in locale_info::supported_locales() {
+        if seen.insert(locale_info::normalized_locale_name(&name)) {
+            locales.push(name);
+        }
     }
     locales
 }
 
+/// Display names worked out so far, kept across menu rebuilds. Cleared
+/// when it grows past `DISPLAY_NAME_CACHE_SIZE`, which no system's list of
+/// locales comes near.
+static DISPLAY_NAMES: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+const DISPLAY_NAME_CACHE_SIZE: usize = 2048;
+
+fn locale_display_name(locale_code: &str) -> String {
+    let Ok(mut names) = DISPLAY_NAMES.lock() else {
+        return locale_code_to_display_name(locale_code);
+    };
+    if let Some(name) = names.get(locale_code) {
+        return name.clone();
+    }
+    if names.len() >= DISPLAY_NAME_CACHE_SIZE {
+        names.clear();
+    }
+    let name = locale_code_to_display_name(locale_code);
+    names.insert(locale_code.to_string(), name.clone());
+    name
+}
+
 fn locale_code_to_display_name(locale_code: &str) -> String {
     // Convert locale codes to human-readable names
     match locale_code {
-        "C" | "C.UTF-8" => "C (POSIX)".to_string(),
+        "C" | "C.UTF-8" | "C.utf8" => "C (POSIX)".to_string(),
         code if code.starts_with("en_US") => "English (US)".to_string(),
         code if code.starts_with("en_GB") => "English (UK)".to_string(),
         code if code.starts_with("da_DK") => "Danish (Denmark)".to_string(),
@@ -391,6 +2847,10 @@ fn locale_code_to_display_name(locale_code: &str) -> String {
         code if code.starts_with("hu_HU") => "Hungarian (Hungary)".to_string(),
         code if code.starts_with("tr_TR") => "Turkish (Turkey)".to_string(),
         code if code.starts_with("ar_SA") => "Arabic (Saudi Arabia)".to_string(),
+        code if code.starts_with("ar_EG") => "Arabic (Egypt)".to_string(),
+        code if code.starts_with("he_IL") => "Hebrew (Israel)".to_string(),
+        code if code.starts_with("fa_IR") => "Persian (Iran)".to_string(),
+        code if code.starts_with("ur_PK") => "Urdu (Pakistan)".to_string(),
         code if code.starts_with("hi_IN") => "Hindi (India)".to_string(),
         code if code.starts_with("th_TH") => "Thai (Thailand)".to_string(),
         code if code.starts_with("vi_VN") => "Vietnamese (Vietnam)".to_string(),
@@ -409,28 +2869,182 @@ fn locale_code_to_display_name(locale_code: &str) -> String {
     }
 }
 
+fn native_language_name(locale_code: &str) -> Option<&'static str> {
+    // Endonyms for languages whose English name differs from what speakers
+    // would look for; shown next to the English display name
+    let lang = locale_code.split(['_', '.', '@']).next()?;
+    let native = match lang {
+        "ar" => "العربية",
+        "he" => "עברית",
+        "fa" => "فارسی",
+        "ur" => "اردو",
+        "yi" => "ייִדיש",
+        "zh" => "中文",
+        "ja" => "日本語",
+        "ko" => "한국어",
+        "ru" => "Русский",
+        "th" => "ไทย",
+        "hi" => "हिन्दी",
+        "el" => "Ελληνικά",
+        "uk" => "Українська",
+        _ => return None,
+    };
+    Some(native)
+}
+
+fn bidi_visual_order(text: &str) -> String {
+    // Terminals draw cells in logical order, so right-to-left runs (Arabic,
+    // Hebrew, ...) come out reversed unless we reorder them ourselves. The
+    // paragraph level is pinned to LTR so the surrounding menu chrome stays put.
+    let bidi_info = BidiInfo::new(text, Some(Level::ltr()));
+    if !bidi_info.has_rtl() {
+        return text.to_string();
+    }
+    bidi_info
+        .paragraphs
+        .iter()
+        .map(|para| bidi_info.reorder_line(para, para.range.clone()))
+        .collect()
+}
+
+/// Switches every setting a saved profile declares, announcing the outcome.
+fn apply_profile(name: &str, config: &config::Config) -> Result<()> {
+    report_profile(name, &profile::Profile::named(name)?.apply_steps(config))
+}
+
+/// The first layout of `code` without its variant, e.g. "de" for
+/// "de(nodeadkeys),us" or the console's "de-latin1".
+fn base_layout(code: &str) -> &str {
+    let first = code.split(',').next().unwrap_or(code).trim();
+    first.split(['(', '-']).next().unwrap_or(first)
+}
+
+/// Compares the compositor's `layout` with X11's and the console's, and
+/// with the layout LANG's language goes with.
+fn find_mismatch(layout: &str, locale: &str) -> Option<Mismatch> {
+    let keyboard = backend::active();
+    let mut found = Vec::new();
+    for name in ["x11", "console"] {
+        let other = backend::named(name);
+        if other.name() == keyboard.name() {
+            continue;
+        }
+        if let Some(theirs) = other.current_layout()
+            && base_layout(&theirs) != base_layout(layout)
+        {
+            found.push(format!("{} {}", name, theirs));
+        }
+    }
+    // Several layouts are fine as long as one of them fits
+    let lang_layout = locale_to_keyboard_layout(locale)
+        .filter(|wanted| !layout.split(',').any(|code| base_layout(code) == wanted));
+    if let Some(wanted) = &lang_layout {
+        found.push(format!("LANG {} ({})", locale, wanted));
+    }
+    if found.is_empty() {
+        return None;
+    }
+    Some(Mismatch(format!("{} {}, {}", keyboard.name(), layout, found.join(", "))))
+}
+
+/// The layout a sync to `locale` puts every keyboard on.
+fn sync_layout(locale: &str) -> Result<String> {
+    locale_to_keyboard_layout(locale).ok_or_else(|| anyhow!("No keyboard layout is known to go with {}", locale))
+}
+
+/// Announces how each part of a sync went, in one line with the failures
+/// announced separately as well.
+fn report_sync(locale: &str, outcome: &profile::Outcome) -> Result<()> {
+    let marks: Vec<String> = outcome
+        .steps
+        .iter()
+        .map(|(step, result)| format!("{} {}", step, if result.is_ok() { "✓" } else { "✗" }))
+        .collect();
+    let marks = marks.join(", ");
+    let values = messages::Values { code: locale, name: locale, detail: &marks, ..Default::default() };
+    let summary = messages::text(messages::Message::Synced, &values);
+    let failed = outcome.failed();
+    if failed == 0 {
+        notify(&summary);
+        return Ok(());
+    }
+    notify_failure(&summary);
+    for (step, result) in &outcome.steps {
+        if let Err(e) = result {
+            notify_failure(&format!("{}: {:#}", step, e));
+        }
+    }
+    for (step, result) in &outcome.reverted {
+        match result {
+            Ok(()) => notify(&messages::text(
+                messages::Message::Reverted,
+                &messages::Values { detail: step, ..Default::default() },
+            )),
+            Err(e) => notify_failure(&format!("Couldn't revert {}: {:#}", step, e)),
+        }
+    }
+    bail!("{} of {} steps of the sync failed", failed, outcome.steps.len())
+}
+
+fn report_profile(name: &str, outcome: &profile::Outcome) -> Result<()> {
+    let failures: Vec<String> = outcome
+        .steps
+        .iter()
+        .filter_map(|(step, result)| Some(format!("{}: {:#}", step, result.as_ref().err()?)))
+        .collect();
+    if failures.is_empty() {
+        let values = messages::Values { code: name, name, backend: backend::active().name(), ..Default::default() };
+        notify(&messages::text(messages::Message::ProfileApplied, &values));
+        return Ok(());
+    }
+    for failure in &failures {
+        notify_failure(failure);
+    }
+    for (step, result) in &outcome.reverted {
+        match result {
+            Ok(()) => notify(&messages::text(
+                messages::Message::Reverted,
+                &messages::Values { detail: step, ..Default::default() },
+            )),
+            Err(e) => notify_failure(&format!("Couldn't revert {}: {:#}", step, e)),
+        }
+    }
+    bail!("{} of {} steps of profile {} failed", failures.len(), outcome.steps.len(), name)
+}
+
+/// What a profile sets and which of those settings would change.
+fn profile_details(name: &str) -> Vec<String> {
+    let profile = match profile::Profile::named(name) {
+        Ok(profile) => profile,
+        Err(e) => return vec![format!("{:#}", e)],
+    };
+    let mut lines = vec![format!("Profile: {}", name)];
+    let changes = profile.changes();
+    if changes.is_empty() {
+        lines.push("All of its settings are in effect".to_string());
+        return lines;
+    }
+    lines.push(String::new());
+    lines.push("Applying it changes:".to_string());
+    for change in changes {
+        lines.push(format!("  {}", change.describe()));
+    }
+    lines
+}
+
 fn set_locale(locale_code: &str) -> Result<()> {
-    let result = Command::new("sudo")
-        .args(["localectl", "set-locale", &format!("LANG={}", locale_code)])
-        .output();
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                let display_name = get_available_locales()
-                    .iter()
-                    .find(|(code, _)| code == locale_code)
-                    .map(|(_, name)| name.clone())
-                    .unwrap_or_else(|| locale_code.to_string());
-                notify(&format!("Language set to: {}", display_name));
-                Ok(())
-            } else {
-                notify("Failed to set language (check sudo access)");
-                bail!("Failed to set language")
-            }
+    let backend = backend::active_locale();
+    match backend.validate(locale_code).and_then(|()| backend.set_locale(locale_code)) {
+        Ok(()) => {
+            let name = locale_display_name(locale_code);
+            let values =
+                messages::Values { code: locale_code, name: &name, backend: backend.name(), ..Default::default() };
+            notify(&messages::text(messages::Message::LocaleSet, &values));
+            Ok(())
         }
-        Err(_) => {
-            notify("Failed to set language (check sudo access)");
-            bail!("Failed to set language")
+        Err(e) => {
+            notify_failure(&format!("{:#}", e));
+            Err(e)
         }
     }
 }
@@ -458,17 +3072,91 @@ fn cleanup_terminal() -> Result<()> {
 }
 
 fn main() -> Result<()> {
+    let args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("levocale: {:#}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.version {
+        println!("levocale {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if let Some(seat) = &args.seat {
+        // Other seats are told apart on this machine only
+        let problem = if args.host.is_some() {
+            Some("--seat can't be combined with --host".to_string())
+        } else if !seat::list().contains(seat) {
+            Some(format!("No seat named {} (seats: {})", seat, seat::list().join(", ")))
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            eprintln!("levocale: {}", problem);
+            std::process::exit(2);
+        }
+        seat::set_target(seat);
+    }
+    if let Some(name) = &args.backend
+        && let Err(e) = backend::force(name)
+    {
+        eprintln!("levocale: {:#}", e);
+        std::process::exit(2);
+    }
+    if let Some(host) = args.host {
+        exec::set_remote_host(host);
+    }
+    if let Some(mode) = args.mode {
+        exec::set_mode(mode);
+    }
+    if let Some(command) = args.command {
+        let result = run_command(command);
+        // Exiting would cut off notifications still on their way
+        notifications::wait();
+        if let Err(e) = result {
+            eprintln!("levocale: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.plain {
+        return plain::run(args.read_only);
+    }
+    let Some(instance) = ipc::claim_instance()? else {
+        println!("levocale is already running; switched to it");
+        return Ok(());
+    };
+
     // Setup signal handlers
     let running = setup_signal_handlers();
 
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
+    if let Some(height) = args.inline {
+        // Drawn below the prompt, leaving the scrollback alone
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal =
+            Terminal::with_options(backend, TerminalOptions { viewport: Viewport::Inline(height) })?;
+        let res = run_app(&mut terminal, running, true, args.read_only, &instance);
+        let _ = fs::remove_file(ipc::instance_path());
+        // Clearing puts the cursor back where the viewport started
+        terminal.clear()?;
+        disable_raw_mode()?;
+        terminal.show_cursor()?;
+        return res;
+    }
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let res = run_app(&mut terminal, running);
+    let res = run_app(&mut terminal, running, false, args.read_only, &instance);
+    let _ = fs::remove_file(ipc::instance_path());
 
     // Cleanup terminal
     cleanup_terminal()?;
@@ -477,38 +3165,684 @@ fn main() -> Result<()> {
     res
 }
 
+/// Smallest terminal the interface is drawn in at all.
+const MIN_WIDTH: u16 = 30;
+const MIN_HEIGHT: u16 = 8;
+/// Below this height the status and controls shrink to a line each.
+const COMPACT_HEIGHT: u16 = 24;
+/// Below this height the status is left out.
+const TINY_HEIGHT: u16 = 12;
+
+/// The controls line cut down to what fits on one line.
+fn short_controls(keys: &keys::Bindings) -> String {
+    format!(
+        "{}/{} Move • {} Select • {} Search • Tab Pane • {} Quit",
+        keys.label(keys::Action::Up),
+        keys.label(keys::Action::Down),
+        keys.label(keys::Action::Select),
+        keys.label(keys::Action::Search),
+        keys.label(keys::Action::Quit),
+    )
+}
+
+/// Shown instead of the interface when it can't be drawn sensibly.
+fn render_too_small(f: &mut Frame, area: Rect) {
+    let text = format!(
+        "Terminal too small\n{}x{}, needs {}x{}",
+        area.width, area.height, MIN_WIDTH, MIN_HEIGHT
+    );
+    let lines = Rect { y: area.y + area.height.saturating_sub(2) / 2, height: area.height.min(2), ..area };
+    f.render_widget(
+        Paragraph::new(text).style(theme::current().warning).alignment(Alignment::Center).wrap(Wrap { trim: true }),
+        lines,
+    );
+}
+
+/// The whole interface squeezed into an inline viewport: a status line, the
+/// focused pane, and one line for whatever needs attention.
+fn render_compact(f: &mut Frame, app_state: &mut AppState) {
+    let size = f.size();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
+        .split(size);
+    let mut status = format!("Locale: {} | Layout: {}", app_state.current_locale, app_state.current_layout);
+    status.push_str(&format!(" | {} mode", exec::mode().name()));
+    if app_state.read_only {
+        status.push_str(" | read-only");
+    }
+    f.render_widget(Paragraph::new(status).style(theme::current().dim), chunks[0]);
+
+    let title = app_state.pane_title(app_state.focus);
+    render_menu_list(f, chunks[1], app_state.pane_mut(), &title, true);
+
+    let (line, style) = if let Some(job) = &app_state.job {
+        (format!("{}… ({}s)", job.label, job.started.elapsed().as_secs()), theme::current().frame)
+    } else if let Some(prompt) = app_state.prompts.front() {
+        (format!("{} [y/n]", prompt.message), theme::current().popup)
+    } else if let Some(name) = &app_state.profile_name_input {
+        (format!("Save profile as: {}_", name), theme::current().popup)
+    } else if let Some((text, _)) = &app_state.search_input {
+        (format!("Search: {}_", text), theme::current().popup)
+    } else if let Some((message, failure, _)) = notifications::toasts().pop() {
+        (message, if failure { theme::current().failure } else { theme::current().success })
+    } else {
+        (short_controls(&app_state.keys), theme::current().dim)
+    };
+    f.render_widget(Paragraph::new(line).style(style), chunks[2]);
+
+    if let Some(test) = &app_state.typing_test {
+        render_typing_test(f, size, test, &app_state.current_layout);
+    } else if let Some(screen) = &app_state.devices_screen {
+        render_devices(f, size, screen, &app_state.config);
+    } else if let Some(form) = &app_state.locale_form {
+        render_locale_form(f, size, form);
+    } else if let Some(preview) = &app_state.profile_preview {
+        render_profile_preview(f, size, preview);
+    } else if let Some(scroll) = app_state.log_scroll {
+        render_log(f, size, scroll);
+    }
+}
+
+fn render_menu_list(f: &mut Frame, area: Rect, list: &mut MenuList, title: &str, focused: bool) {
+    // Calculate visible area for menu
+    let menu_height = area.height.saturating_sub(2) as usize; // -2 for borders
+    let item_height = 2; // Each item takes 2 lines
+    let visible_items = menu_height / item_height;
+
+    // Update scroll based on actual visible area
+    list.adjust_scroll_for_height(visible_items);
+
+    // Get visible menu items
+    let end_index = (list.scroll_offset + visible_items).min(list.items.len());
+    for item in list.items.get_mut(list.scroll_offset..end_index).unwrap_or_default() {
+        item.name();
+    }
+    let visible_menu_items = if list.items.is_empty() {
+        &[]
+    } else {
+        &list.items[list.scroll_offset..end_index]
+    };
+
+    // Menu area
+    let theme = theme::current();
+    let menu_block = Block::default()
+        .borders(Borders::ALL)
+        .title(title.to_string())
+        .border_style(if focused { theme.focused } else { theme.faint });
+    let menu_inner = menu_block.inner(area);
+    f.render_widget(menu_block, area);
+
+    if visible_menu_items.is_empty() {
+        return;
+    }
+
+    // Create constraints for visible items
+    let menu_constraints: Vec<Constraint> = visible_menu_items
+        .iter()
+        .map(|_| Constraint::Length(item_height as u16))
+        .collect();
+
+    let menu_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(menu_constraints)
+        .split(menu_inner);
+
+    // Render visible menu items
+    for (i, item) in visible_menu_items.iter().enumerate() {
+        let global_index = list.scroll_offset + i;
+        let is_header = matches!(item.kind, ItemKind::Header(_));
+
+        let (style, prefix) = if global_index == list.selected && !focused {
+            // Remember the position in unfocused panes without competing
+            // with the real cursor
+            (theme.inactive_selection, "  ")
+        } else if global_index == list.selected {
+            if is_header {
+                (theme.header_selection, "► ")
+            } else {
+                (theme.selection, "► ")
+            }
+        } else if is_header {
+            (theme.header, "  ")
+        } else {
+            (theme.text, "  ")
+        };
+
+        let content = format!(
+            "{}{}\n{}",
+            prefix,
+            bidi_visual_order(&item.label),
+            item.description
+        );
+        let paragraph = Paragraph::new(content)
+            .style(style);
+
+        if i < menu_chunks.len() {
+            f.render_widget(paragraph, menu_chunks[i]);
+        }
+    }
+}
+
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Stacks recent toasts in the bottom-right corner above the controls,
+/// dimming each over its last second.
+fn render_toasts(f: &mut Frame, area: Rect) {
+    let max_width = (area.width / 2).max(20).min(area.width.saturating_sub(2));
+    let mut bottom = area.y + area.height.saturating_sub(4);
+    for (message, failure, age) in notifications::toasts().iter().rev() {
+        let text_width = max_width.saturating_sub(2).max(1);
+        let lines = (message.chars().count() as u16).div_ceil(text_width).clamp(1, 3);
+        let width = (message.chars().count() as u16 + 2).clamp(12, max_width);
+        let height = lines + 2;
+        if bottom < area.y + height {
+            break;
+        }
+        let rect = Rect {
+            x: area.x + area.width.saturating_sub(width + 1),
+            y: bottom - height,
+            width,
+            height,
+        };
+        let fading = *age + Duration::from_secs(1) >= notifications::TOAST_LIFETIME;
+        let theme = theme::current();
+        let border = match (fading, failure) {
+            (true, _) => theme.faint,
+            (false, true) => theme.failure,
+            (false, false) => theme.success,
+        };
+        let paragraph = Paragraph::new(message.as_str())
+            .style(if fading { theme.faint } else { theme.text })
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).border_style(border));
+        f.render_widget(Clear, rect);
+        f.render_widget(paragraph, rect);
+        bottom -= height;
+    }
+}
+
+/// Room for one "LC_IDENTIFICATION=en_US.UTF-8" entry and its gap.
+const ENVIRONMENT_CELL_WIDTH: u16 = 34;
+
+/// The expanded status view: LANG, LANGUAGE and LC_ALL on one line, then the
+/// categories `per_row` at a time. Explicit settings are highlighted over
+/// ones implied by LANG, and a set LC_ALL in red.
+fn environment_lines(variables: &[locale_info::LocaleVariable], per_row: usize) -> Vec<Line<'static>> {
+    let span = |variable: &locale_info::LocaleVariable| {
+        let value = if variable.value.is_empty() { "(unset)" } else { &variable.value };
+        let theme = theme::current();
+        let style = match (variable.explicit, variable.name.as_str()) {
+            (true, "LC_ALL") => theme.failure,
+            (true, _) => theme.warning,
+            (false, _) => theme.dim,
+        };
+        Span::styled(
+            format!("{:<width$}", format!("{}={}", variable.name, value), width = ENVIRONMENT_CELL_WIDTH as usize - 2),
+            style,
+        )
+    };
+    let join = |variables: &[&locale_info::LocaleVariable]| {
+        let mut spans = Vec::new();
+        for (i, variable) in variables.iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::raw("  "));
+            }
+            spans.push(span(variable));
+        }
+        Line::from(spans)
+    };
+    let (categories, general): (Vec<_>, Vec<_>) = variables
+        .iter()
+        .partition(|variable| variable.name.starts_with("LC_") && variable.name != "LC_ALL");
+    let mut lines = vec![join(&general)];
+    lines.extend(categories.chunks(per_row).map(join));
+    lines
+}
+
+fn render_job(f: &mut Frame, area: Rect, job: &Job) {
+    const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+    let elapsed = job.started.elapsed();
+    let frame = FRAMES[(elapsed.as_millis() / 100) as usize % FRAMES.len()];
+    let text = format!("{} {}… ({}s)", frame, job.label, elapsed.as_secs());
+    let width = (text.chars().count() as u16 + 6).max(30);
+    let popup = centered_rect(width, 3, area);
+    let paragraph = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .style(theme::current().text)
+        .block(Block::default().borders(Borders::ALL).border_style(theme::current().frame));
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn render_typing_test(f: &mut Frame, area: Rect, test: &TypingTest, layout: &str) {
+    let popup = centered_rect(60.min(area.width), 9, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("⌨ Typing test ({})", layout))
+        .border_style(theme::current().popup);
+    // Dead keys and combining marks are easier to check by code point
+    let last = match test.text.chars().last() {
+        Some(c) => format!("Last: {} U+{:04X}", c, c as u32),
+        None => "Type anything; dead keys and AltGr should work".to_string(),
+    };
+    let keys = match &test.revert_to {
+        Some(previous) => format!("[Enter] Keep   [Esc] Back to {}", previous),
+        None => "[Enter/Esc] Close".to_string(),
+    };
+    // Keep the tail in view once it outgrows two lines
+    let room = 2 * popup.width.saturating_sub(4) as usize;
+    let skip = test.text.chars().count().saturating_sub(room);
+    let typed: String = test.text.chars().skip(skip).collect();
+    let text = format!("{}_\n\n{}\n\n{}", typed, last, keys);
+    let paragraph = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .style(theme::current().text)
+        .wrap(Wrap { trim: false })
+        .block(block);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn render_devices(f: &mut Frame, area: Rect, screen: &DevicesScreen, config: &config::Config) {
+    let height = (screen.devices.len() as u16 + 6).min(area.height);
+    let popup = centered_rect(76.min(area.width), height, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("⌨ Keyboards")
+        .border_style(theme::current().popup);
+    let mut lines = Vec::new();
+    for (i, device) in screen.devices.iter().enumerate() {
+        let rule = match (config.device_rule(device), &screen.editing) {
+            (_, Some((field, text))) if i == screen.selected => {
+                let name = if *field == DeviceField::Layout { "Layout" } else { "Options" };
+                format!("{}: {}_", name, text)
+            }
+            (Some((pattern, rule)), _) if pattern == device => daemon::describe_rule(rule),
+            (Some((pattern, rule)), _) => format!("{} (from {})", daemon::describe_rule(rule), pattern),
+            (None, _) => "shared layout".to_string(),
+        };
+        let style = if i == screen.selected {
+            theme::current().selection
+        } else {
+            theme::current().text
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!(" {:<36} ", device), style),
+            Span::styled(rule, theme::current().dim),
+        ]));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(if screen.editing.is_some() {
+        "[Enter] Save and apply   [Esc] Cancel   (empty clears)"
+    } else {
+        "[Enter] Layout   [o] Options   [Del] Remove rule   [Esc] Close"
+    }));
+    let paragraph = Paragraph::new(lines).block(block);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn render_locale_form(f: &mut Frame, area: Rect, form: &LocaleForm) {
+    let popup = centered_rect(52, 9, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("🛠 Compile a locale")
+        .border_style(theme::current().popup);
+    let fields = [
+        (LocaleField::Source, "Definition", form.source.clone()),
+        (LocaleField::Charmap, "Charmap", form.charmap.clone()),
+        (LocaleField::Name, "Name", form.name()),
+    ];
+    let mut lines: Vec<Line> = fields
+        .into_iter()
+        .map(|(field, label, value)| {
+            if field == form.field {
+                Line::styled(format!(" {:<11}{}_", label, value), theme::current().selection)
+            } else {
+                Line::styled(format!(" {:<11}{}", label, value), theme::current().text)
+            }
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::styled(" [Tab] Complete   [↑/↓] Field", theme::current().dim));
+    lines.push(Line::styled(" [Enter] Compile with localedef   [Esc] Cancel", theme::current().dim));
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+fn render_profile_preview(f: &mut Frame, area: Rect, preview: &ProfilePreview) {
+    let height = (preview.changes.len() as u16 + 4).min(area.height);
+    let popup = centered_rect(70.min(area.width), height, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("📝 Apply profile {}", preview.name))
+        .border_style(theme::current().popup);
+    let mut lines: Vec<Line> = preview
+        .changes
+        .iter()
+        .zip(&preview.chosen)
+        .enumerate()
+        .map(|(i, (change, chosen))| {
+            let checkbox = if *chosen { "[x]" } else { "[ ]" };
+            let style = if i == preview.selected {
+                theme::current().selection
+            } else if *chosen {
+                theme::current().text
+            } else {
+                theme::current().faint
+            };
+            Line::styled(format!(" {} {}", checkbox, change.describe()), style)
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::styled(" [Space] Pick   [Enter] Apply picked   [Esc] Cancel", theme::current().dim));
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+/// The log's lines, oldest first.
+fn log_lines() -> Vec<Line<'static>> {
+    log::entries()
+        .into_iter()
+        .map(|entry| match entry.kind {
+            log::Kind::Command => Line::styled(format!("{} $ {}", entry.time, entry.text), theme::current().text),
+            log::Kind::Output => Line::styled(format!("         {}", entry.text), theme::current().dim),
+            log::Kind::Message => Line::styled(format!("{} • {}", entry.time, entry.text), theme::current().success),
+            log::Kind::Error => Line::styled(format!("{} ✗ {}", entry.time, entry.text), theme::current().failure),
+        })
+        .collect()
+}
+
+fn render_log(f: &mut Frame, area: Rect, scroll: usize) {
+    let popup = centered_rect(area.width.saturating_sub(4), area.height.saturating_sub(2), area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("📜 Log")
+        .border_style(theme::current().popup);
+    let mut lines = log_lines();
+    if lines.is_empty() {
+        lines.push(Line::styled(" Nothing has been run yet", theme::current().faint));
+    }
+    // Leave room for the hint line below the entries
+    let visible = popup.height.saturating_sub(4) as usize;
+    let end = lines.len().saturating_sub(scroll.min(lines.len().saturating_sub(visible)));
+    let mut shown: Vec<Line> = lines.drain(end.saturating_sub(visible)..end).collect();
+    shown.push(Line::from(""));
+    shown.push(Line::styled(" [↑/↓/PgUp/PgDn] Scroll   [Home/End] Oldest/Newest   [Esc] Close", theme::current().dim));
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(shown).block(block), popup);
+}
+
+fn render_name_input(f: &mut Frame, area: Rect, name: &str) {
+    let popup = centered_rect(40, 5, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("💾 Save profile")
+        .border_style(theme::current().popup);
+    let text = format!("Name: {}_\n\n[Enter] Save   [Esc] Cancel", name);
+    let paragraph = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .style(theme::current().text)
+        .block(block);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn render_prompt(f: &mut Frame, area: Rect, prompt: &Prompt) {
+    let width = (prompt.message.chars().count() as u16 + 6).max(30);
+    let popup = centered_rect(width, 5, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("❓ Confirm")
+        .border_style(theme::current().popup);
+    let text = format!("{}\n\n[y] Yes   [n] No", prompt.message);
+    let paragraph = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .style(theme::current().text)
+        .wrap(Wrap { trim: true })
+        .block(block);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+/// Runs a subcommand to completion without the TUI.
+fn run_command(command: cli::Command) -> Result<()> {
+    let config = config::Config::load()?;
+    match command {
+        cli::Command::Apply(path) => profile::Profile::load(&path)?.apply(&config),
+        cli::Command::Set(variables) => {
+            let mut wanted = profile::Profile::default();
+            for (name, value) in variables {
+                match name.as_str() {
+                    "LANG" => wanted.lang = Some(value),
+                    _ => {
+                        wanted.categories.insert(name, value);
+                    }
+                }
+            }
+            profile::print_outcome(&profile::apply_locale_together(&wanted.changes(), &config))
+        }
+        cli::Command::Import(from) => import::run(&from),
+        cli::Command::ExportShell => {
+            print!("{}", profile::Profile::current().shell_script());
+            Ok(())
+        }
+        cli::Command::ProfileList => {
+            for name in profile::names() {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+        cli::Command::ProfileSave(name) => {
+            let path = profile::Profile::current().save(&name)?;
+            println!("Saved {}", path.display());
+            Ok(())
+        }
+        cli::Command::Snapshot(name) => {
+            let path = profile::Profile::snapshot().save(&name)?;
+            println!("Saved {}; levocale profile use {} restores it", path.display(), name);
+            Ok(())
+        }
+        cli::Command::ProfileUse(name) => profile::Profile::named(&name)?.apply(&config),
+        cli::Command::KeymapDump(_) | cli::Command::KeymapLoad(_) | cli::Command::KeymapClear
+            if exec::remote_host().is_some() =>
+        {
+            bail!("--host only manages the locale; keymaps belong to this session's keyboard")
+        }
+        cli::Command::KeymapDump(path) => {
+            let path = config::expand_home(&path);
+            fs::write(&path, keymap::current(backend::active().as_ref())?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Saved {}; levocale keymap load {} loads it back", path.display(), path.display());
+            Ok(())
+        }
+        cli::Command::KeymapLoad(path) => {
+            let path = config::expand_home(&path);
+            let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            // xkbcomp would take a lone section too, but a compositor wants a whole keymap
+            if !contents.contains("xkb_keymap") {
+                bail!("{} isn't a keymap: there's no xkb_keymap block in it", path.display());
+            }
+            // The backend may resolve it from elsewhere, e.g. Hyprland's own directory
+            let path = path.canonicalize().with_context(|| format!("Failed to resolve {}", path.display()))?;
+            let keyboard = backend::active();
+            keyboard.set_keymap_file(Some(&path.to_string_lossy()))?;
+            println!("Loaded {} on {}", path.display(), keyboard.name());
+            Ok(())
+        }
+        cli::Command::KeymapClear => {
+            let keyboard = backend::active();
+            keyboard.set_keymap_file(None)?;
+            println!("Back to the layout and options on {}", keyboard.name());
+            Ok(())
+        }
+        cli::Command::Sync(locale) => {
+            let layout = sync_layout(&locale)?;
+            profile::print_outcome(&profile::sync_all(&layout, &locale, &config))
+        }
+        // The keyboard it manages is this session's
+        cli::Command::Daemon if exec::remote_host().is_some() => bail!("The daemon can't be used with --host"),
+        cli::Command::Daemon => daemon::run(&config),
+        cli::Command::Listen { json } => listen::run(json),
+        cli::Command::Watch { format } => {
+            listen::watch_changes(format.as_deref().unwrap_or(listen::DEFAULT_WATCH_FORMAT))
+        }
+    }
+}
+
+const AUTO_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may contain spaces
+    stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Focuses the terminal window this runs in, found by walking up from our
+/// process to the one the compositor knows the window by. Only Hyprland and
+/// sway can be asked; elsewhere the running instance just refreshes.
+fn focus_own_window() {
+    let mut pid = parent_pid(std::process::id());
+    while let Some(current) = pid.filter(|pid| *pid > 1) {
+        let focused = match backend::active().name() {
+            "hyprland" => exec::local("hyprctl")
+                .args(["dispatch", "focuswindow", &format!("pid:{}", current)])
+                .output()
+                .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "ok"),
+            "sway" => exec::local("swaymsg")
+                .arg(format!("[pid={}] focus", current))
+                .output()
+                .is_ok_and(|output| output.status.success()),
+            _ => return,
+        };
+        if focused {
+            return;
+        }
+        pid = parent_pid(current);
+    }
+}
+
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
-    running: Arc<AtomicBool>
+    running: Arc<AtomicBool>,
+    inline: bool,
+    read_only: bool,
+    instance: &UnixListener,
 ) -> Result<()> {
     let mut app_state = AppState::new();
+    app_state.read_only = read_only;
+    app_state.inline = inline;
+    exec::forbid_password_prompts();
+    app_state.config_modified = config::modified();
+    match config::Config::load() {
+        Ok(config) => app_state.config = config,
+        Err(e) => notify_failure(&format!("Using default settings: {:#}", e)),
+    }
+    // Config::load has vetted the bindings already
+    app_state.keys = keys::Bindings::new(&app_state.config.keys).unwrap_or_default();
+    notifications::configure(app_state.config.notifications.clone());
+    theme::set(&app_state.config.theme);
     app_state.refresh_status();
-    app_state.build_menu();
+    // Nothing saved yet means levocale hasn't been used here before
+    if !state::state_path().exists() {
+        app_state.suggest_for_region();
+    }
+    app_state.restore_state(state::State::load());
+    let mut last_refresh = Instant::now();
 
     loop {
         // Check if we should quit due to signal
         if !running.load(Ordering::SeqCst) || app_state.should_quit {
             break;
         }
+        app_state.poll_job();
+        app_state.poll_mismatch();
+        app_state.poll_refresh();
+        if ipc::raise_requested(instance) {
+            focus_own_window();
+            app_state.reload();
+            last_refresh = Instant::now();
+            notify("levocale is already open here");
+        }
+        let modified = config::modified();
+        if modified != app_state.config_modified {
+            app_state.config_modified = modified;
+            app_state.reload_config();
+        }
+        // Pick up changes made by other tools; a running job refreshes when done
+        let idle = app_state.job.is_none() && app_state.refresh.is_none();
+        if idle && last_refresh.elapsed() >= AUTO_REFRESH_INTERVAL {
+            app_state.start_refresh();
+            last_refresh = Instant::now();
+        }
+
+        let details = if app_state.show_details {
+            app_state.selected_details()
+        } else {
+            Vec::new()
+        };
+        let shortcuts = app_state.shortcuts();
 
+        if app_state.needs_clear {
+            app_state.needs_clear = false;
+            terminal.clear()?;
+        }
         terminal.draw(|f| {
+            if inline {
+                render_compact(f, &mut app_state);
+                return;
+            }
             let size = f.size();
-            // Main container
+            if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+                render_too_small(f, size);
+                return;
+            }
+            // Short terminals get one-line status and controls, and the
+            // shortest lose the status entirely
+            let compact = size.height < COMPACT_HEIGHT;
+            let tiny = size.height < TINY_HEIGHT;
+            // Main container, saying whether changes can reach the system
+            let mut notes = vec![format!("{} mode", exec::mode().name())];
+            if app_state.read_only {
+                notes.push("read-only".to_string());
+            }
             let main_block = Block::default()
                 .borders(Borders::ALL)
-                .title("🌐 Levocale - Locale & Keyboard Switcher")
+                .title(format!("🌐 Levocale - Locale & Keyboard Switcher ({})", notes.join(", ")))
                 .title_alignment(Alignment::Center)
-                .border_style(Style::default().fg(Color::Cyan));
+                .border_style(theme::current().frame);
             let inner = main_block.inner(size);
             f.render_widget(main_block, size);
 
-            // Split into status, menu area, and instructions
+            // Split into status, shortcuts, menu area, and instructions
+            let shortcuts_height = if shortcuts.is_empty() || tiny { 0 } else { 1 };
+            let environment = if app_state.show_environment && !compact {
+                let per_row = (inner.width.saturating_sub(2) / ENVIRONMENT_CELL_WIDTH).max(1) as usize;
+                environment_lines(&app_state.session_locale, per_row)
+            } else {
+                Vec::new()
+            };
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
-                    Constraint::Length(4),   // Status display
-                    Constraint::Min(0),      // Menu items
-                    Constraint::Length(3),   // Instructions
+                    Constraint::Length(match (tiny, compact) {
+                        (true, _) => 0,
+                        (false, true) => 1,
+                        (false, false) => 4 + environment.len() as u16 + app_state.mismatch.is_some() as u16,
+                    }), // Status display
+                    Constraint::Length(shortcuts_height),   // Quick-select row
+                    Constraint::Min(0),                     // Menu items
+                    Constraint::Length(if compact { 1 } else { 3 }), // Instructions
                 ])
                 .split(inner);
 
@@ -516,147 +3850,444 @@ fn run_app<B: ratatui::backend::Backend>(
             let status_block = Block::default()
                 .borders(Borders::ALL)
                 .title("📊 Current Status")
-                .border_style(Style::default().fg(Color::Green));
+                .border_style(theme::current().status);
 
-            let status_text = format!(
-                "Locale: {} | Keyboard Layout: {}",
-                app_state.current_locale,
-                app_state.current_layout
-            );
-            let status_paragraph = Paragraph::new(status_text)
-                .style(Style::default().fg(Color::White))
-                .alignment(Alignment::Center)
-                .block(status_block);
+            let keyboard_status = match &app_state.current_keymap {
+                Some(path) => format!("Keymap: {}", keymap_name(path)),
+                None => format!("Keyboard Layout: {}", app_state.current_layout),
+            };
+            let keyboard_status = match seat::target() {
+                Some(seat) => format!("{} on {}", keyboard_status, seat),
+                None => keyboard_status,
+            };
+            let status_text = match exec::remote_host() {
+                Some(host) => format!("Host: {} | Locale: {}", host, app_state.current_locale),
+                None => format!(
+                    "Locale: {} | {} | Compose: {} | Backend: {}",
+                    app_state.current_locale,
+                    keyboard_status,
+                    compose_key(&app_state.kb_options).unwrap_or("off"),
+                    app_state.backend
+                ),
+            };
+            // LC_ALL beats every other setting, so flag it even when collapsed
+            let lc_all = app_state
+                .session_locale
+                .iter()
+                .find(|variable| variable.name == "LC_ALL" && variable.explicit);
+            let status_text = match lc_all {
+                Some(variable) if !app_state.show_environment => {
+                    format!("{} | ⚠ LC_ALL={}", status_text, variable.value)
+                }
+                _ => status_text,
+            };
+            let mut status_lines = vec![Line::from(status_text)];
+            if let Some(mismatch) = app_state.mismatch.as_ref().filter(|_| !compact) {
+                status_lines.push(Line::styled(format!("⚠ Out of sync: {}", mismatch.0), theme::current().warning));
+            }
+            status_lines.extend(environment);
+            let status_paragraph = Paragraph::new(status_lines)
+                .style(theme::current().text)
+                .alignment(Alignment::Center);
+            let status_paragraph = if compact { status_paragraph } else { status_paragraph.block(status_block) };
             f.render_widget(status_paragraph, chunks[0]);
 
-            // Calculate visible area for menu
-            let menu_height = chunks[1].height.saturating_sub(2) as usize; // -2 for borders
-            let item_height = 2; // Each item takes 2 lines
-            let visible_items = menu_height / item_height;
-
-            // Update scroll based on actual visible area
-            app_state.adjust_scroll_for_height(visible_items);
+            if shortcuts_height > 0 {
+                let shortcuts_text = shortcuts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, kind)| {
+                        let name = match kind {
+                            ItemKind::Keymap(path) => keymap_name(path),
+                            _ => kind.code().unwrap_or_default(),
+                        };
+                        format!("[{}] {}", i + 1, name)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                let shortcuts_paragraph = Paragraph::new(shortcuts_text)
+                    .style(theme::current().popup)
+                    .alignment(Alignment::Center);
+                f.render_widget(shortcuts_paragraph, chunks[1]);
+            }
 
-            // Get visible menu items
-            let end_index = (app_state.scroll_offset + visible_items).min(app_state.menu_items.len());
-            let visible_menu_items = if app_state.menu_items.is_empty() {
-                &[]
+            // Details pane sits to the right on wide terminals, below otherwise,
+            // unless that would leave short terminals hardly any menu
+            let (menu_area, details_area) = if !app_state.show_details || (compact && chunks[2].width < 100) {
+                (chunks[2], None)
+            } else if chunks[2].width >= 100 {
+                let split = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                    .split(chunks[2]);
+                (split[0], Some(split[1]))
             } else {
-                &app_state.menu_items[app_state.scroll_offset..end_index]
+                let split = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(8)])
+                    .split(chunks[2]);
+                (split[0], Some(split[1]))
             };
 
-            // Menu area
-            let menu_block = Block::default()
-                .borders(Borders::ALL)
-                .title("📋 Options")
-                .border_style(Style::default().fg(Color::Blue));
-            let menu_inner = menu_block.inner(chunks[1]);
-            f.render_widget(menu_block, chunks[1]);
-
-            // Create constraints for visible items
-            if !visible_menu_items.is_empty() {
-                let menu_constraints: Vec<Constraint> = visible_menu_items
-                    .iter()
-                    .map(|_| Constraint::Length(item_height as u16))
-                    .collect();
+            if let Some(details_area) = details_area {
+                let details_block = Block::default()
+                    .borders(Borders::ALL)
+                    .title("🔎 Details")
+                    .border_style(theme::current().details);
+                let details_paragraph = Paragraph::new(details.join("\n"))
+                    .style(theme::current().text)
+                    .wrap(Wrap { trim: false })
+                    .block(details_block);
+                f.render_widget(details_paragraph, details_area);
+            }
 
-                let menu_chunks = Layout::default()
+            // Tab view shows one pane at a time under a tab bar
+            if app_state.view_mode == ViewMode::Tabs {
+                let tab_chunks = Layout::default()
                     .direction(Direction::Vertical)
-                    .constraints(menu_constraints)
-                    .split(menu_inner);
-
-                // Render visible menu items
-                for (i, item) in visible_menu_items.iter().enumerate() {
-                    let global_index = app_state.scroll_offset + i;
-                    let is_header = item.label.contains("▼") || item.label.contains("▶");
-
-                    let (style, prefix) = if global_index == app_state.selected {
-                        if is_header {
-                            (Style::default()
-                                .fg(Color::Black)
-                                .bg(Color::Cyan)
-                                .add_modifier(Modifier::BOLD), "► ")
-                        } else {
-                            (Style::default()
-                                .fg(Color::Black)
-                                .bg(Color::Yellow)
-                                .add_modifier(Modifier::BOLD), "► ")
-                        }
-                    } else if is_header {
-                        (Style::default()
-                            .fg(Color::Cyan)
-                            .add_modifier(Modifier::BOLD), "  ")
-                    } else {
-                        (Style::default().fg(Color::White), "  ")
-                    };
-
-                    let content = format!("{}{}\n{}", prefix, item.label, item.description);
-                    let paragraph = Paragraph::new(content)
-                        .style(style);
-
-                    if i < menu_chunks.len() {
-                        f.render_widget(paragraph, menu_chunks[i]);
-                    }
+                    .constraints([Constraint::Length(2), Constraint::Min(0)])
+                    .split(menu_area);
+                let titles = app_state.sections().into_iter().map(|section| app_state.tab_title(section)).collect();
+                let tabs = Tabs::new(titles)
+                    .select(app_state.focus)
+                    .style(theme::current().dim)
+                    .highlight_style(theme::current().header_selection)
+                    .block(Block::default().borders(Borders::BOTTOM));
+                f.render_widget(tabs, tab_chunks[0]);
+                let title = app_state.pane_title(app_state.focus);
+                render_menu_list(f, tab_chunks[1], app_state.pane_mut(), &title, true);
+            } else {
+                // Menu panes, side by side in split view
+                let pane_areas = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(
+                        app_state
+                            .panes
+                            .iter()
+                            .map(|_| Constraint::Ratio(1, app_state.panes.len() as u32))
+                            .collect::<Vec<_>>(),
+                    )
+                    .split(menu_area);
+                let titles: Vec<String> = (0..app_state.panes.len())
+                    .map(|i| app_state.pane_title(i))
+                    .collect();
+                let focus = app_state.focus;
+                for (i, pane) in app_state.panes.iter_mut().enumerate() {
+                    render_menu_list(f, pane_areas[i], pane, &titles[i], i == focus);
                 }
             }
 
             // Scroll indicators and instructions
-            let mut instructions_text = "Controls: ↑/↓ Navigate • Enter Select/Toggle • q/Esc/Ctrl+C Quit".to_string();
-            if app_state.scroll_offset > 0 {
+            let label = |action| app_state.keys.label(action);
+            let mut instructions_text = if exec::remote_host().is_some() {
+                format!(
+                    "Controls: {}/{} Navigate • {} Select/Toggle • {} Fold • +/- Expand/Collapse all • c Current • 1-9 Shortcut • P Save profile • y Copy code • x Image • {} Search • i Details • e Environment • s Sort • g Installed/All • n New locale • T Theme • L Log • {} Retry • {}/Ctrl+C Quit",
+                    label(keys::Action::Up),
+                    label(keys::Action::Down),
+                    label(keys::Action::Select),
+                    label(keys::Action::Toggle),
+                    label(keys::Action::Search),
+                    label(keys::Action::Retry),
+                    label(keys::Action::Quit),
+                )
+            } else {
+                format!(
+                    "Controls: {}/{} Navigate • {} Select/Toggle • {} Fold • a Locale+Layout • A Sync all • +/- Expand/Collapse all • c Current • C Compose • 1-9 Shortcut • P Save profile • y Copy code • x Image • t Typing test • D Devices • S Seat • {} Search • Tab/Shift+Tab Pane • v View • i Details • e Environment • s Sort • g Installed/All • n New locale • T Theme • L Log • {} Retry • {}/Ctrl+C Quit",
+                    label(keys::Action::Up),
+                    label(keys::Action::Down),
+                    label(keys::Action::Select),
+                    label(keys::Action::Toggle),
+                    label(keys::Action::Search),
+                    label(keys::Action::Retry),
+                    label(keys::Action::Quit),
+                )
+            };
+            if compact {
+                instructions_text = short_controls(&app_state.keys);
+            }
+            if let Some((text, _)) = &app_state.search_input {
+                instructions_text = format!("Search: {}_   [Enter] Done   [Esc] Back", text);
+            }
+            let pane = app_state.pane();
+            if pane.scroll_offset > 0 {
                 instructions_text += " • ⬆ More above";
             }
-            if end_index < app_state.menu_items.len() {
+            if pane.scroll_offset + pane.visible_items < pane.items.len() {
                 instructions_text += " • ⬇ More below";
             }
 
             let instructions = Paragraph::new(instructions_text)
-                .style(Style::default().fg(Color::Gray))
+                .style(theme::current().dim)
                 .alignment(Alignment::Center)
-                .block(Block::default().borders(Borders::TOP));
-            f.render_widget(instructions, chunks[2]);
+                .wrap(Wrap { trim: true })
+                .block(Block::default().borders(if compact { Borders::NONE } else { Borders::TOP }));
+            f.render_widget(instructions, chunks[3]);
+
+            render_toasts(f, size);
+
+            if let Some(job) = &app_state.job {
+                render_job(f, size, job);
+            } else if let Some(prompt) = app_state.prompts.front() {
+                render_prompt(f, size, prompt);
+            } else if let Some(name) = &app_state.profile_name_input {
+                render_name_input(f, size, name);
+            } else if let Some(test) = &app_state.typing_test {
+                render_typing_test(f, size, test, &app_state.current_layout);
+            } else if let Some(screen) = &app_state.devices_screen {
+                render_devices(f, size, screen, &app_state.config);
+            } else if let Some(form) = &app_state.locale_form {
+                render_locale_form(f, size, form);
+            } else if let Some(preview) = &app_state.profile_preview {
+                render_profile_preview(f, size, preview);
+            } else if let Some(scroll) = app_state.log_scroll {
+                render_log(f, size, scroll);
+            }
         })?;
 
         // Handle events with timeout to check for signals
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+        {
+            // Nothing else may start until the running action is done
+            if app_state.job.is_some() {
+                continue;
+            }
+            if let Some(name) = &mut app_state.profile_name_input {
                 match key.code {
-                    KeyCode::Up => {
-                        app_state.move_up();
-                    }
-                    KeyCode::Down => {
-                        app_state.move_down();
+                    KeyCode::Enter => {
+                        let name = name.trim().to_string();
+                        app_state.profile_name_input = None;
+                        if !name.is_empty() {
+                            let _ = app_state.save_profile(&name);
+                        }
                     }
-                    KeyCode::Enter | KeyCode::Right => {
-                        let _ = app_state.execute_selected();
+                    KeyCode::Esc => app_state.profile_name_input = None,
+                    KeyCode::Backspace => {
+                        name.pop();
                     }
-                    KeyCode::Left => {
-                        // Collapse current section if it's expanded
-                        if !app_state.menu_items.is_empty() {
-                            let item = &app_state.menu_items[app_state.selected];
-                            if (item.label.contains("▼ Keyboard") && app_state.keyboard_section_expanded) ||
-                               (item.label.contains("▼ System") && app_state.locale_section_expanded) {
-                                app_state.toggle_section();
-                            }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => name.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some((text, origin)) = &mut app_state.search_input {
+                match key.code {
+                    KeyCode::Enter => app_state.search_input = None,
+                    KeyCode::Esc => {
+                        let origin = origin.take();
+                        app_state.search_input = None;
+                        if let Some(kind) = origin {
+                            app_state.select_item(&kind);
                         }
                     }
-                    KeyCode::Char('q') | KeyCode::Esc => {
-                        app_state.should_quit = true;
+                    KeyCode::Backspace => {
+                        text.pop();
+                        let text = text.clone();
+                        app_state.find(&text);
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        text.push(c);
+                        let text = text.clone();
+                        app_state.find(&text);
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(test) = &mut app_state.typing_test {
+                match key.code {
+                    KeyCode::Enter => app_state.finish_typing_test(true),
+                    KeyCode::Esc => app_state.finish_typing_test(false),
+                    KeyCode::Backspace => {
+                        test.text.pop();
+                    }
+                    // AltGr arrives as plain characters; only Ctrl chords aren't text
+                    KeyCode::Char(c) if key.modifiers != KeyModifiers::CONTROL => test.text.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(screen) = &mut app_state.devices_screen {
+                let device = screen.devices[screen.selected].clone();
+                if let Some((field, text)) = &mut screen.editing {
+                    match key.code {
+                        KeyCode::Enter => {
+                            let (field, text) = (*field, std::mem::take(text));
+                            screen.editing = None;
+                            app_state.set_device_field(&device, field, &text);
+                        }
+                        KeyCode::Esc => screen.editing = None,
+                        KeyCode::Backspace => {
+                            text.pop();
+                        }
+                        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => text.push(c),
+                        _ => {}
                     }
-                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        app_state.should_quit = true;
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Up => screen.selected = screen.selected.checked_sub(1).unwrap_or(screen.devices.len() - 1),
+                    KeyCode::Down => screen.selected = (screen.selected + 1) % screen.devices.len(),
+                    KeyCode::Enter => app_state.edit_device_field(DeviceField::Layout),
+                    KeyCode::Char('o') => app_state.edit_device_field(DeviceField::Options),
+                    KeyCode::Delete => app_state.remove_device_rule(&device),
+                    KeyCode::Esc | KeyCode::Char('q') => app_state.devices_screen = None,
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(form) = &mut app_state.locale_form {
+                match key.code {
+                    KeyCode::Enter => app_state.compile_custom_locale(),
+                    KeyCode::Esc => app_state.locale_form = None,
+                    KeyCode::Tab => form.complete(),
+                    KeyCode::Up | KeyCode::BackTab => form.prev_field(),
+                    KeyCode::Down => form.next_field(),
+                    KeyCode::Backspace => {
+                        form.text_mut().pop();
                     }
-                    KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        app_state.should_quit = true;
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => form.text_mut().push(c),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(preview) = &mut app_state.profile_preview {
+                let count = preview.changes.len();
+                match key.code {
+                    KeyCode::Up => preview.selected = preview.selected.checked_sub(1).unwrap_or(count - 1),
+                    KeyCode::Down => preview.selected = (preview.selected + 1) % count,
+                    KeyCode::Char(' ') => preview.chosen[preview.selected] ^= true,
+                    KeyCode::Enter => app_state.apply_profile_preview(),
+                    KeyCode::Esc | KeyCode::Char('q') => app_state.profile_preview = None,
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(scroll) = &mut app_state.log_scroll {
+                // Clamped when drawn, so scrolling past the top just stops there
+                let oldest = log::entries().len();
+                match key.code {
+                    KeyCode::Up => *scroll = (*scroll + 1).min(oldest),
+                    KeyCode::Down => *scroll = scroll.saturating_sub(1),
+                    KeyCode::PageUp => *scroll = (*scroll + 10).min(oldest),
+                    KeyCode::PageDown => *scroll = scroll.saturating_sub(10),
+                    KeyCode::Home => *scroll = oldest,
+                    KeyCode::End => *scroll = 0,
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('L') => app_state.log_scroll = None,
+                    _ => {}
+                }
+                continue;
+            }
+            // An open prompt swallows every key until it is answered
+            if !app_state.prompts.is_empty() {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => {
+                        let _ = app_state.answer_prompt(true);
                     }
-                    KeyCode::Char('r') => {
-                        // Refresh status
-                        app_state.refresh_status();
-                        app_state.build_menu();
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        let _ = app_state.answer_prompt(false);
                     }
                     _ => {}
                 }
+                continue;
+            }
+            if let Some(action) = app_state.keys.action(&key) {
+                match action {
+                    keys::Action::Up => app_state.pane_mut().move_up(),
+                    keys::Action::Down => app_state.pane_mut().move_down(),
+                    keys::Action::Select => {
+                        let _ = app_state.execute_selected();
+                    }
+                    keys::Action::Toggle => app_state.toggle_selected_section(),
+                    keys::Action::Search => {
+                        let origin = app_state.pane().selected_item().map(|item| item.kind.clone());
+                        // Folded entries can't be highlighted
+                        app_state.set_all_sections_expanded(true);
+                        app_state.search_input = Some((String::new(), origin));
+                    }
+                    keys::Action::Refresh => {
+                        app_state.reload();
+                        last_refresh = Instant::now();
+                    }
+                    keys::Action::Retry => app_state.retry_failed(),
+                    keys::Action::Quit => app_state.should_quit = true,
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Tab => {
+                    app_state.focus_next_pane();
+                }
+                KeyCode::BackTab => {
+                    app_state.focus_prev_pane();
+                }
+                KeyCode::Char('v') => {
+                    app_state.toggle_view_mode();
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app_state.should_quit = true;
+                }
+                KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app_state.should_quit = true;
+                }
+                KeyCode::Char('c') => {
+                    app_state.jump_to_current();
+                }
+                // Both change the local keyboard, which isn't managed remotely
+                KeyCode::Char('C') if exec::remote_host().is_none() => {
+                    let _ = app_state.toggle_compose();
+                }
+                KeyCode::Char('a') if exec::remote_host().is_none() => {
+                    app_state.apply_locale_with_layout();
+                }
+                KeyCode::Char('A') if exec::remote_host().is_none() => app_state.sync_selected(),
+                KeyCode::Char('t') if exec::remote_host().is_none() => {
+                    app_state.start_typing_test();
+                }
+                KeyCode::Char('T') => {
+                    notify(&format!("Theme: {}", theme::cycle().name));
+                }
+                KeyCode::Char('s') => app_state.cycle_sort(),
+                KeyCode::Char('g') => app_state.toggle_all_locales(),
+                KeyCode::Char('n') => app_state.open_locale_form(),
+                KeyCode::Char('S') if exec::remote_host().is_none() => {
+                    app_state.next_seat();
+                }
+                KeyCode::Char('D') if exec::remote_host().is_none() => {
+                    app_state.open_devices_screen();
+                }
+                KeyCode::Char('L') => app_state.log_scroll = Some(0),
+                KeyCode::Char(digit @ '1'..='9') => {
+                    app_state.apply_shortcut(digit as usize - '1' as usize);
+                }
+                KeyCode::Delete => {
+                    app_state.remove_selected_engine();
+                }
+                KeyCode::Char('+') => {
+                    app_state.set_all_sections_expanded(true);
+                }
+                KeyCode::Char('-') => {
+                    app_state.set_all_sections_expanded(false);
+                }
+                KeyCode::Char('i') => {
+                    app_state.show_details = !app_state.show_details;
+                }
+                KeyCode::Char('e') => {
+                    app_state.show_environment = !app_state.show_environment;
+                }
+                KeyCode::Char('P') if !app_state.refuse_change() => {
+                    app_state.profile_name_input = Some(String::new());
+                }
+                KeyCode::Char('y') => {
+                    app_state.copy_selected_code();
+                }
+                KeyCode::Char('x') => app_state.export_layout_image(),
+                _ => {}
             }
         }
     }
+    if let Err(e) = app_state.state().save() {
+        notify_failure(&format!("{:#}", e));
+    }
     Ok(())
 }
@@ -0,0 +1,52 @@
+//! Sections of user-owned config files that levocale rewrites wholesale,
+//! fenced by marker comments so everything around them is left alone.
+
+const BEGIN: &str = "BEGIN levocale managed block";
+const END: &str = "END levocale managed block";
+
+/// Replaces the managed block in `contents` with `body`, or appends a new
+/// block when there isn't one yet. `comment` is the file's line comment
+/// prefix, e.g. "#". Writing the same body twice yields the same file.
+pub fn upsert(contents: &str, comment: &str, body: &str) -> String {
+    let begin = format!("{} {}", comment, BEGIN);
+    let end = format!("{} {}", comment, END);
+    let mut block = format!("{} (changes inside are overwritten)\n", begin);
+    block.push_str(body.trim_end());
+    block.push('\n');
+    block.push_str(&end);
+    block.push('\n');
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let start = lines.iter().position(|line| line.trim_start().starts_with(&begin));
+    let stop = start.and_then(|start| {
+        lines[start..]
+            .iter()
+            .position(|line| line.trim_start().starts_with(&end))
+            .map(|offset| start + offset)
+    });
+    let mut out = String::new();
+    match (start, stop) {
+        (Some(start), Some(stop)) => {
+            for line in &lines[..start] {
+                out.push_str(line);
+                out.push('\n');
+            }
+            out.push_str(&block);
+            for line in &lines[stop + 1..] {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        _ => {
+            out.push_str(contents);
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&block);
+        }
+    }
+    out
+}
@@ -0,0 +1,107 @@
+//! The wording of the announcements made after a change: English, one of
+//! the [`crate::translations`] picked by the user's message locale, or a
+//! template from `[notifications.messages]`. Templates fill in {code},
+//! {name}, {backend} and {detail}.
+
+use crate::{notifications, translations};
+
+#[derive(Clone, Copy)]
+pub enum Message {
+    LayoutSet,
+    KeymapLoaded,
+    KeymapCleared,
+    LocaleSet,
+    LocaleAndLayout,
+    InputMethodSet,
+    EngineEnabled,
+    EngineRemoved,
+    OptionEnabled,
+    OptionDisabled,
+    ComposeEnabled,
+    ComposeDisabled,
+    RestartNeeded,
+    ProfileApplied,
+    ProfileInEffect,
+    ProfileSaved,
+    Reverted,
+    Synced,
+    SeatTargeted,
+    SeatReset,
+    Copied,
+    ImageSaved,
+    Installed,
+    Layered,
+    Sorted,
+}
+
+/// What a template can mention; whatever a message has no use for is left
+/// empty.
+#[derive(Default)]
+pub struct Values<'a> {
+    /// e.g. "de(nodeadkeys)", "de_DE.UTF-8" or a profile's name.
+    pub code: &'a str,
+    /// e.g. "German (no dead keys)" or "German (Germany)".
+    pub name: &'a str,
+    /// The backend that made the change, e.g. "hyprland" or "localed".
+    pub backend: &'a str,
+    /// Anything else the message reports, e.g. the file something was
+    /// saved to or the steps of a sync.
+    pub detail: &'a str,
+}
+
+impl Message {
+    /// The key in `[notifications.messages]` and the built-in tables.
+    fn id(self) -> &'static str {
+        match self {
+            Message::LayoutSet => "layout_set",
+            Message::KeymapLoaded => "keymap_loaded",
+            Message::KeymapCleared => "keymap_cleared",
+            Message::LocaleSet => "locale_set",
+            Message::LocaleAndLayout => "locale_and_layout",
+            Message::InputMethodSet => "input_method_set",
+            Message::EngineEnabled => "engine_enabled",
+            Message::EngineRemoved => "engine_removed",
+            Message::OptionEnabled => "option_enabled",
+            Message::OptionDisabled => "option_disabled",
+            Message::ComposeEnabled => "compose_enabled",
+            Message::ComposeDisabled => "compose_disabled",
+            Message::RestartNeeded => "restart_needed",
+            Message::ProfileApplied => "profile_applied",
+            Message::ProfileInEffect => "profile_in_effect",
+            Message::ProfileSaved => "profile_saved",
+            Message::Reverted => "reverted",
+            Message::Synced => "synced",
+            Message::SeatTargeted => "seat_targeted",
+            Message::SeatReset => "seat_reset",
+            Message::Copied => "copied",
+            Message::ImageSaved => "image_saved",
+            Message::Installed => "installed",
+            Message::Layered => "layered",
+            Message::Sorted => "sorted",
+        }
+    }
+}
+
+/// The language messages are shown in, from the variables that choose it
+/// for other programs, e.g. "de" for LANG=de_DE.UTF-8.
+fn language() -> String {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    locale.split(['_', '.', '@']).next().unwrap_or_default().to_string()
+}
+
+pub fn text(message: Message, values: &Values) -> String {
+    let settings = notifications::settings();
+    let template = match settings.messages.get(message.id()) {
+        Some(template) => template.to_string(),
+        None => translations::lookup(&language(), message.id()).unwrap_or_default().to_string(),
+    };
+    template
+        .replace("{code}", values.code)
+        .replace("{name}", values.name)
+        .replace("{backend}", values.backend)
+        .replace("{detail}", values.detail)
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{config, fsutil, xkb};
+
+pub fn snippet_path() -> PathBuf {
+    config::config_dir().join("nixos-snippet.nix")
+}
+
+/// A configuration.nix fragment that sets the same locale and keyboard
+/// declaratively.
+pub fn snippet(locale: &str, layout: &str, options: &str) -> String {
+    let (layout, variant) = xkb::split_layout_variant(layout);
+    format!(
+        "# Generated by levocale; merge into configuration.nix and run nixos-rebuild switch.
+{{
+  i18n.defaultLocale = \"{}\";
+  services.xserver.xkb = {{
+    layout = \"{}\";
+    variant = \"{}\";
+    options = \"{}\";
+  }};
+  console.useXkbConfig = true;
+}}
+",
+        locale,
+        layout,
+        variant.unwrap_or(""),
+        options
+    )
+}
+
+pub fn write_snippet(locale: &str, layout: &str, options: &str) -> Result<PathBuf> {
+    let path = snippet_path();
+    fsutil::write_atomic(&path, &snippet(locale, layout, options))?;
+    Ok(path)
+}
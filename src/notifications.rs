@@ -0,0 +1,219 @@
+//! Desktop notifications through the org.freedesktop.Notifications D-Bus
+//! interface, called with our own D-Bus client on Linux and busctl
+//! elsewhere, and the toasts the TUI shows for the same messages.
+
+#[cfg(not(target_os = "linux"))]
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[cfg(target_os = "linux")]
+use crate::dbus::{self, Message, Value, dict, string};
+#[cfg(not(target_os = "linux"))]
+use crate::exec;
+use crate::{config, log};
+
+/// The id of our last notification, so the next one replaces it instead of
+/// stacking up while the user flicks through layouts.
+static LAST_ID: Mutex<u32> = Mutex::new(0);
+
+/// Notifications still being delivered, for [`wait`].
+static PENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// The `[notifications]` settings, once the config has been read.
+static SETTINGS: Mutex<Option<config::Notifications>> = Mutex::new(None);
+
+const APP_NAME: &str = "Levocale";
+
+/// How long a toast stays on screen.
+pub const TOAST_LIFETIME: Duration = Duration::from_secs(4);
+
+/// A message shown briefly inside the TUI.
+struct Toast {
+    message: String,
+    failure: bool,
+    shown_at: Instant,
+}
+
+static TOASTS: Mutex<Vec<Toast>> = Mutex::new(Vec::new());
+
+/// Whether messages also go to the terminal, for `--plain`.
+static ECHO: AtomicBool = AtomicBool::new(false);
+
+/// Prints every message as well, successes to stdout and errors to stderr.
+pub fn set_echo(echo: bool) {
+    ECHO.store(echo, Ordering::Relaxed);
+}
+
+/// Toasts still within their lifetime as (message, failure, age), oldest
+/// first.
+pub fn toasts() -> Vec<(String, bool, Duration)> {
+    let Ok(mut toasts) = TOASTS.lock() else {
+        return Vec::new();
+    };
+    toasts.retain(|toast| toast.shown_at.elapsed() < TOAST_LIFETIME);
+    toasts.iter().map(|toast| (toast.message.clone(), toast.failure, toast.shown_at.elapsed())).collect()
+}
+
+fn push_toast(message: &str, failure: bool) {
+    if let Ok(mut toasts) = TOASTS.lock() {
+        toasts.push(Toast { message: message.to_string(), failure, shown_at: Instant::now() });
+        // Only the last few fit in the corner
+        let excess = toasts.len().saturating_sub(3);
+        toasts.drain(..excess);
+    }
+}
+
+/// org.freedesktop.Notifications urgency levels.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+pub fn configure(settings: config::Notifications) {
+    if let Ok(mut current) = SETTINGS.lock() {
+        *current = Some(settings);
+    }
+}
+
+pub fn settings() -> config::Notifications {
+    SETTINGS.lock().ok().and_then(|settings| settings.clone()).unwrap_or_default()
+}
+
+/// Announces something that worked.
+#[track_caller]
+pub fn success(body: &str) {
+    log::record(log::Kind::Message, body);
+    if ECHO.load(Ordering::Relaxed) {
+        println!("{}", body);
+    }
+    let settings = settings();
+    if settings.toasts {
+        push_toast(body, false);
+    }
+    if settings.successes {
+        send(body, settings.urgency, &settings);
+    }
+}
+
+/// Announces an error.
+#[track_caller]
+pub fn failure(body: &str) {
+    log::record(log::Kind::Error, body);
+    if ECHO.load(Ordering::Relaxed) {
+        eprintln!("Error: {}", body);
+    }
+    let settings = settings();
+    if settings.toasts {
+        push_toast(body, true);
+    }
+    if settings.failures {
+        send(body, settings.failure_urgency, &settings);
+    }
+}
+
+/// Shows `body` without blocking the caller. Does nothing when there is no
+/// session bus or notification daemon.
+fn send(body: &str, urgency: Urgency, settings: &config::Notifications) {
+    if !settings.enabled {
+        return;
+    }
+    let body = body.to_string();
+    let icon = settings.icon.clone();
+    let timeout_ms = settings.timeout_ms;
+    let sending = std::thread::spawn(move || {
+        // Held across the call so replacements happen in order
+        let Ok(mut last_id) = LAST_ID.lock() else {
+            return;
+        };
+        if let Some(id) = call_notify(*last_id, &body, &icon, urgency, timeout_ms) {
+            *last_id = id;
+        }
+    });
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.retain(|sending| !sending.is_finished());
+        pending.push(sending);
+    }
+}
+
+/// Waits for the notifications still being delivered, e.g. before a
+/// command exits.
+pub fn wait() {
+    let pending = match PENDING.lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending),
+        Err(_) => return,
+    };
+    for sending in pending {
+        let _ = sending.join();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn call_notify(replaces_id: u32, body: &str, icon: &str, urgency: Urgency, timeout_ms: i32) -> Option<u32> {
+    let (bus, mut incoming) = dbus::Bus::session().ok()?;
+    let call = Message::method_call(
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+        "Notify",
+        vec![
+            string(APP_NAME),
+            Value::U32(replaces_id),
+            string(icon),
+            string(APP_NAME),
+            string(body),
+            // no actions, one hint
+            Value::Array("s".to_string(), Vec::new()),
+            dict(vec![("urgency", Value::Byte(urgency as u8))]),
+            Value::I32(timeout_ms),
+        ],
+    );
+    let reply = bus.call(&mut incoming, call, Duration::from_secs(2)).ok()?;
+    reply.body.first()?.as_u32()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn call_notify(replaces_id: u32, body: &str, icon: &str, urgency: Urgency, timeout_ms: i32) -> Option<u32> {
+    let replaces_id = replaces_id.to_string();
+    let urgency = (urgency as u8).to_string();
+    let timeout = timeout_ms.to_string();
+    let output = exec::local("busctl")
+        .args([
+            "--user",
+            "--timeout=2",
+            "call",
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Notify",
+            "susssasa{sv}i",
+            APP_NAME,
+            &replaces_id,
+            icon,
+            APP_NAME,
+            body,
+            // no actions, one hint
+            "0",
+            "1",
+            "urgency",
+            "y",
+            &urgency,
+            &timeout,
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // The reply reads: u 42
+    String::from_utf8_lossy(&output.stdout).split_whitespace().nth(1)?.parse().ok()
+}
@@ -0,0 +1,134 @@
+//! `--plain`: the same menus as numbered lists in the normal terminal
+//! buffer, answered one line at a time on stdin. Screen readers follow this
+//! far better than a redrawn full-screen interface.
+
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::{AppState, MenuItem, Section, config, exec, notifications};
+
+/// Prints `question` and reads the answer, or None at the end of input.
+fn ask(question: &str) -> Result<Option<String>> {
+    print!("{} ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        println!();
+        return Ok(None);
+    }
+    Ok(Some(answer.trim().to_string()))
+}
+
+/// An entry's label in words: the markers the TUI draws read badly aloud.
+fn spoken(item: &MenuItem) -> String {
+    let label = item.label.trim();
+    let (label, state) = if let Some(label) = label.strip_prefix("● ") {
+        (label, ", current")
+    } else if let Some(label) = label.strip_prefix("[x] ") {
+        (label, ", on")
+    } else if let Some(label) = label.strip_prefix("[ ] ") {
+        (label, ", off")
+    } else {
+        (label, "")
+    };
+    format!("{} ({}){}", label.trim(), item.description, state)
+}
+
+/// Waits out anything running in the background, then asks each pending
+/// question in turn.
+fn settle(app: &mut AppState) -> Result<()> {
+    loop {
+        if let Some(job) = &app.job {
+            println!("{}…", job.label);
+            while app.job.is_some() {
+                thread::sleep(Duration::from_millis(100));
+                app.poll_job();
+            }
+            continue;
+        }
+        let Some(prompt) = app.prompts.front() else {
+            return Ok(());
+        };
+        let question = format!("{} (y/n):", prompt.message);
+        let answer = ask(&question)?;
+        let confirmed = answer.as_deref().is_some_and(|answer| answer.eq_ignore_ascii_case("y"));
+        app.answer_prompt(confirmed)?;
+    }
+}
+
+/// Lists a section's entries and applies the ones picked, until the user
+/// goes back.
+fn section_menu(app: &mut AppState, section: Section) -> Result<bool> {
+    loop {
+        let mut entries = app.section_entries(section);
+        entries.retain(|item| app.shown(&item.kind));
+        entries.iter_mut().for_each(MenuItem::name);
+        println!();
+        println!("{}, current: {}", app.section_title(section), app.section_current(section));
+        if entries.is_empty() {
+            println!("Nothing to choose from here.");
+            return Ok(true);
+        }
+        for (index, item) in entries.iter().enumerate() {
+            println!("{}. {}", index + 1, spoken(item));
+        }
+        let Some(answer) = ask("Entry number, b to go back, q to quit:")? else {
+            return Ok(false);
+        };
+        match answer.as_str() {
+            "b" | "" => return Ok(true),
+            "q" => return Ok(false),
+            _ => match answer.parse::<usize>().ok().and_then(|number| entries.get(number.checked_sub(1)?)) {
+                Some(item) => {
+                    app.apply_entry(item.kind.clone());
+                    settle(app)?;
+                }
+                None => println!("There is no entry {}.", answer),
+            },
+        }
+    }
+}
+
+pub fn run(read_only: bool) -> Result<()> {
+    let mut app = AppState::new();
+    app.read_only = read_only;
+    notifications::set_echo(true);
+    match config::Config::load() {
+        Ok(config) => app.config = config,
+        Err(e) => eprintln!("Using default settings: {:#}", e),
+    }
+    // The desktop would repeat what's printed anyway
+    notifications::configure(config::Notifications { enabled: false, ..app.config.notifications.clone() });
+    app.refresh_status();
+    loop {
+        let sections = app.sections();
+        println!();
+        println!(
+            "Locale: {}. Keyboard layout: {}. Mode: {}.",
+            app.current_locale,
+            app.current_layout,
+            exec::mode().name()
+        );
+        for (index, section) in sections.iter().enumerate() {
+            println!("{}. {}, current: {}", index + 1, app.section_title(*section), app.section_current(*section));
+        }
+        let Some(answer) = ask("Section number, r to refresh, q to quit:")? else {
+            return Ok(());
+        };
+        match answer.as_str() {
+            "q" => return Ok(()),
+            "r" | "" => app.refresh_status(),
+            _ => match answer.parse::<usize>().ok().and_then(|number| sections.get(number.checked_sub(1)?)) {
+                Some(section) => {
+                    if !section_menu(&mut app, *section)? {
+                        return Ok(());
+                    }
+                }
+                None => println!("There is no section {}.", answer),
+            },
+        }
+    }
+}
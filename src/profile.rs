@@ -0,0 +1,542 @@
+//! Declarative keyboard and locale settings read from a TOML file, applied
+//! by changing only what differs from the current state. Named profiles are
+//! the same files kept in the config directory.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config};
+use crate::{accounts, backend, exec, fsutil, greeter, hooks, locale_info, nixos, system, xkb};
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// Comma-separated XKB options, e.g. "ctrl:nocaps,compose:ralt".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+    #[serde(rename = "LANG", skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// LC_* categories to unset, e.g. those a snapshot found unset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unset: Vec<String>,
+    /// LC_* overrides keyed by category name.
+    #[serde(flatten)]
+    pub categories: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Setting {
+    Layout,
+    Options,
+    Lang,
+    Category(String),
+}
+
+impl Setting {
+    pub fn name(&self) -> &str {
+        match self {
+            Setting::Layout => "layout",
+            Setting::Options => "options",
+            Setting::Lang => "LANG",
+            Setting::Category(category) => category,
+        }
+    }
+
+    fn is_keyboard(&self) -> bool {
+        matches!(self, Setting::Layout | Setting::Options)
+    }
+}
+
+/// A setting whose current value differs from the profile's.
+#[derive(Clone)]
+pub struct Change {
+    pub setting: Setting,
+    pub from: Option<String>,
+    pub to: String,
+}
+
+impl Change {
+    /// e.g. "layout: us → de(nodeadkeys)"
+    pub fn describe(&self) -> String {
+        let show = |value: &str| if value.is_empty() { "(unset)".to_string() } else { value.to_string() };
+        format!("{}: {} → {}", self.setting.name(), show(self.from.as_deref().unwrap_or_default()), show(&self.to))
+    }
+}
+
+/// What applying a profile did, step by step.
+pub struct Outcome {
+    pub steps: Vec<(String, Result<()>)>,
+    /// Steps undone, newest first, after a later one failed.
+    pub reverted: Vec<(String, Result<()>)>,
+}
+
+impl Outcome {
+    pub fn failed(&self) -> usize {
+        self.steps.iter().filter(|(_, result)| result.is_err()).count()
+    }
+}
+
+pub fn profiles_dir() -> PathBuf {
+    config::config_dir().join("profiles")
+}
+
+fn profile_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid profile name {:?}", name);
+    }
+    Ok(profiles_dir().join(format!("{}.toml", name)))
+}
+
+/// Names of the saved profiles, sorted.
+pub fn names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(profiles_dir()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Profile> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let profile: Profile =
+            toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+        // flatten rules out deny_unknown_fields, so check the leftovers here
+        if let Some(name) = profile.categories.keys().find(|name| !locale_info::LC_CATEGORIES.contains(&name.as_str())) {
+            bail!("{}: unknown setting {}", path.display(), name);
+        }
+        if let Some(name) = profile.unset.iter().find(|name| !locale_info::LC_CATEGORIES.contains(&name.as_str())) {
+            bail!("{}: {} in unset isn't an LC_* category", path.display(), name);
+        }
+        Ok(profile)
+    }
+
+    pub fn named(name: &str) -> Result<Profile> {
+        let path = profile_path(name)?;
+        if !path.exists() {
+            bail!("No profile named {} (looked for {})", name, path.display());
+        }
+        Profile::load(&path)
+    }
+
+    /// A snapshot of the settings in effect now.
+    pub fn current() -> Profile {
+        let mut profile = Profile::default();
+        if exec::remote_host().is_none() {
+            let keyboard = backend::active();
+            if let Some(code) = keyboard.current_layout().filter(|code| !code.is_empty()) {
+                let (layout, variant) = xkb::split_layout_variant(&code);
+                profile.layout = Some(layout.to_string());
+                profile.variant = variant.map(str::to_string);
+            }
+            profile.options = keyboard.options();
+        }
+        let locale = backend::active_locale();
+        profile.lang = locale.current_locale();
+        profile.categories = locale.categories().into_iter().collect();
+        profile
+    }
+
+    /// Like [`Profile::current`], with the LC_* categories that aren't set
+    /// listed to unset. Applied later, it takes back LC_* values set in the
+    /// meantime as well, leaving those categories to follow LANG again.
+    pub fn snapshot() -> Profile {
+        let mut profile = Profile::current();
+        profile.unset = locale_info::LC_CATEGORIES
+            .iter()
+            .filter(|category| !profile.categories.contains_key(**category))
+            .map(|category| category.to_string())
+            .collect();
+        profile
+    }
+
+    pub fn save(&self, name: &str) -> Result<PathBuf> {
+        let path = profile_path(name)?;
+        let contents = toml::to_string(self).context("Failed to serialize the profile")?;
+        fsutil::write_atomic(&path, &contents)?;
+        Ok(path)
+    }
+
+    /// A shell script that sets everything this profile declares through
+    /// the commands the active backends use.
+    pub fn shell_script(&self) -> String {
+        let mut script = String::from("#!/bin/sh\n# Generated by levocale export --shell\nset -e\n");
+        if let Some(layout) = self.wanted_layout(None) {
+            let keyboard = backend::active();
+            script.push_str(&format!("\n# Keyboard ({})\n", keyboard.name()));
+            for command in keyboard.shell_commands(&layout, self.options.as_deref().unwrap_or_default()) {
+                script.push_str(&command);
+                script.push('\n');
+            }
+        }
+        if let Some(lang) = &self.lang {
+            let locale = backend::active_locale();
+            let categories: Vec<(String, String)> =
+                self.categories.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+            script.push_str(&format!("\n# Locale ({})", locale.name()));
+            if let Some(host) = exec::remote_host() {
+                script.push_str(&format!(", to run on {}", host));
+            }
+            script.push('\n');
+            for command in locale.shell_commands(lang, &categories) {
+                script.push_str(&command);
+                script.push('\n');
+            }
+        }
+        script
+    }
+
+    /// The layout code the profile asks for, with the variant folded in as
+    /// the backends expect, e.g. "de(nodeadkeys)".
+    fn wanted_layout(&self, current: Option<&str>) -> Option<String> {
+        if self.layout.is_none() && self.variant.is_none() {
+            return None;
+        }
+        let current_base = current.map(|code| xkb::split_layout_variant(code).0);
+        let layout = self.layout.as_deref().or(current_base)?;
+        Some(match self.variant.as_deref().filter(|variant| !variant.is_empty()) {
+            Some(variant) => format!("{}({})", layout, variant),
+            None => layout.to_string(),
+        })
+    }
+
+    /// Whether everything this profile sets already holds in `current`, a
+    /// snapshot from [`Profile::current`]. Cheaper than [`Profile::changes`]
+    /// when checking many profiles.
+    pub fn is_satisfied_by(&self, current: &Profile) -> bool {
+        let current_layout = current.layout.as_ref().map(|layout| match &current.variant {
+            Some(variant) => format!("{}({})", layout, variant),
+            None => layout.clone(),
+        });
+        let keyboard_matches = exec::remote_host().is_some()
+            || (self.wanted_layout(current_layout.as_deref()).is_none_or(|layout| Some(layout) == current_layout)
+                && self.options.as_ref().is_none_or(|options| Some(options) == current.options.as_ref()));
+        keyboard_matches
+            && self.lang.as_ref().is_none_or(|lang| Some(lang) == current.lang.as_ref())
+            && self.categories.iter().all(|(category, value)| current.categories.get(category) == Some(value))
+            && self.unset.iter().all(|category| !current.categories.contains_key(category))
+    }
+
+    /// Everything that would change, in the order it gets applied.
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let mut push = |setting: Setting, from: Option<String>, to: Option<String>| {
+            if let Some(to) = to
+                && from.as_ref() != Some(&to)
+            {
+                changes.push(Change { setting, from, to });
+            }
+        };
+
+        // The keyboard belongs to this session even with --host
+        if exec::remote_host().is_none() {
+            let keyboard = backend::active();
+            let layout = keyboard.current_layout();
+            push(Setting::Layout, layout.clone(), self.wanted_layout(layout.as_deref()));
+            push(Setting::Options, keyboard.options(), self.options.clone());
+        }
+
+        let locale = backend::active_locale();
+        push(Setting::Lang, locale.current_locale(), self.lang.clone());
+        let current = locale.categories();
+        for (category, value) in &self.categories {
+            let from = current.iter().find(|(name, _)| name == category).map(|(_, value)| value.clone());
+            push(Setting::Category(category.clone()), from, Some(value.clone()));
+        }
+        // Unsetting shows as a change to nothing
+        for category in &self.unset {
+            if let Some((_, value)) = current.iter().find(|(name, _)| name == category) {
+                changes.push(Change {
+                    setting: Setting::Category(category.clone()),
+                    from: Some(value.clone()),
+                    to: String::new(),
+                });
+            }
+        }
+        changes
+    }
+
+    /// Applies [`Profile::changes`], printing a line per step, and fails if
+    /// any of them did.
+    pub fn apply(&self, config: &Config) -> Result<()> {
+        if exec::remote_host().is_some() && (self.layout.is_some() || self.variant.is_some() || self.options.is_some()) {
+            println!("  keyboard: skipped, it isn't managed on a remote host");
+        }
+        print_outcome(&self.apply_steps(config))
+    }
+
+    /// Applies every change, returning a description and outcome per step.
+    pub fn apply_steps(&self, config: &Config) -> Outcome {
+        apply_changes(&self.changes(), config)
+    }
+}
+
+/// Prints a line per step and per revert, and fails if any step did.
+pub fn print_outcome(outcome: &Outcome) -> Result<()> {
+    if outcome.steps.is_empty() {
+        println!("Already up to date");
+        return Ok(());
+    }
+    for (step, result) in &outcome.steps {
+        match result {
+            Ok(()) => println!("✓ {}", step),
+            Err(e) => println!("✗ {}: {:#}", step, e),
+        }
+    }
+    for (step, result) in &outcome.reverted {
+        match result {
+            Ok(()) => println!("↺ reverted {}", step),
+            Err(e) => println!("✗ couldn't revert {}: {:#}", step, e),
+        }
+    }
+    let failed = outcome.failed();
+    if failed > 0 {
+        bail!("{} of {} steps failed", failed, outcome.steps.len());
+    }
+    Ok(())
+}
+
+/// Puts the keyboard in use, X11, the Linux console and LANG on one
+/// `layout` and `locale`. The layout is set even when the keyboard in use
+/// has it already, since X11 and the console can't be read as reliably.
+pub fn sync_all(layout: &str, locale: &str, config: &Config) -> Outcome {
+    let mut config = config.clone();
+    for name in ["x11", "console"] {
+        if !config.mirror_keyboard.iter().any(|mirror| mirror == name) {
+            config.mirror_keyboard.push(name.to_string());
+        }
+    }
+    let mut changes = Vec::new();
+    // The keyboard belongs to this session even with --host
+    if exec::remote_host().is_none() {
+        let from = backend::active().current_layout();
+        changes.push(Change { setting: Setting::Layout, from, to: layout.to_string() });
+    }
+    let from = backend::active_locale().current_locale();
+    if from.as_deref() != Some(locale) {
+        changes.push(Change { setting: Setting::Lang, from, to: locale.to_string() });
+    }
+    apply_changes(&changes, &config)
+}
+
+/// Applies `changes`, a selection from [`Profile::changes`] in the same
+/// order. Stops at the first one that fails and puts back those already
+/// made, the failed one included, so the system isn't left with half a
+/// profile.
+pub fn apply_changes(changes: &[Change], config: &Config) -> Outcome {
+    // Layout and options go in one step, so the keymap doesn't pass
+    // through a mix of old and new settings
+    let keyboard: Vec<&Change> = changes.iter().filter(|change| change.setting.is_keyboard()).collect();
+    let groups = Some(keyboard)
+        .filter(|keyboard| !keyboard.is_empty())
+        .into_iter()
+        .chain(changes.iter().filter(|change| !change.setting.is_keyboard()).map(|change| vec![change]));
+    let mut outcome = Outcome { steps: Vec::new(), reverted: Vec::new() };
+    let mut applied: Vec<Vec<&Change>> = Vec::new();
+    for group in groups {
+        let allowed = allow_group(&group, config);
+        let started = allowed.is_ok();
+        let result = allowed.and_then(|()| apply_group(&group, config));
+        let failed = result.is_err();
+        outcome.steps.push((describe_group(&group), result));
+        // A group the hooks refused never started; one that failed may have
+        // got partway, e.g. the layout set and then the options refused
+        if started {
+            applied.push(group);
+        }
+        if failed {
+            outcome.reverted = applied.iter().rev().map(|group| revert_group(group, config)).collect();
+            return outcome;
+        }
+    }
+    if changes.iter().any(|change| change.setting.is_keyboard()) {
+        let wanted = |setting: Setting| {
+            changes.iter().find(|change| change.setting == setting).map(|change| change.to.as_str())
+        };
+        for (name, result) in backend::mirror_keyboard(config, wanted(Setting::Layout), wanted(Setting::Options)) {
+            outcome.steps.push((format!("keyboard on {}", name), result));
+        }
+        if config.persist_keyboard {
+            outcome.steps.push(("persist keyboard".to_string(), backend::active().persist()));
+        }
+        if config.sync_greeter {
+            outcome.steps.push(("sync greeter".to_string(), sync_greeter()));
+        }
+    }
+    let keyboard = changes.iter().any(|change| change.setting.is_keyboard());
+    let locale = changes.iter().any(|change| !change.setting.is_keyboard());
+    for (changed, wanted) in [(hooks::Changed::Layout, keyboard), (hooks::Changed::Locale, locale)] {
+        if wanted && !changed.after(&config.hooks).is_empty() {
+            outcome.steps.push((format!("after-{} hooks", changed.name()), hooks::run_after(&config.hooks, changed)));
+        }
+    }
+    outcome
+}
+
+/// Applies locale `changes` in a single step, one backend call for all of
+/// them, e.g. for `levocale set` in a provisioning script where each call
+/// could mean another polkit prompt.
+pub fn apply_locale_together(changes: &[Change], config: &Config) -> Outcome {
+    // The NixOS snippet only has room for LANG
+    if system::Platform::detect() == system::Platform::NixOs {
+        return apply_changes(changes, config);
+    }
+    let group: Vec<&Change> = changes.iter().collect();
+    let mut outcome = Outcome { steps: Vec::new(), reverted: Vec::new() };
+    if group.is_empty() {
+        return outcome;
+    }
+    let locale = backend::active_locale();
+    let variables: Vec<(String, String)> =
+        changes.iter().map(|change| (change.setting.name().to_string(), change.to.clone())).collect();
+    let result = allow_group(&group, config)
+        .and_then(|()| changes.iter().try_for_each(|change| locale.validate(&change.to)))
+        .and_then(|()| locale.set_variables(&variables));
+    let failed = result.is_err();
+    outcome.steps.push((describe_group(&group), result));
+    if failed {
+        return outcome;
+    }
+    if config.accountsservice
+        && let Some(lang) = changes.iter().find(|change| change.setting == Setting::Lang)
+    {
+        outcome.steps.push(("AccountsService language".to_string(), accounts::set_language(&lang.to)));
+    }
+    if !hooks::Changed::Locale.after(&config.hooks).is_empty() {
+        outcome.steps.push(("after-locale hooks".to_string(), hooks::run_after(&config.hooks, hooks::Changed::Locale)));
+    }
+    outcome
+}
+
+fn describe_group(group: &[&Change]) -> String {
+    let mut step = group.iter().map(|change| change.describe()).collect::<Vec<_>>().join(", ");
+    // /etc is generated on NixOS, so the snippet is all we can offer
+    if group.iter().any(|change| change.setting == Setting::Lang)
+        && system::Platform::detect() == system::Platform::NixOs
+    {
+        step.push_str(&format!(" (in {})", nixos::snippet_path().display()));
+    }
+    step
+}
+
+/// Asks the before hooks about a group, naming the layout, or the options
+/// when the layout stays, or the locale.
+fn allow_group(group: &[&Change], config: &Config) -> Result<()> {
+    let Some(first) = group.first() else {
+        return Ok(());
+    };
+    let changed = if first.setting.is_keyboard() { hooks::Changed::Layout } else { hooks::Changed::Locale };
+    hooks::run_before(&config.hooks, changed, &first.to)
+}
+
+/// Applies the keyboard settings together, or a single locale setting.
+fn apply_group(group: &[&Change], config: &Config) -> Result<()> {
+    match group {
+        [change] if !change.setting.is_keyboard() => apply_change(change, config),
+        _ => {
+            let wanted = |setting: Setting| {
+                group.iter().find(|change| change.setting == setting).map(|change| change.to.as_str())
+            };
+            backend::active().set_keyboard(wanted(Setting::Layout), wanted(Setting::Options))
+        }
+    }
+}
+
+/// Sets everything in an applied group back to what it was, describing
+/// the step the way it's applied: from the profile's value to the old one.
+/// Locale variables that weren't set before are unset again, and options
+/// that weren't are cleared.
+fn revert_group(group: &[&Change], config: &Config) -> (String, Result<()>) {
+    let mut undo: Vec<Change> = Vec::new();
+    let mut unset: Vec<String> = Vec::new();
+    let mut unknown: Vec<&Change> = Vec::new();
+    for change in group {
+        let to = match (&change.from, &change.setting) {
+            (Some(from), _) => from.clone(),
+            (None, Setting::Lang | Setting::Category(_)) => {
+                unset.push(change.setting.name().to_string());
+                String::new()
+            }
+            (None, Setting::Options) => String::new(),
+            (None, Setting::Layout) => {
+                unknown.push(change);
+                continue;
+            }
+        };
+        undo.push(Change { setting: change.setting.clone(), from: Some(change.to.clone()), to });
+    }
+    let mut step = describe_group(&undo.iter().collect::<Vec<_>>());
+    let reset: Vec<&Change> =
+        undo.iter().filter(|change| !unset.iter().any(|name| name == change.setting.name())).collect();
+    let mut result = if reset.is_empty() { Ok(()) } else { apply_group(&reset, config) };
+    if !unset.is_empty() {
+        result = result.and(backend::active_locale().unset_variables(&unset));
+    }
+    // Nothing to set the layout back to: the old one couldn't be read
+    for change in unknown {
+        if !step.is_empty() {
+            step.push_str(", ");
+        }
+        step.push_str(change.setting.name());
+        result = result.and(Err(anyhow!("the layout before couldn't be read, so it's still {}", change.to)));
+    }
+    (step, result)
+}
+
+fn apply_change(change: &Change, config: &Config) -> Result<()> {
+    if let Setting::Category(category) = &change.setting
+        && change.to.is_empty()
+    {
+        return backend::active_locale().unset_variables(std::slice::from_ref(category));
+    }
+    if matches!(change.setting, Setting::Lang | Setting::Category(_)) {
+        backend::active_locale().validate(&change.to)?;
+    }
+    match &change.setting {
+        Setting::Layout => backend::active().set_layout(&change.to),
+        Setting::Options => backend::active().set_options(&change.to),
+        Setting::Lang if system::Platform::detect() == system::Platform::NixOs => {
+            let keyboard = backend::active();
+            nixos::write_snippet(
+                &change.to,
+                &keyboard.current_layout().unwrap_or_default(),
+                &keyboard.options().unwrap_or_default(),
+            )
+            .map(|_| ())
+        }
+        Setting::Lang => {
+            // set_locale may replace the whole file (localectl does), which
+            // would drop the LC_* values the profile leaves as they are
+            backend::active_locale().set_variables(&[("LANG".to_string(), change.to.clone())])?;
+            if config.accountsservice {
+                accounts::set_language(&change.to)?;
+            }
+            Ok(())
+        }
+        Setting::Category(category) => backend::active_locale().set_category(category, &change.to),
+    }
+}
+
+fn sync_greeter() -> Result<()> {
+    let Some(greeter) = greeter::Greeter::detect() else {
+        bail!("no supported greeter found");
+    };
+    let keyboard = backend::active();
+    greeter.sync_keyboard(
+        &keyboard.current_layout().unwrap_or_default(),
+        &keyboard.options().unwrap_or_default(),
+    )
+}
@@ -0,0 +1,62 @@
+//! Where the machine probably is, going by its timezone, for suggesting
+//! locales and layouts on the first run.
+
+use std::collections::HashSet;
+use std::fs;
+
+use crate::locale_info;
+
+/// The configured timezone, e.g. "Europe/Berlin": TZ first, then
+/// /etc/timezone as Debian keeps it, then where /etc/localtime points.
+pub fn timezone() -> Option<String> {
+    let from_env = std::env::var("TZ").ok().map(|zone| zone.trim_start_matches(':').to_string());
+    let from_file = || fs::read_to_string("/etc/timezone").ok().map(|zone| zone.trim().to_string());
+    let from_link = || {
+        let target = fs::read_link("/etc/localtime").ok()?;
+        let target = target.to_string_lossy();
+        target.split_once("zoneinfo/").map(|(_, zone)| zone.to_string())
+    };
+    from_env
+        .filter(|zone| zone.contains('/'))
+        .or_else(|| from_file().filter(|zone| !zone.is_empty()))
+        .or_else(from_link)
+        // UTC and the like say nothing about where anyone is
+        .filter(|zone| !zone.starts_with("Etc/") && zone.contains('/'))
+}
+
+/// The country a timezone belongs to, e.g. "DE" for "Europe/Berlin", from
+/// tzdata's zone tables.
+pub fn country(timezone: &str) -> Option<String> {
+    for table in ["/usr/share/zoneinfo/zone.tab", "/usr/share/zoneinfo/zone1970.tab"] {
+        let Ok(contents) = fs::read_to_string(table) else {
+            continue;
+        };
+        // e.g. "DE	+5230+01322	Europe/Berlin	most of Germany"; zone1970.tab
+        // lists every country sharing the zone, the main one first
+        let found = contents.lines().filter(|line| !line.starts_with('#')).find_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (fields.get(2) == Some(&timezone)).then(|| fields[0].split(',').next().unwrap_or_default().to_string())
+        });
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// The locales among `available` for `country`, UTF-8 ones only when there
+/// are any, e.g. "de_DE.UTF-8" for "DE".
+pub fn locales_for(country: &str, available: &[String]) -> Vec<String> {
+    let matching: Vec<&String> = available
+        .iter()
+        .filter(|code| locale_info::split_locale_code(code).territory == Some(country))
+        .collect();
+    let utf8 = |code: &&String| locale_info::split_locale_code(code).codeset.is_some_and(locale_info::is_utf8);
+    let mut locales: Vec<String> = match matching.iter().any(utf8) {
+        true => matching.into_iter().filter(utf8).cloned().collect(),
+        false => matching.into_iter().cloned().collect(),
+    };
+    let mut seen = HashSet::new();
+    locales.retain(|code| seen.insert(locale_info::normalized_locale_name(code)));
+    locales
+}
@@ -0,0 +1,77 @@
+//! logind seats. Keyboard changes normally go to the compositor of the seat
+//! levocale runs on; another seat can be picked instead, whose keyboards are
+//! then configured through Xorg's per-seat config.
+
+use std::fs;
+use std::sync::Mutex;
+
+use crate::exec;
+
+/// The seat picked with --seat or in the TUI, when it isn't our own.
+static TARGET: Mutex<Option<String>> = Mutex::new(None);
+
+/// The seat this session belongs to.
+pub fn own() -> String {
+    std::env::var("XDG_SEAT").ok().filter(|seat| !seat.is_empty()).unwrap_or_else(|| "seat0".to_string())
+}
+
+/// Every seat logind knows, falling back to our own when loginctl can't
+/// tell.
+pub fn list() -> Vec<String> {
+    let seats: Vec<String> = exec::local("loginctl")
+        .args(["list-seats", "--no-legend"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| line.split_whitespace().next().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    if seats.is_empty() { vec![own()] } else { seats }
+}
+
+pub fn target() -> Option<String> {
+    TARGET.lock().ok().and_then(|target| target.clone())
+}
+
+/// Points keyboard changes at `seat`; our own seat means the live session
+/// again.
+pub fn set_target(seat: &str) {
+    if let Ok(mut target) = TARGET.lock() {
+        *target = (seat != own()).then(|| seat.to_string());
+    }
+}
+
+/// The seat keyboard changes currently go to.
+pub fn effective() -> String {
+    target().unwrap_or_else(own)
+}
+
+/// Names of the keyboards attached to `seat`, as the kernel reports them.
+pub fn keyboards(seat: &str) -> Vec<String> {
+    let Ok(output) = exec::local("loginctl").args(["seat-status", "--no-pager", seat]).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // │ input:input5 "AT Translated Set 2 keyboard"
+            let (_, rest) = line.split_once("input:")?;
+            let (device, name) = rest.split_once(' ')?;
+            is_keyboard(device).then(|| name.trim().trim_matches('"').to_string())
+        })
+        .collect()
+}
+
+/// Whether an input device has key repeat, which keyboards do and mice,
+/// power buttons and the like don't.
+fn is_keyboard(device: &str) -> bool {
+    const EV_REP: u64 = 1 << 20;
+    fs::read_to_string(format!("/sys/class/input/{}/capabilities/ev", device))
+        .ok()
+        .and_then(|bits| u64::from_str_radix(bits.trim(), 16).ok())
+        .is_some_and(|bits| bits & EV_REP != 0)
+}
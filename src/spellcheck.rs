@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::{exec, log};
+
+const HUNSPELL_DIRS: [&str; 3] = ["/usr/share/hunspell", "/usr/share/myspell/dicts", "/usr/share/myspell"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Pacman,
+    Apt,
+    Dnf,
+    Zypper,
+    Apk,
+    RpmOstree,
+}
+
+impl PackageManager {
+    pub fn detect() -> Option<PackageManager> {
+        // dnf may be present on ostree systems but can't install there
+        if exec::exists(Path::new("/run/ostree-booted")) && exec::exists(Path::new("/usr/bin/rpm-ostree")) {
+            return Some(PackageManager::RpmOstree);
+        }
+        [
+            ("/usr/bin/pacman", PackageManager::Pacman),
+            ("/usr/bin/apt-get", PackageManager::Apt),
+            ("/usr/bin/dnf", PackageManager::Dnf),
+            ("/usr/bin/zypper", PackageManager::Zypper),
+            ("/sbin/apk", PackageManager::Apk),
+        ]
+        .into_iter()
+        .find(|(binary, _)| exec::exists(Path::new(binary)))
+        .map(|(_, manager)| manager)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PackageManager::Pacman => "pacman",
+            PackageManager::Apt => "apt-get",
+            PackageManager::Dnf => "dnf",
+            PackageManager::Zypper => "zypper",
+            PackageManager::Apk => "apk",
+            PackageManager::RpmOstree => "rpm-ostree",
+        }
+    }
+
+    fn install_args(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Pacman => &["-S", "--needed", "--noconfirm"],
+            PackageManager::Apt => &["install", "-y"],
+            PackageManager::Dnf => &["install", "-y"],
+            PackageManager::Zypper => &["--non-interactive", "install"],
+            PackageManager::Apk => &["add"],
+            PackageManager::RpmOstree => &["install", "--idempotent"],
+        }
+    }
+
+    /// Whether installed packages only show up after a reboot.
+    pub fn needs_reboot(self) -> bool {
+        self == PackageManager::RpmOstree
+    }
+
+    /// Best guess at the hunspell dictionary package for a language under
+    /// each distribution's naming scheme.
+    pub fn dictionary_package(self, language: &str, territory: Option<&str>) -> String {
+        let territory_lower = territory.map(str::to_lowercase);
+        match self {
+            // Arch splits English by region but ships one package otherwise
+            PackageManager::Pacman => match territory_lower {
+                Some(t) if language == "en" => format!("hunspell-en_{}", t),
+                _ => format!("hunspell-{}", language),
+            },
+            PackageManager::Apt | PackageManager::Apk => match territory_lower {
+                Some(t) => format!("hunspell-{}-{}", language, t),
+                None => format!("hunspell-{}", language),
+            },
+            PackageManager::Dnf | PackageManager::RpmOstree => format!("hunspell-{}", language),
+            PackageManager::Zypper => match territory {
+                Some(t) => format!("myspell-{}_{}", language, t),
+                None => format!("myspell-{}", language),
+            },
+        }
+    }
+
+    pub fn install(self, package: &str) -> Result<()> {
+        let mut args = vec![self.name()];
+        args.extend(self.install_args());
+        args.push(package);
+        let result = log::run(&mut exec::privileged(&args)?);
+        match result {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                bail!("Failed to install {}: {}", package, error.trim())
+            }
+            Err(e) => bail!("Failed to execute {}: {}", self.name(), e),
+        }
+    }
+}
+
+fn hunspell_has(language: &str) -> bool {
+    HUNSPELL_DIRS.iter().any(|dir| {
+        exec::read_dir_names(Path::new(dir)).iter().any(|name| {
+            name.ends_with(".dic")
+                && (*name == format!("{}.dic", language) || name.starts_with(&format!("{}_", language)))
+        })
+    })
+}
+
+fn aspell_has(language: &str) -> bool {
+    let Ok(output) = exec::command("aspell", &["dicts"]).output() else {
+        return false;
+    };
+    String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+        let line = line.trim();
+        line == language || line.starts_with(&format!("{}_", language))
+    })
+}
+
+/// Whether any hunspell or aspell dictionary covers the language.
+pub fn has_dictionary(language: &str) -> bool {
+    hunspell_has(language) || aspell_has(language)
+}
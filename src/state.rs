@@ -0,0 +1,52 @@
+//! UI state remembered between runs, kept apart from the config since it
+//! changes on every run and losing it costs nothing.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct State {
+    /// The highlighted entry as an item key, e.g. "layout:de".
+    pub selected: Option<String>,
+    /// Names of the folded-away sections.
+    pub collapsed: Vec<String>,
+    pub view_mode: Option<String>,
+    /// Whether the locale list shows every supported locale rather than
+    /// only the installed ones.
+    pub all_locales: Option<bool>,
+    /// Item keys of recently applied entries, newest first.
+    pub recent: Vec<String>,
+    /// The keymap file loaded into the X server, by display, since the
+    /// server can't say where its keymap came from.
+    pub x11_keymaps: BTreeMap<String, String>,
+}
+
+pub fn state_path() -> PathBuf {
+    config::xdg_state_home().join("levocale").join("state.toml")
+}
+
+impl State {
+    /// The saved state, or defaults when it's missing or unreadable.
+    pub fn load() -> State {
+        fs::read_to_string(state_path())
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = state_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let contents = toml::to_string(self).context("Failed to serialize the UI state")?;
+        fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use crate::exec;
+
+/// The kind of OS install, where it changes how system settings are made.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Generic,
+    /// NixOS rebuilds /etc from configuration.nix, so direct writes are lost.
+    NixOs,
+    /// rpm-ostree systems (Silverblue, Kinoite): /usr is read-only, so
+    /// locales and packages have to be layered.
+    Ostree,
+    /// musl libc (Alpine, Void's musl flavor): there's no locale archive or
+    /// localedef, and any locale name is accepted as it is.
+    Musl,
+}
+
+impl Platform {
+    pub fn detect() -> Platform {
+        if exec::exists(Path::new("/etc/NIXOS")) || os_release_field("ID").as_deref() == Some("nixos") {
+            return Platform::NixOs;
+        }
+        if exec::exists(Path::new("/run/ostree-booted")) {
+            return Platform::Ostree;
+        }
+        if is_musl() {
+            return Platform::Musl;
+        }
+        Platform::Generic
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Generic => "generic",
+            Platform::NixOs => "NixOS",
+            Platform::Ostree => "rpm-ostree",
+            Platform::Musl => "musl",
+        }
+    }
+}
+
+/// Whether the C library is musl, whose `ldd --version` says so on stderr.
+pub fn is_musl() -> bool {
+    exec::exists(Path::new("/etc/alpine-release"))
+        || exec::command("ldd", &["--version"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stderr).contains("musl"))
+}
+
+/// A field from /etc/os-release, unquoted.
+pub fn os_release_field(key: &str) -> Option<String> {
+    let contents = exec::read_to_string(Path::new("/etc/os-release")).ok()?;
+    contents.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        (name == key).then(|| value.trim_matches('"').to_string())
+    })
+}
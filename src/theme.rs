@@ -0,0 +1,169 @@
+//! Color presets for the TUI, picked with `theme` in the config and
+//! switchable while running.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ratatui::style::{Color, Modifier, Style};
+
+/// The style of each part of the screen, by role rather than by color.
+pub struct Theme {
+    pub name: &'static str,
+    pub text: Style,
+    /// Secondary text, e.g. the controls line.
+    pub dim: Style,
+    /// Inactive borders and fading toasts.
+    pub faint: Style,
+    /// The outer frame and progress popups.
+    pub frame: Style,
+    pub status: Style,
+    pub details: Style,
+    /// The border of the pane that has focus.
+    pub focused: Style,
+    pub header: Style,
+    pub selection: Style,
+    pub header_selection: Style,
+    /// Where the cursor stays in panes without focus.
+    pub inactive_selection: Style,
+    /// Borders of prompts and other popups that wait for input, and the
+    /// quick-select row.
+    pub popup: Style,
+    pub success: Style,
+    pub failure: Style,
+    pub warning: Style,
+}
+
+const fn fg(color: Color) -> Style {
+    Style::new().fg(color)
+}
+
+const fn on(fg: Color, bg: Color) -> Style {
+    Style::new().fg(fg).bg(bg).add_modifier(Modifier::BOLD)
+}
+
+pub const PRESETS: [Theme; 5] = [
+    Theme {
+        name: "default",
+        text: fg(Color::White),
+        dim: fg(Color::Gray),
+        faint: fg(Color::DarkGray),
+        frame: fg(Color::Cyan),
+        status: fg(Color::Green),
+        details: fg(Color::Magenta),
+        focused: fg(Color::Blue),
+        header: fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        selection: on(Color::Black, Color::Yellow),
+        header_selection: on(Color::Black, Color::Cyan),
+        inactive_selection: Style::new().fg(Color::White).bg(Color::DarkGray),
+        popup: fg(Color::Yellow),
+        success: fg(Color::Green),
+        failure: fg(Color::Red),
+        warning: fg(Color::Yellow),
+    },
+    // Bright colors only, and white where the default has dim ones
+    Theme {
+        name: "high-contrast",
+        text: fg(Color::White),
+        dim: fg(Color::White),
+        faint: fg(Color::Gray),
+        frame: fg(Color::White),
+        status: fg(Color::White),
+        details: fg(Color::White),
+        focused: fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+        header: fg(Color::LightCyan).add_modifier(Modifier::BOLD),
+        selection: on(Color::Black, Color::White),
+        header_selection: on(Color::Black, Color::LightCyan),
+        inactive_selection: Style::new().fg(Color::White).add_modifier(Modifier::UNDERLINED),
+        popup: fg(Color::LightYellow),
+        success: fg(Color::LightGreen),
+        failure: fg(Color::LightRed).add_modifier(Modifier::BOLD),
+        warning: fg(Color::LightYellow),
+    },
+    // Okabe-Ito colors: blue and orange instead of green and red
+    Theme {
+        name: "deuteranopia",
+        text: fg(Color::White),
+        dim: fg(Color::Gray),
+        faint: fg(Color::DarkGray),
+        frame: fg(Color::Rgb(86, 180, 233)),
+        status: fg(Color::Rgb(0, 114, 178)),
+        details: fg(Color::Rgb(204, 121, 167)),
+        focused: fg(Color::Rgb(240, 228, 66)),
+        header: fg(Color::Rgb(86, 180, 233)).add_modifier(Modifier::BOLD),
+        selection: on(Color::Black, Color::Rgb(240, 228, 66)),
+        header_selection: on(Color::Black, Color::Rgb(86, 180, 233)),
+        inactive_selection: Style::new().fg(Color::White).bg(Color::DarkGray),
+        popup: fg(Color::Rgb(240, 228, 66)),
+        success: fg(Color::Rgb(86, 180, 233)),
+        failure: fg(Color::Rgb(230, 159, 0)).add_modifier(Modifier::BOLD),
+        warning: fg(Color::Rgb(240, 228, 66)),
+    },
+    // Only the 16 palette colors, and the terminal's own foreground and
+    // background for text, so palettes from pywal, base16 and the like
+    // carry over. Highlights are reversed rather than drawn on a fixed
+    // background, which keeps them readable on light palettes too.
+    Theme {
+        name: "terminal",
+        text: Style::new(),
+        dim: fg(Color::Gray),
+        faint: fg(Color::DarkGray),
+        frame: fg(Color::Blue),
+        status: fg(Color::Green),
+        details: fg(Color::Magenta),
+        focused: fg(Color::Cyan),
+        header: fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        selection: fg(Color::Yellow).add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        header_selection: fg(Color::Cyan).add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        inactive_selection: fg(Color::DarkGray).add_modifier(Modifier::REVERSED),
+        popup: fg(Color::Yellow),
+        success: fg(Color::Green),
+        failure: fg(Color::Red),
+        warning: fg(Color::Yellow),
+    },
+    // The terminal's own colors, with emphasis through attributes alone
+    Theme {
+        name: "monochrome",
+        text: Style::new(),
+        dim: Style::new(),
+        faint: Style::new().add_modifier(Modifier::DIM),
+        frame: Style::new(),
+        status: Style::new(),
+        details: Style::new(),
+        focused: Style::new().add_modifier(Modifier::BOLD),
+        header: Style::new().add_modifier(Modifier::BOLD),
+        selection: Style::new().add_modifier(Modifier::REVERSED),
+        header_selection: Style::new().add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        inactive_selection: Style::new().add_modifier(Modifier::UNDERLINED),
+        popup: Style::new().add_modifier(Modifier::BOLD),
+        success: Style::new(),
+        failure: Style::new().add_modifier(Modifier::BOLD),
+        warning: Style::new().add_modifier(Modifier::BOLD),
+    },
+];
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn current() -> &'static Theme {
+    &PRESETS[CURRENT.load(Ordering::Relaxed)]
+}
+
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|theme| theme.name).collect()
+}
+
+/// Switches to the preset called `name`, if there is one.
+pub fn set(name: &str) -> bool {
+    match PRESETS.iter().position(|theme| theme.name == name) {
+        Some(index) => {
+            CURRENT.store(index, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Moves on to the next preset, wrapping around.
+pub fn cycle() -> &'static Theme {
+    let next = (CURRENT.load(Ordering::Relaxed) + 1) % PRESETS.len();
+    CURRENT.store(next, Ordering::Relaxed);
+    &PRESETS[next]
+}
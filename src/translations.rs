@@ -0,0 +1,136 @@
+//! The built-in wording of the announcements in [`crate::messages`], one
+//! table per language keyed by message id. The ids are the keys of
+//! `[notifications.messages]`, and English covers every one of them.
+
+const ENGLISH: &[(&str, &str)] = &[
+    ("layout_set", "Keyboard layout set to: {code}"),
+    ("keymap_loaded", "Keymap file loaded: {name}"),
+    ("keymap_cleared", "Keymap file cleared"),
+    ("locale_set", "Language set to: {name}"),
+    ("locale_and_layout", "Locale: {code} {detail} • Layout: {name}"),
+    ("input_method_set", "Input method set to: {code}"),
+    ("engine_enabled", "Enabled {code} in {backend}"),
+    ("engine_removed", "Removed {code} from {backend}"),
+    ("option_enabled", "Keyboard option {code} enabled"),
+    ("option_disabled", "Keyboard option {code} disabled"),
+    ("compose_enabled", "Compose key enabled on {code}"),
+    ("compose_disabled", "Compose key disabled"),
+    ("restart_needed", "{detail} (takes effect after restarting {backend})"),
+    ("profile_applied", "Switched to profile {name}"),
+    ("profile_in_effect", "Profile {name} is in effect already"),
+    ("profile_saved", "Saved profile {name} to {detail}"),
+    ("reverted", "Reverted {detail}"),
+    ("synced", "Synced to {code}: {detail}"),
+    ("seat_targeted", "Keyboard changes now go to {name} (the locale is shared by all seats)"),
+    ("seat_reset", "Keyboard changes go to this session on {name} again"),
+    ("copied", "Copied {code}"),
+    ("image_saved", "Saved an image of {code} to {detail}"),
+    ("installed", "Installed {code}"),
+    ("layered", "Layered {code}; reboot to start using it"),
+    ("sorted", "Sorted by {name}"),
+];
+
+const GERMAN: &[(&str, &str)] = &[
+    ("layout_set", "Tastaturbelegung: {code}"),
+    ("keymap_loaded", "Keymap-Datei geladen: {name}"),
+    ("keymap_cleared", "Keymap-Datei entfernt"),
+    ("locale_set", "Sprache: {name}"),
+    ("locale_and_layout", "Sprache: {code} {detail} • Belegung: {name}"),
+    ("input_method_set", "Eingabemethode: {code}"),
+    ("engine_enabled", "{code} in {backend} aktiviert"),
+    ("engine_removed", "{code} aus {backend} entfernt"),
+    ("option_enabled", "Tastaturoption {code} aktiviert"),
+    ("option_disabled", "Tastaturoption {code} deaktiviert"),
+    ("compose_enabled", "Compose-Taste auf {code} aktiviert"),
+    ("compose_disabled", "Compose-Taste deaktiviert"),
+    ("restart_needed", "{detail} (wirkt nach einem Neustart von {backend})"),
+    ("profile_applied", "Profil {name} angewendet"),
+    ("profile_in_effect", "Profil {name} ist bereits aktiv"),
+    ("profile_saved", "Profil {name} unter {detail} gespeichert"),
+    ("reverted", "Zurückgenommen: {detail}"),
+    ("synced", "Auf {code} abgeglichen: {detail}"),
+    ("seat_targeted", "Tastaturänderungen gehen jetzt an {name} (die Sprache gilt für alle Seats)"),
+    ("seat_reset", "Tastaturänderungen gehen wieder an diese Sitzung auf {name}"),
+    ("copied", "{code} kopiert"),
+    ("image_saved", "Bild von {code} unter {detail} gespeichert"),
+    ("installed", "{code} installiert"),
+    ("layered", "{code} eingespielt; nach einem Neustart verfügbar"),
+    ("sorted", "Sortiert nach {name}"),
+];
+
+const FRENCH: &[(&str, &str)] = &[
+    ("layout_set", "Disposition du clavier : {code}"),
+    ("keymap_loaded", "Fichier keymap chargé : {name}"),
+    ("keymap_cleared", "Fichier keymap retiré"),
+    ("locale_set", "Langue : {name}"),
+    ("locale_and_layout", "Langue : {code} {detail} • Disposition : {name}"),
+    ("input_method_set", "Méthode de saisie : {code}"),
+    ("engine_enabled", "{code} activé dans {backend}"),
+    ("engine_removed", "{code} retiré de {backend}"),
+    ("option_enabled", "Option du clavier {code} activée"),
+    ("option_disabled", "Option du clavier {code} désactivée"),
+    ("compose_enabled", "Touche compose activée sur {code}"),
+    ("compose_disabled", "Touche compose désactivée"),
+    ("restart_needed", "{detail} (effectif après le redémarrage de {backend})"),
+    ("profile_applied", "Profil {name} appliqué"),
+    ("profile_in_effect", "Le profil {name} est déjà en place"),
+    ("profile_saved", "Profil {name} enregistré dans {detail}"),
+    ("reverted", "Annulé : {detail}"),
+    ("synced", "Synchronisé sur {code} : {detail}"),
+    (
+        "seat_targeted",
+        "Les changements du clavier vont maintenant à {name} (la langue est commune à tous les postes)",
+    ),
+    ("seat_reset", "Les changements du clavier vont de nouveau à cette session sur {name}"),
+    ("copied", "{code} copié"),
+    ("image_saved", "Image de {code} enregistrée dans {detail}"),
+    ("installed", "{code} installé"),
+    ("layered", "{code} ajouté ; redémarrez pour l'utiliser"),
+    ("sorted", "Trié par {name}"),
+];
+
+const SPANISH: &[(&str, &str)] = &[
+    ("layout_set", "Distribución del teclado: {code}"),
+    ("keymap_loaded", "Archivo keymap cargado: {name}"),
+    ("keymap_cleared", "Archivo keymap quitado"),
+    ("locale_set", "Idioma: {name}"),
+    ("locale_and_layout", "Idioma: {code} {detail} • Distribución: {name}"),
+    ("input_method_set", "Método de entrada: {code}"),
+    ("engine_enabled", "{code} activado en {backend}"),
+    ("engine_removed", "{code} quitado de {backend}"),
+    ("option_enabled", "Opción de teclado {code} activada"),
+    ("option_disabled", "Opción de teclado {code} desactivada"),
+    ("compose_enabled", "Tecla compose activada en {code}"),
+    ("compose_disabled", "Tecla compose desactivada"),
+    ("restart_needed", "{detail} (surte efecto tras reiniciar {backend})"),
+    ("profile_applied", "Perfil {name} aplicado"),
+    ("profile_in_effect", "El perfil {name} ya está en uso"),
+    ("profile_saved", "Perfil {name} guardado en {detail}"),
+    ("reverted", "Revertido: {detail}"),
+    ("synced", "Sincronizado con {code}: {detail}"),
+    ("seat_targeted", "Los cambios de teclado van ahora a {name} (el idioma es común a todos los puestos)"),
+    ("seat_reset", "Los cambios de teclado vuelven a esta sesión en {name}"),
+    ("copied", "{code} copiado"),
+    ("image_saved", "Imagen de {code} guardada en {detail}"),
+    ("installed", "{code} instalado"),
+    ("layered", "{code} añadido; reinicie para usarlo"),
+    ("sorted", "Ordenado por {name}"),
+];
+
+/// Languages by their code in a locale name, e.g. "de" for de_DE.UTF-8.
+const LANGUAGES: &[(&str, &[(&str, &str)])] = &[("en", ENGLISH), ("de", GERMAN), ("fr", FRENCH), ("es", SPANISH)];
+
+/// The wording of message `id` in `language`, falling back to English.
+pub fn lookup(language: &str, id: &str) -> Option<&'static str> {
+    let find = |table: &[(&str, &'static str)]| table.iter().find(|(key, _)| *key == id).map(|(_, text)| *text);
+    LANGUAGES
+        .iter()
+        .find(|(code, _)| *code == language)
+        .and_then(|(_, table)| find(table))
+        .or_else(|| find(ENGLISH))
+}
+
+/// Every message id, e.g. to check `[notifications.messages]` against.
+pub fn ids() -> impl Iterator<Item = &'static str> {
+    ENGLISH.iter().map(|(id, _)| *id)
+}
@@ -0,0 +1,405 @@
+//! The daemon's tray icon, as a StatusNotifierItem: the layout code drawn
+//! as a picture, and a dbusmenu menu to switch between the favorite layouts
+//! or open the TUI. Clicking the icon opens the TUI and scrolling over it
+//! cycles the layouts.
+
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use anyhow::{Context, Result};
+
+use crate::dbus::{self, Bus, Incoming, Message, Value, dict, string, variant};
+use crate::log;
+use crate::xkb::{self, XkbRegistry};
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+
+/// What was picked from the tray, for the daemon to carry out.
+pub enum Action {
+    SwitchTo(String),
+    OpenTui,
+}
+
+/// Rows of a 3x5 font, the top bit of three being the left column.
+fn glyph(character: char) -> [u8; 5] {
+    match character {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        _ => [0; 5],
+    }
+}
+
+/// The few letters shown for a layout, e.g. "DE" for "de(nodeadkeys),us".
+fn short_code(layout: &str) -> String {
+    let first = layout.split(',').next().unwrap_or(layout).trim();
+    let (base, _) = xkb::split_layout_variant(first);
+    base.chars().filter(char::is_ascii_alphanumeric).take(3).collect::<String>().to_uppercase()
+}
+
+/// An icon `size` pixels square with `text` in white on dark grey, as
+/// SNI's ARGB32 pixels in network byte order.
+fn draw(text: &str, size: usize) -> Value {
+    let count = text.chars().count().max(1);
+    // Glyphs are 3 wide with a 1 column gap between them
+    let scale = (size * 7 / 8 / (count * 4 - 1)).min(size * 7 / 8 / 5).max(1);
+    let (width, height) = ((count * 4 - 1) * scale, 5 * scale);
+    let (left, top) = (size.saturating_sub(width) / 2, size.saturating_sub(height) / 2);
+    let mut pixels = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let lit = x >= left && y >= top && y < top + height && {
+                let (column, row) = ((x - left) / scale, (y - top) / scale);
+                let character = text.chars().nth(column / 4);
+                column % 4 < 3
+                    && character.is_some_and(|character| glyph(character)[row] & (0b100 >> (column % 4)) != 0)
+            };
+            pixels.extend(if lit { [0xff, 0xff, 0xff, 0xff] } else { [0xff, 0x30, 0x30, 0x30] });
+        }
+    }
+    let bytes = pixels.into_iter().map(Value::Byte).collect();
+    Value::Struct(vec![Value::I32(size as i32), Value::I32(size as i32), Value::Array("y".to_string(), bytes)])
+}
+
+/// What the icon and menu show, shared between the thread answering the
+/// bus and the daemon reporting changes.
+struct Shown {
+    registry: XkbRegistry,
+    favorites: Vec<String>,
+    current: String,
+    /// Bumped whenever the menu changes, as dbusmenu wants.
+    revision: u32,
+}
+
+impl Shown {
+    fn describe(&self, layout: &str) -> String {
+        self.registry.describe(layout).unwrap_or(layout).to_string()
+    }
+
+    /// The layouts offered in the menu: the favorites, then the current one
+    /// if it's not among them. Menu item ids are their index plus one.
+    fn layouts(&self) -> Vec<String> {
+        let mut layouts = self.favorites.clone();
+        if !self.current.is_empty() && !layouts.contains(&self.current) {
+            layouts.push(self.current.clone());
+        }
+        layouts
+    }
+
+    fn title(&self) -> String {
+        format!("Levocale: {}", self.current)
+    }
+
+    fn item_property(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "Category" => string("Hardware"),
+            "Id" => string("levocale"),
+            "Title" => string(&self.title()),
+            "Status" => string("Active"),
+            "WindowId" => Value::I32(0),
+            "IconName" => string(""),
+            "IconPixmap" => {
+                let text = short_code(&self.current);
+                Value::Array("(iiay)".to_string(), vec![draw(&text, 22), draw(&text, 24), draw(&text, 48)])
+            }
+            "ToolTip" => Value::Struct(vec![
+                string(""),
+                Value::Array("(iiay)".to_string(), Vec::new()),
+                string(&self.title()),
+                string(&self.describe(&self.current)),
+            ]),
+            "ItemIsMenu" => Value::Bool(false),
+            "Menu" => Value::Path(MENU_PATH.to_string()),
+            _ => return None,
+        })
+    }
+
+    fn menu_property(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "Version" => Value::U32(3),
+            "TextDirection" => string("ltr"),
+            "Status" => string("normal"),
+            _ => return None,
+        })
+    }
+
+    fn properties(&self, interface: &str) -> Vec<(&'static str, Value)> {
+        let names: &[&'static str] = match interface {
+            ITEM_INTERFACE => &[
+                "Category", "Id", "Title", "Status", "WindowId", "IconName", "IconPixmap", "ToolTip", "ItemIsMenu",
+                "Menu",
+            ],
+            MENU_INTERFACE => &["Version", "TextDirection", "Status"],
+            _ => &[],
+        };
+        let get = |name: &str| match interface {
+            ITEM_INTERFACE => self.item_property(name),
+            _ => self.menu_property(name),
+        };
+        names.iter().filter_map(|name| Some((*name, get(name)?))).collect()
+    }
+
+    /// The menu entries as (id, properties), the root being id 0.
+    fn menu_items(&self) -> Vec<(i32, Vec<(&'static str, Value)>)> {
+        let layouts = self.layouts();
+        let mut items: Vec<(i32, Vec<(&'static str, Value)>)> = layouts
+            .iter()
+            .enumerate()
+            .map(|(index, layout)| {
+                let properties = vec![
+                    ("label", string(&format!("{} ({})", self.describe(layout), layout))),
+                    ("toggle-type", string("radio")),
+                    ("toggle-state", Value::I32((*layout == self.current) as i32)),
+                ];
+                (index as i32 + 1, properties)
+            })
+            .collect();
+        let next = layouts.len() as i32 + 1;
+        if !layouts.is_empty() {
+            items.push((next, vec![("type", string("separator"))]));
+        }
+        items.push((next + 1, vec![("label", string("Open levocale"))]));
+        items
+    }
+
+    fn menu_item(id: i32, properties: Vec<(&str, Value)>, children: Vec<Value>) -> Value {
+        Value::Struct(vec![Value::I32(id), dict(properties), Value::Array("v".to_string(), children)])
+    }
+
+    fn layout_reply(&self) -> Vec<Value> {
+        let children = self
+            .menu_items()
+            .into_iter()
+            .map(|(id, properties)| variant(Shown::menu_item(id, properties, Vec::new())))
+            .collect();
+        let root = Shown::menu_item(0, vec![("children-display", string("submenu"))], children);
+        vec![Value::U32(self.revision), root]
+    }
+
+    /// What clicking menu item `id` asks for.
+    fn action(&self, id: i32) -> Option<Action> {
+        let layouts = self.layouts();
+        match usize::try_from(id - 1).ok().and_then(|index| layouts.get(index)) {
+            Some(layout) => Some(Action::SwitchTo(layout.clone())),
+            None if id == layouts.len() as i32 + 2 => Some(Action::OpenTui),
+            None => None,
+        }
+    }
+
+    /// The layout after (or with `back`, before) the current one.
+    fn cycle(&self, back: bool) -> Option<String> {
+        let layouts = self.layouts();
+        let index = layouts.iter().position(|layout| *layout == self.current)?;
+        let next = if back { index + layouts.len() - 1 } else { index + 1 } % layouts.len();
+        Some(layouts[next].clone()).filter(|layout| *layout != self.current)
+    }
+}
+
+/// The running tray icon.
+pub struct Tray {
+    bus: Arc<Bus>,
+    shown: Arc<Mutex<Shown>>,
+}
+
+impl Tray {
+    /// Puts the icon in the tray showing `current`, offering the
+    /// "layout:" entries among `favorites` in its menu.
+    pub fn start(favorites: &[String], current: &str, actions: mpsc::Sender<Action>) -> Result<Tray> {
+        let (bus, incoming) = Bus::session()?;
+        let bus = Arc::new(bus);
+        let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+        bus.call_bus("RequestName", vec![string(&name), Value::U32(0)])?;
+        // A tray that starts later announces its watcher by taking the name
+        let rule = format!("type='signal',sender='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'", WATCHER);
+        bus.call_bus("AddMatch", vec![string(&rule)])?;
+        register(&bus, &name)?;
+        let favorites = favorites.iter().filter_map(|entry| entry.strip_prefix("layout:"));
+        let favorites = favorites.map(str::to_string).collect();
+        let shown = Arc::new(Mutex::new(Shown {
+            registry: xkb::load_registry(),
+            favorites,
+            current: current.to_string(),
+            revision: 1,
+        }));
+        let tray = Tray { bus: bus.clone(), shown: shown.clone() };
+        thread::spawn(move || {
+            if let Err(e) = answer(&bus, incoming, &shown, &name, &actions) {
+                log::error(&format!("tray: {:#}", e));
+            }
+        });
+        Ok(tray)
+    }
+
+    /// Shows `layout` as the current one, if it isn't already.
+    pub fn update(&self, layout: &str) -> Result<()> {
+        {
+            let Ok(mut shown) = self.shown.lock() else {
+                return Ok(());
+            };
+            if shown.current == layout {
+                return Ok(());
+            }
+            shown.current = layout.to_string();
+            shown.revision += 1;
+        }
+        for member in ["NewIcon", "NewTitle", "NewToolTip"] {
+            self.bus.send(Message::signal(ITEM_PATH, ITEM_INTERFACE, member, Vec::new()))?;
+        }
+        let revision = self.shown.lock().map(|shown| shown.revision).unwrap_or_default();
+        let body = vec![Value::U32(revision), Value::I32(0)];
+        self.bus.send(Message::signal(MENU_PATH, MENU_INTERFACE, "LayoutUpdated", body))?;
+        Ok(())
+    }
+}
+
+/// Tells the tray's watcher about the icon. Without a tray running there's
+/// no one to tell yet, which isn't an error.
+fn register(bus: &Bus, name: &str) -> Result<()> {
+    let call = Message::method_call(WATCHER, "/StatusNotifierWatcher", WATCHER, "RegisterStatusNotifierItem", vec![
+        string(name),
+    ]);
+    bus.send(call).context("Failed to register the tray icon")?;
+    Ok(())
+}
+
+/// Answers the tray's questions about the icon and menu until the bus goes
+/// away.
+fn answer(
+    bus: &Bus,
+    mut incoming: Incoming,
+    shown: &Mutex<Shown>,
+    name: &str,
+    actions: &mpsc::Sender<Action>,
+) -> Result<()> {
+    loop {
+        let message = incoming.next()?;
+        if message.kind == dbus::SIGNAL {
+            // The watcher (re)appeared, so it doesn't know about us
+            if message.member() == "NameOwnerChanged"
+                && message.body.get(2).and_then(Value::as_str).is_some_and(|owner| !owner.is_empty())
+            {
+                register(bus, name)?;
+            }
+            continue;
+        }
+        if message.kind != dbus::METHOD_CALL {
+            continue;
+        }
+        let (reply, action) = {
+            let Ok(shown) = shown.lock() else {
+                return Ok(());
+            };
+            respond(&shown, &message)
+        };
+        bus.send(reply)?;
+        if let Some(action) = action
+            && actions.send(action).is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn respond(shown: &Shown, message: &Message) -> (Message, Option<Action>) {
+    let argument = |index: usize| message.body.get(index);
+    let text = |index: usize| argument(index).and_then(Value::as_str).unwrap_or_default();
+    let path = message.path.as_deref().unwrap_or_default();
+    let unknown = || {
+        message.error("org.freedesktop.DBus.Error.UnknownMethod", &format!("No method {}", message.member()))
+    };
+    let reply = match (path, message.interface(), message.member()) {
+        (_, "org.freedesktop.DBus.Peer", "Ping") => message.reply(Vec::new()),
+        (_, "org.freedesktop.DBus.Properties", "Get") => {
+            let value = match text(0) {
+                ITEM_INTERFACE => shown.item_property(text(1)),
+                MENU_INTERFACE => shown.menu_property(text(1)),
+                _ => None,
+            };
+            match value {
+                Some(value) => message.reply(vec![variant(value)]),
+                None => {
+                    message.error("org.freedesktop.DBus.Error.UnknownProperty", &format!("No property {}", text(1)))
+                }
+            }
+        }
+        (_, "org.freedesktop.DBus.Properties", "GetAll") => message.reply(vec![dict(shown.properties(text(0)))]),
+        (ITEM_PATH, ITEM_INTERFACE, "Activate") => return (message.reply(Vec::new()), Some(Action::OpenTui)),
+        (ITEM_PATH, ITEM_INTERFACE, "Scroll") => {
+            let back = argument(0).and_then(Value::as_i32).unwrap_or_default() < 0;
+            return (message.reply(Vec::new()), shown.cycle(back).map(Action::SwitchTo));
+        }
+        // Left to the tray: the menu is shown from the Menu property
+        (ITEM_PATH, ITEM_INTERFACE, "ContextMenu" | "SecondaryActivate") => message.reply(Vec::new()),
+        (MENU_PATH, MENU_INTERFACE, "GetLayout") => message.reply(shown.layout_reply()),
+        (MENU_PATH, MENU_INTERFACE, "GetGroupProperties") => {
+            let wanted = argument(0).map(Value::elements).unwrap_or_default();
+            let wanted: Vec<i32> = wanted.iter().flat_map(Value::as_i32).collect();
+            let items = shown
+                .menu_items()
+                .into_iter()
+                .filter(|(id, _)| wanted.is_empty() || wanted.contains(id))
+                .map(|(id, properties)| Value::Struct(vec![Value::I32(id), dict(properties)]))
+                .collect();
+            message.reply(vec![Value::Array("(ia{sv})".to_string(), items)])
+        }
+        (MENU_PATH, MENU_INTERFACE, "Event") => {
+            let clicked = argument(0).and_then(Value::as_i32).filter(|_| text(1) == "clicked");
+            let action = clicked.and_then(|id| shown.action(id));
+            return (message.reply(Vec::new()), action);
+        }
+        (MENU_PATH, MENU_INTERFACE, "EventGroup") => {
+            // Only the first click counts, there being one action at a time
+            let action = argument(0).map(Value::elements).unwrap_or_default().iter().find_map(|event| match event {
+                Value::Struct(fields) if fields.get(1).and_then(Value::as_str) == Some("clicked") => {
+                    fields.first().and_then(Value::as_i32).and_then(|id| shown.action(id))
+                }
+                _ => None,
+            });
+            return (message.reply(vec![Value::Array("i".to_string(), Vec::new())]), action);
+        }
+        (MENU_PATH, MENU_INTERFACE, "AboutToShow") => message.reply(vec![Value::Bool(false)]),
+        (MENU_PATH, MENU_INTERFACE, "AboutToShowGroup") => {
+            let empty = || Value::Array("i".to_string(), Vec::new());
+            message.reply(vec![empty(), empty()])
+        }
+        _ => unknown(),
+    };
+    (reply, None)
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+pub struct XkbVariant {
+    pub name: String,
+    pub description: String,
+}
+
+pub struct XkbLayout {
+    pub code: String,
+    pub description: String,
+    pub variants: Vec<XkbVariant>,
+}
+
+#[derive(Default)]
+pub struct XkbRegistry {
+    pub layouts: Vec<XkbLayout>,
+    /// Layouts from symbols files in the user's own XKB directory.
+    pub user_layouts: Vec<XkbLayout>,
+    /// Option names with their descriptions, e.g. ("ctrl:nocaps", "Caps Lock as Ctrl").
+    pub options: Vec<(String, String)>,
+}
+
+impl XkbRegistry {
+    pub fn layout(&self, code: &str) -> Option<&XkbLayout> {
+        self.user_layouts
+            .iter()
+            .chain(&self.layouts)
+            .find(|layout| layout.code == code)
+    }
+
+    /// The description of a layout code, or of its variant when it names
+    /// one, e.g. "German (no dead keys)" for "de(nodeadkeys)".
+    pub fn describe(&self, code: &str) -> Option<&str> {
+        let (layout, variant) = split_layout_variant(code);
+        self.layout(layout).map(|entry| {
+            variant
+                .and_then(|variant| entry.variants.iter().find(|known| known.name == variant))
+                .map_or(entry.description.as_str(), |variant| variant.description.as_str())
+        })
+    }
+
+    pub fn is_user_layout(&self, code: &str) -> bool {
+        self.user_layouts.iter().any(|layout| layout.code == code)
+    }
+
+    pub fn option_description(&self, option: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(name, _)| name == option)
+            .map(|(_, description)| description.as_str())
+    }
+}
+
+pub struct OptionToggle {
+    pub option: &'static str,
+    pub label: &'static str,
+    /// Toggles sharing a group remap the same key, so enabling one turns
+    /// the others off.
+    pub group: Option<&'static str>,
+}
+
+pub const COMMON_OPTIONS: &[OptionToggle] = &[
+    OptionToggle { option: "ctrl:nocaps", label: "Caps Lock as Ctrl", group: Some("caps") },
+    OptionToggle { option: "caps:escape", label: "Caps Lock as Escape", group: Some("caps") },
+    OptionToggle { option: "caps:swapescape", label: "Swap Caps Lock and Escape", group: Some("caps") },
+    OptionToggle { option: "ctrl:swapcaps", label: "Swap Ctrl and Caps Lock", group: Some("caps") },
+    OptionToggle { option: "altwin:swap_alt_win", label: "Swap Alt and Win", group: None },
+    OptionToggle { option: "lv3:ralt_switch", label: "Right Alt chooses 3rd level", group: None },
+    OptionToggle { option: "grp:alt_shift_toggle", label: "Alt+Shift switches layout", group: Some("grp") },
+    OptionToggle { option: "grp:win_space_toggle", label: "Win+Space switches layout", group: Some("grp") },
+    OptionToggle { option: "grp_led:caps", label: "Caps Lock LED shows alternative layout", group: Some("grp_led") },
+    OptionToggle { option: "grp_led:scroll", label: "Scroll Lock LED shows alternative layout", group: Some("grp_led") },
+    OptionToggle { option: "terminate:ctrl_alt_bksp", label: "Ctrl+Alt+Backspace terminates X", group: None },
+];
+
+pub fn has_option(options: &str, option: &str) -> bool {
+    options.split(',').any(|part| part.trim() == option)
+}
+
+/// Applies a toggle to an options string: switches `option` on or off and,
+/// when turning it on, drops any toggle in the same group.
+pub fn toggle_option(options: &str, option: &str) -> String {
+    let enable = !has_option(options, option);
+    let mut new_options = options.to_string();
+    if enable
+        && let Some(group) = COMMON_OPTIONS.iter().find(|t| t.option == option).and_then(|t| t.group)
+    {
+        for other in COMMON_OPTIONS.iter().filter(|t| t.group == Some(group)) {
+            new_options = merge_option(&new_options, other.option, false);
+        }
+    }
+    merge_option(&new_options, option, enable)
+}
+
+fn rules_dir() -> PathBuf {
+    // Respect the same override libxkbcommon uses
+    let root = std::env::var("XKB_CONFIG_ROOT").unwrap_or_else(|_| "/usr/share/X11/xkb".to_string());
+    PathBuf::from(root).join("rules")
+}
+
+pub fn load_registry() -> XkbRegistry {
+    let dir = rules_dir();
+    let contents = fs::read_to_string(dir.join("evdev.lst"))
+        .or_else(|_| fs::read_to_string(dir.join("base.lst")));
+    let mut registry = match contents {
+        Ok(contents) => parse_rules_list(&contents),
+        Err(_) => XkbRegistry::default(),
+    };
+    registry.user_layouts = load_user_layouts(&config::xdg_config_home().join("xkb").join("symbols"));
+    registry
+}
+
+/// Compiled keymaps in `dir`, as produced by `xkbcomp -xkb` or
+/// `xkbcli compile-keymap`.
+pub fn keymap_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "xkb"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Splits XKB's "layout(variant)" notation into its two halves.
+pub fn split_layout_variant(code: &str) -> (&str, Option<&str>) {
+    match code.split_once('(') {
+        Some((layout, variant)) => (layout, Some(variant.trim_end_matches(')'))),
+        None => (code, None),
+    }
+}
+
+/// Reads every symbols file in `dir`, which libxkbcommon searches before
+/// the system XKB data, so each file name is usable as a layout code.
+fn load_user_layouts(dir: &Path) -> Vec<XkbLayout> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut layouts: Vec<XkbLayout> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let code = entry.file_name().to_string_lossy().into_owned();
+            // Skip editor backups and other dotfiles
+            if code.starts_with('.') || code.ends_with('~') {
+                return None;
+            }
+            let contents = fs::read_to_string(entry.path()).ok()?;
+            parse_symbols_file(&code, &contents)
+        })
+        .collect();
+    layouts.sort_by(|a, b| a.code.cmp(&b.code));
+    layouts
+}
+
+/// Collects the `xkb_symbols` blocks of a symbols file. The block marked
+/// `default` (or else the first one) is the layout itself; the rest are
+/// its variants.
+fn parse_symbols_file(code: &str, contents: &str) -> Option<XkbLayout> {
+    // (block name, is default, description from name[Group1])
+    let mut blocks: Vec<(String, bool, Option<String>)> = Vec::new();
+    for line in contents.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        if let Some(index) = line.find("xkb_symbols") {
+            let Some(name) = line[index..].split('"').nth(1) else {
+                continue;
+            };
+            let is_default = line[..index].split_whitespace().any(|flag| flag == "default");
+            blocks.push((name.to_string(), is_default, None));
+        } else if line.starts_with("name[")
+            && let Some((_, _, description)) = blocks.last_mut()
+            && description.is_none()
+        {
+            *description = line.split('"').nth(1).map(str::to_string);
+        }
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+    let default_index = blocks.iter().position(|(_, is_default, _)| *is_default).unwrap_or(0);
+    let (_, _, default_description) = blocks.remove(default_index);
+    Some(XkbLayout {
+        code: code.to_string(),
+        description: default_description.unwrap_or_else(|| code.to_string()),
+        variants: blocks
+            .into_iter()
+            .map(|(name, _, description)| XkbVariant {
+                description: description.unwrap_or_else(|| name.clone()),
+                name,
+            })
+            .collect(),
+    })
+}
+
+fn parse_rules_list(contents: &str) -> XkbRegistry {
+    let mut registry = XkbRegistry::default();
+    let mut section = "";
+    for line in contents.lines() {
+        if let Some(name) = line.strip_prefix('!') {
+            section = name.trim();
+            continue;
+        }
+        let line = line.trim();
+        let Some((name, description)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let description = description.trim();
+        match section {
+            "layout" => registry.layouts.push(XkbLayout {
+                code: name.to_string(),
+                description: description.to_string(),
+                variants: Vec::new(),
+            }),
+            // Skip group headings such as "grp  Switching to another layout"
+            "option" if name.contains(':') => {
+                registry.options.push((name.to_string(), description.to_string()));
+            }
+            "variant" => {
+                // Variant lines look like "nodeadkeys  de: German (no dead keys)"
+                if let Some((layout_code, variant_description)) = description.split_once(':')
+                    && let Some(layout) = registry.layouts.iter_mut().find(|l| l.code == layout_code)
+                {
+                    layout.variants.push(XkbVariant {
+                        name: name.to_string(),
+                        description: variant_description.trim().to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    registry
+}
+
+/// Adds or removes a single option in a comma-separated XKB options string,
+/// leaving every other option untouched and in order.
+pub fn merge_option(options: &str, option: &str, enable: bool) -> String {
+    let mut parts: Vec<&str> = options
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty() && *part != option)
+        .collect();
+    if enable {
+        parts.push(option);
+    }
+    parts.join(",")
+}
+
+pub fn remove_options_with_prefix(options: &str, prefix: &str) -> String {
+    options
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty() && !part.starts_with(prefix))
+        .collect::<Vec<_>>()
+        .join(",")
+}